@@ -1,8 +1,14 @@
+use backend::{ GetterHandle, ServiceHandle, SetterHandle };
+
 use foxbox_taxonomy::api::{ Error, ResultMap };
+use foxbox_taxonomy::selector::SetterSelector;
 use foxbox_taxonomy::services::*;
 use foxbox_taxonomy::util::*;
 use foxbox_taxonomy::values::*;
 
+use std::collections::HashMap;
+use std::sync::Arc;
+
 /// A witness that we are currently watching for a value.
 /// Watching stops when the guard is dropped.
 pub trait AdapterWatchGuard {
@@ -39,7 +45,12 @@ pub trait AdapterManagerHandle {
     /// - `service` has channels;
     /// - a service with id `service.id` is already installed on the system;
     /// - there is no adapter with id `service.adapter`.
-    fn add_service(&self, service: Service) -> Result<(), Error>;
+    ///
+    /// On success, returns a handle to the newly-registered service, so a caller that wants to
+    /// look it back up later (e.g. to remove it with `remove_service_checked`) doesn't have to
+    /// make a separate `service_handle` call that could race against a remove/re-add of the same
+    /// `service.id` in between.
+    fn add_service(&self, service: Service) -> Result<ServiceHandle, Error>;
 
     /// Remove a service previously registered on the system. Typically, called by
     /// an adapter when a service (e.g. a device) is disconnected.
@@ -65,7 +76,9 @@ pub trait AdapterManagerHandle {
     /// Returns an error if the adapter is not registered, the parent service is not
     /// registered, or a channel with the same identifier is already registered.
     /// In either cases, this method reverts all its changes.
-    fn add_getter(&self, setter: Channel<Getter>) -> Result<(), Error>;
+    ///
+    /// On success, returns a handle to the newly-registered getter. See `add_service`.
+    fn add_getter(&self, setter: Channel<Getter>) -> Result<GetterHandle, Error>;
 
     /// Remove a setter previously registered on the system. Typically, called by
     /// an adapter when a service is reconfigured to remove one of its getters.
@@ -90,7 +103,9 @@ pub trait AdapterManagerHandle {
     /// Returns an error if the adapter is not registered, the parent service is not
     /// registered, or a channel with the same identifier is already registered.
     /// In either cases, this method reverts all its changes.
-    fn add_setter(&self, setter: Channel<Setter>) -> Result<(), Error>;
+    ///
+    /// On success, returns a handle to the newly-registered setter. See `add_service`.
+    fn add_setter(&self, setter: Channel<Setter>) -> Result<SetterHandle, Error>;
 
     /// Remove a setter previously registered on the system. Typically, called by
     /// an adapter when a service is reconfigured to remove one of its setters.
@@ -103,6 +118,182 @@ pub trait AdapterManagerHandle {
     fn remove_setter(&self, id: &Id<Setter>) -> Result<(), Error>;
 }
 
+/// A condition checked against a setter's current value before `Adapter::send_values_checked`
+/// applies a new one.
+#[derive(Clone)]
+pub enum Precondition {
+    /// The setter's current value, if any, must equal this value.
+    IfEqual(Value),
+    /// The setter's current value, if any, must fall within this range.
+    IfRangeMatches(Range),
+    /// The setter must not currently have a known value.
+    IfUnset,
+}
+
+/// The error returned by `Adapter::send_values_checked`, extending `foxbox_taxonomy::api::Error`
+/// with a precondition-check outcome. `Error` is defined in an external crate and cannot gain a
+/// `PreconditionFailed` variant directly, so it is wrapped here instead.
+pub enum ConditionalWriteError {
+    /// An error unrelated to the precondition, exactly as `send_values` could have returned.
+    Inner(Error),
+    /// The precondition did not hold. `current` is the value it was checked against, if known.
+    PreconditionFailed { current: Option<Value> },
+}
+
+/// The error returned by `AdapterManagerState::fetch_values_with_timeout`/
+/// `send_values_with_timeout` for any channel whose owning adapter did not reply before the
+/// deadline. `Error` is defined in an external crate and cannot gain a `Timeout` variant
+/// directly, so it is wrapped here instead.
+pub enum TimeoutError {
+    /// An error unrelated to the deadline, exactly as the non-timeout-bounded call could have
+    /// returned.
+    Inner(Error),
+
+    /// The adapter did not reply before the deadline elapsed. The job already submitted to the
+    /// worker pool is not cancelled - there is no way to interrupt an adapter mid-call - but it
+    /// is abandoned: its one-shot reply channel is scoped to this call alone, so a late reply
+    /// simply has nobody left to receive it and is dropped rather than misdelivered to a later
+    /// call.
+    Timeout,
+}
+
+/// The error returned by `AdapterManagerState::send_values_with_handle` for any setter whose
+/// operation was cancelled, via its `JobHandle`, before the adapter replied. `Error` is defined
+/// in an external crate and cannot gain a `Cancelled` variant directly, so it is wrapped here
+/// instead.
+pub enum CancellationError {
+    /// An error unrelated to cancellation, exactly as `send_values` could have returned.
+    Inner(Error),
+
+    /// This setter's operation was cancelled before its adapter replied. As with
+    /// `TimeoutError::Timeout`, the job already submitted to the worker pool is not interrupted
+    /// mid-call - there is no way to do that - it is abandoned: a late reply simply has nobody
+    /// left to receive it.
+    Cancelled,
+}
+
+/// Identifies a single `AdapterManagerState::send_values_verified` call, shared by every
+/// `SetterVerification` event it produces, so a caller watching several concurrent calls to the
+/// same setter can tell their events apart.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct RequestId(pub u64);
+
+/// A staged progress event for one setter of a `send_values_verified` call. Every accepted
+/// setter eventually gets exactly one `Completed`, even if its adapter is removed before it
+/// replies - see `send_values_verified`.
+pub enum SetterVerification {
+    /// The write passed validation (the selector resolved to a known setter) and was accepted.
+    Accepted { request: RequestId, setter: Id<Setter> },
+    /// The write was handed to its adapter.
+    Started { request: RequestId, setter: Id<Setter> },
+    /// The write reached a terminal outcome, successful or not.
+    ///
+    /// A failure is wrapped in `Arc` rather than held bare: `foxbox_taxonomy::api::Error` is
+    /// defined in an external crate with no guaranteed `Clone`, but `send_values_verified` still
+    /// needs to both emit this event and return the same failure to its own caller.
+    Completed { request: RequestId, setter: Id<Setter>, result: Result<(), Arc<Error>> },
+}
+
+/// A JSON-pointer-like path into a structured `Value`, e.g. `"/foo/bar/0"`. See `PatchOp`.
+pub type ValuePath = String;
+
+/// A single operation in an `UpdateKind::Patch`, applied in order against a setter's current
+/// value.
+#[derive(Clone)]
+pub enum PatchOp {
+    Add { path: ValuePath, value: Value },
+    Remove { path: ValuePath },
+    Replace { path: ValuePath, value: Value },
+}
+
+/// How `AdapterManagerState::send_values_updated` should combine a new value with a setter's
+/// current one.
+#[derive(Clone)]
+pub enum UpdateKind {
+    /// Overwrite the setter's value outright, exactly as `send_values` does today.
+    Replace(Value),
+    /// Recursively overlay the fields of this value onto the setter's current structured value;
+    /// a `null` field deletes the corresponding key. See `UpdateError::UnsupportedUpdate`.
+    Merge(Value),
+    /// Apply these operations, in order, against a JSON-pointer-like path into the setter's
+    /// current structured value. See `UpdateError::UnsupportedUpdate`.
+    Patch(Vec<PatchOp>),
+}
+
+/// The error returned by `AdapterManagerState::send_values_updated`, extending
+/// `foxbox_taxonomy::api::Error` with a rejection for an update this crate cannot materialize.
+pub enum UpdateError {
+    /// An error unrelated to merge/patch support, exactly as `send_values` could have returned.
+    Inner(Error),
+    /// `foxbox_taxonomy::values::Value` has no generic object/array representation for this
+    /// crate to merge or patch against: it is a closed set of concrete, typed variants, not a
+    /// composite record. `UpdateKind::Merge`/`UpdateKind::Patch` cannot be materialized here
+    /// until the taxonomy exposes such a representation, so they are rejected outright rather
+    /// than silently falling back to `Replace` semantics.
+    UnsupportedUpdate,
+}
+
+/// Why `AdapterManagerState::send_values_atomic` rejected an entire batch without sending
+/// anything to any adapter. Unlike `send_values`'s per-channel best-effort semantics, a single
+/// bad entry anywhere in the batch means none of it is applied, so every offending entry is
+/// reported at once rather than stopping at the first one found.
+#[derive(Debug)]
+pub struct AtomicSendRejection {
+    /// Every setter a selector resolved to whose `ChannelKind` rejects the `Value` it was
+    /// paired with, keyed by that setter.
+    pub type_errors: HashMap<Id<Setter>, TypeError>,
+    /// Every selector group in the batch that matched no setter at all.
+    pub unmatched: Vec<Vec<SetterSelector>>,
+}
+
+/// A marker type for `Id<LogicalChannelId>`, identifying a `LogicalChannel`. `foxbox_taxonomy`
+/// has no notion of a setter backed by more than one physical channel, so this exists purely to
+/// give `Id<T>` a distinct type to tag, exactly as `AdapterId` does for `Id<AdapterId>`.
+pub struct LogicalChannelId;
+
+/// A write channel backed by several physical setters, registered with
+/// `AdapterManagerState::add_logical_channel` so replicated/redundant devices can be driven
+/// through a single consistent control surface. See `AdapterManagerState::send_to_logical_channel`.
+pub struct LogicalChannel {
+    pub id: Id<LogicalChannelId>,
+    /// The physical setters this logical channel fans a value out to, possibly spanning several
+    /// adapters.
+    pub backing: Vec<Id<Setter>>,
+    /// The number of `backing` setters that must acknowledge a write for
+    /// `send_to_logical_channel` to report success. Must be between 1 and `backing.len()`.
+    pub quorum: usize,
+}
+
+/// The error returned by `AdapterManagerState::add_logical_channel`.
+pub enum LogicalChannelError {
+    /// A logical channel is already registered under this id.
+    DuplicateChannel(Id<LogicalChannelId>),
+    /// `backing` named a setter that is not currently registered.
+    NoSuchSetter(Id<Setter>),
+    /// `quorum` must be at least 1 and at most the number of `backing` setters.
+    InvalidQuorum { quorum: usize, backing: usize },
+}
+
+/// The error returned by `AdapterManagerState::send_to_logical_channel`.
+pub enum QuorumError {
+    /// No `LogicalChannel` is registered under this id.
+    NoSuchChannel(Id<LogicalChannelId>),
+
+    /// The backing setters do not agree on a `Type`, so there is no single value that could be
+    /// valid for all of them - e.g. the `OpenClosed` vs `OnOff` mismatch `ChannelKind::get_type`
+    /// can produce elsewhere in this crate. Surfaced instead of silently sending a value that
+    /// would be ill-typed for some of the backing setters.
+    Diverged(TypeError),
+
+    /// Fewer than `quorum` backing setters acknowledged the write. Lists every backing setter's
+    /// individual result, successes included, for diagnosis.
+    QuorumFailed {
+        quorum: usize,
+        acked: usize,
+        results: Vec<(Id<Setter>, Result<(), Error>)>,
+    },
+}
+
 pub enum WatchEvent {
     /// Fired when we enter the range specified when we started watching, or if no range was
     /// specified, fired whenever a new value is available.
@@ -122,7 +313,11 @@ pub enum WatchEvent {
 ///
 /// Note that all methods are blocking. However, the underlying implementatino of adapters is
 /// expected to either return quickly or be able to handle several requests concurrently.
-pub trait Adapter: Send {
+///
+/// `Sync` (in addition to `Send`) is required so that `AdapterManagerState` can hand a shared
+/// `Arc<Adapter>` to its worker pool and call into an adapter from whichever worker thread picks
+/// up its job, without cloning the adapter itself or moving it out of the registry.
+pub trait Adapter: Send + Sync {
     /// An id unique to this adapter. This id must persist between
     /// reboots/reconnections.
     fn id(&self) -> Id<AdapterId>;
@@ -140,6 +335,23 @@ pub trait Adapter: Send {
     /// Request that values be sent to a channel.
     fn send_values(&self, values: Vec<(Id<Setter>, Value)>) -> ResultMap<Id<Setter>, (), Error>;
 
+    /// Like `send_values`, but honor each entry's precondition (see `Precondition`). An adapter
+    /// that can check a setter's current value and apply the write in one atomic step should
+    /// override this and return `ConditionalWriteError::PreconditionFailed` for any entry whose
+    /// precondition didn't hold. The default implementation has no way to read a setter's
+    /// current value at all, so it simply forwards every entry straight to `send_values` without
+    /// checking its precondition: `AdapterManagerState::send_values_checked` resolves
+    /// preconditions against its own record of the last value written before ever reaching an
+    /// adapter that hasn't overridden this method.
+    fn send_values_checked(&self, values: Vec<(Id<Setter>, Value, Option<Precondition>)>)
+        -> ResultMap<Id<Setter>, (), ConditionalWriteError>
+    {
+        let plain = values.into_iter().map(|(id, value, _)| (id, value)).collect();
+        self.send_values(plain).into_iter()
+            .map(|(id, result)| (id, result.map_err(ConditionalWriteError::Inner)))
+            .collect()
+    }
+
     /// Watch a bunch of getters as they change.
     fn register_watch(&self, Vec<(Id<Getter>, Option<Range>)>,
         cb: Box<Fn(WatchEvent) + Send>) ->
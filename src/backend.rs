@@ -1,6 +1,11 @@
 //! An API for plugging in adapters.
 
-use adapter::{ Adapter, AdapterWatchGuard, WatchEvent as AdapterWatchEvent };
+use adapter::{ Adapter, AdapterWatchGuard, AtomicSendRejection, CancellationError, ConditionalWriteError,
+    LogicalChannel, LogicalChannelError, LogicalChannelId, Precondition, QuorumError, RequestId,
+    SetterVerification, TimeoutError, UpdateError, UpdateKind, WatchEvent as AdapterWatchEvent };
+use effects::{ Effect, EffectBus, EffectReceiver, RejectionKind };
+use metrics::Metrics;
+use provider::{ AdapterFactory, AdapterProviderRegistry };
 use transact::InsertInMap;
 
 use foxbox_taxonomy::api::{ API, Error, InternalError, WatchEvent, ResultMap };
@@ -9,21 +14,27 @@ use foxbox_taxonomy::services::*;
 use foxbox_taxonomy::util::*;
 use foxbox_taxonomy::values::*;
 
+use transformable_channels::mpsc::ExtSender;
+
 use std::cell::RefCell;
-use std::collections::{ HashMap, HashSet };
+use std::collections::{ HashMap, HashSet, VecDeque };
 use std::collections::hash_map::Entry;
 use std::hash::{ Hash, Hasher };
+use std::mem;
 use std::ops::Deref;
 use std::rc::Rc;
 use std::sync::{ Arc, Mutex };
-use std::sync::atomic::{ AtomicBool, Ordering };
-use std::sync::mpsc::{ channel, Sender };
+use std::sync::atomic::{ AtomicBool, AtomicUsize, Ordering };
+use std::sync::mpsc::{ channel, Receiver, RecvTimeoutError, Sender };
 use std::thread;
+use std::time::{ Duration, SystemTime };
 
 /// Data and metadata on an adapter.
 struct AdapterData {
-    /// The implementation of the adapter.
-    adapter: Box<Adapter>,
+    /// The implementation of the adapter. Kept behind an `Arc` (rather than a plain `Box`, as
+    /// `add_adapter` receives it) so that `fetch_values`/`send_values` can hand a cheap clone to
+    /// the worker pool and call into it from another thread without removing it from this map.
+    adapter: Arc<Adapter>,
 
     /// The services for this adapter.
     services: HashMap<Id<ServiceId>, Rc<RefCell<Service>>>,
@@ -32,15 +43,65 @@ struct AdapterData {
 impl AdapterData {
     fn new(adapter: Box<Adapter>) -> Self {
         AdapterData {
-            adapter: adapter,
+            adapter: Arc::from(adapter),
             services: HashMap::new(),
         }
     }
 }
 impl Deref for AdapterData {
-    type Target = Box<Adapter>;
+    type Target = Adapter;
     fn deref(&self) -> &Self::Target {
-        &self.adapter
+        &*self.adapter
+    }
+}
+
+/// A job submitted to a `WorkerPool`: runs once, on whichever worker thread picks it up.
+type Job = Box<FnOnce() + Send>;
+
+/// Default number of worker threads backing an `AdapterManagerState` created with `new()`. See
+/// `AdapterManagerState::new_with_pool_size`.
+const DEFAULT_POOL_SIZE: usize = 4;
+
+/// A small, bounded pool of long-lived worker threads, used to dispatch each adapter's
+/// `fetch_values`/`send_values` call concurrently instead of one adapter at a time, so a single
+/// slow adapter (e.g. a network-backed device that blocks for seconds) no longer stalls the
+/// others in the same batch. Jobs in excess of the pool size simply wait in the shared queue
+/// until a worker frees up, rather than spawning unboundedly.
+struct WorkerPool {
+    jobs: Sender<Job>,
+}
+
+impl WorkerPool {
+    fn new(size: usize) -> Self {
+        let (tx, rx) = channel::<Job>();
+        let rx = Arc::new(Mutex::new(rx));
+        for _ in 0 .. size {
+            let rx = rx.clone();
+            thread::spawn(move || {
+                loop {
+                    let job = {
+                        let rx = match rx.lock() {
+                            Ok(rx) => rx,
+                            Err(poisoned) => poisoned.into_inner(),
+                        };
+                        rx.recv()
+                    };
+                    match job {
+                        Ok(job) => job(),
+                        Err(_) => break, // The pool itself was dropped: nothing left to do.
+                    }
+                }
+            });
+        }
+        WorkerPool { jobs: tx }
+    }
+
+    /// Queue `job` to run on the next worker thread that becomes free. Never blocks the caller.
+    fn submit(&self, job: Job) {
+        // Can only fail if every worker thread has panicked and exited, in which case there is
+        // no worker left to report the dropped job to; the caller's own result channel will
+        // simply never receive a reply for it.
+        let _ = self.jobs.send(job);
     }
 }
 
@@ -49,6 +110,175 @@ trait Tagged {
     fn remove_tags(&mut self, tags: &[Id<TagId>]);
 }
 
+/// A selector that exposes the id/tag constraints it was built with, so `with_channels` can
+/// narrow its candidate set against `getter_by_tag`/`setter_by_tag` instead of scanning every
+/// channel. Both lists are empty for a selector that imposes no such constraint (e.g. one built
+/// from only `.with_parent(..)` or `.with_kind(..)`, or the unconstrained `GetterSelector::new()`).
+trait IndexedSelector<T> {
+    /// The exact ids this selector is restricted to, if any. When non-empty, this is the most
+    /// selective constraint available and is used in preference to `required_tags`.
+    fn required_ids(&self) -> &[Id<T>];
+    /// The tags every matching channel must carry, if any.
+    fn required_tags(&self) -> &[Id<TagId>];
+}
+
+impl IndexedSelector<Getter> for GetterSelector {
+    fn required_ids(&self) -> &[Id<Getter>] { &self.id }
+    fn required_tags(&self) -> &[Id<TagId>] { &self.tags }
+}
+impl IndexedSelector<Setter> for SetterSelector {
+    fn required_ids(&self) -> &[Id<Setter>] { &self.id }
+    fn required_tags(&self) -> &[Id<TagId>] { &self.tags }
+}
+
+/// A boolean tag predicate layered on top of a selector's own matching, so a single selector can
+/// express more than `.with_tags(..)`'s "all of these". See `Filtered`.
+#[derive(Clone, Default)]
+pub struct TagPredicate {
+    without: Vec<Id<TagId>>,
+    any: Vec<Id<TagId>>,
+}
+impl TagPredicate {
+    pub fn new() -> Self {
+        TagPredicate { without: Vec::new(), any: Vec::new() }
+    }
+
+    /// Reject a channel/service carrying any of `tags`, however many of `with_tags`'s required
+    /// tags it also carries.
+    pub fn without_tags(mut self, tags: Vec<Id<TagId>>) -> Self {
+        self.without = tags;
+        self
+    }
+
+    /// Require at least one of `tags`, in addition to (not instead of) whatever `with_tags`
+    /// already requires.
+    pub fn with_any_tags(mut self, tags: Vec<Id<TagId>>) -> Self {
+        self.any = tags;
+        self
+    }
+
+    fn matches(&self, tags: &HashSet<Id<TagId>>) -> bool {
+        if self.without.iter().any(|excluded| tags.contains(excluded)) {
+            return false;
+        }
+        self.any.is_empty() || self.any.iter().any(|tag| tags.contains(tag))
+    }
+}
+
+/// A `ServiceSelector`/`GetterSelector`/`SetterSelector` paired with a `TagPredicate`.
+/// `foxbox_taxonomy`'s selectors are a closed, external type with no room for this crate to add
+/// tag negation/disjunction directly, so this wraps one instead: the wrapped selector's own
+/// id/`with_tags` matching still applies in full, and `predicate` narrows the result further.
+/// Lets a caller express e.g. "all lights tagged `bedroom` but not `disabled`" as a single value:
+/// `Filtered::new(GetterSelector::new().with_tags(vec![bedroom])).without_tags(vec![disabled])`.
+/// Passed to `get_getter_channels_matching`/`get_setter_channels_matching`/
+/// `get_services_matching` and their tag add/remove counterparts in place of a bare selector.
+#[derive(Clone)]
+pub struct Filtered<S> {
+    pub selector: S,
+    pub predicate: TagPredicate,
+}
+impl<S> Filtered<S> {
+    pub fn new(selector: S) -> Self {
+        Filtered { selector: selector, predicate: TagPredicate::new() }
+    }
+    pub fn without_tags(mut self, tags: Vec<Id<TagId>>) -> Self {
+        self.predicate = self.predicate.without_tags(tags);
+        self
+    }
+    pub fn with_any_tags(mut self, tags: Vec<Id<TagId>>) -> Self {
+        self.predicate = self.predicate.with_any_tags(tags);
+        self
+    }
+}
+impl<S, T> IndexedSelector<T> for Filtered<S> where S: IndexedSelector<T> {
+    fn required_ids(&self) -> &[Id<T>] { self.selector.required_ids() }
+    fn required_tags(&self) -> &[Id<TagId>] { self.selector.required_tags() }
+}
+impl SelectedBy<Filtered<GetterSelector>> for GetterData {
+    fn matches(&self, selector: &Filtered<GetterSelector>) -> bool {
+        self.getter.matches(&selector.selector) && selector.predicate.matches(&self.getter.tags)
+    }
+}
+impl SelectedBy<Filtered<SetterSelector>> for SetterData {
+    fn matches(&self, selector: &Filtered<SetterSelector>) -> bool {
+        self.setter.matches(&selector.selector) && selector.predicate.matches(&self.setter.tags)
+    }
+}
+
+/// Compile `selectors` (OR'd together, as `with_channels` treats them) into the union of their
+/// candidate ids, consulting `by_tag` for any selector constrained by tag, or `None` if at least
+/// one selector imposes neither an id nor a tag constraint and so could match any channel.
+/// Callers still re-check `SelectedBy::matches` against the result: this only narrows *which*
+/// channels are worth checking, it never replaces the check itself, so a selector combining e.g.
+/// an id with a `.with_kind(..)` constraint is still answered correctly.
+fn candidate_ids<S, T>(selectors: &[S], by_tag: &HashMap<Id<TagId>, HashSet<Id<T>>>) -> Option<HashSet<Id<T>>>
+    where S: IndexedSelector<T>,
+          Id<T>: Eq + Hash + Clone,
+{
+    let mut union = HashSet::new();
+    for selector in selectors {
+        let ids = selector.required_ids();
+        if !ids.is_empty() {
+            union.extend(ids.iter().cloned());
+            continue;
+        }
+        let tags = selector.required_tags();
+        if !tags.is_empty() {
+            // Intersect starting from the smallest tag set first, so a selector combining a rare
+            // tag with a common one never pays for scanning the common one in full.
+            let mut tags: Vec<&Id<TagId>> = tags.iter().collect();
+            tags.sort_by_key(|tag| by_tag.get(tag).map_or(0, |set| set.len()));
+            let mut tags = tags.into_iter();
+            let mut candidates = match tags.next() {
+                Some(tag) => by_tag.get(tag).cloned().unwrap_or_else(HashSet::new),
+                None => HashSet::new(),
+            };
+            for tag in tags {
+                if candidates.is_empty() {
+                    break;
+                }
+                candidates = match by_tag.get(tag) {
+                    Some(set) => candidates.intersection(set).cloned().collect(),
+                    None => HashSet::new(),
+                };
+            }
+            union.extend(candidates);
+            continue;
+        }
+        // Neither an id nor a tag constraint: this selector alone can match any channel, so there
+        // is no sublinear way to enumerate its candidates. Fall back to a full scan for the batch.
+        return None;
+    }
+    Some(union)
+}
+
+/// Add `id` to `by_tag`'s set for every tag in `tags`. Used to seed `getter_by_tag`/
+/// `setter_by_tag` when a channel is added already carrying tags, and by `aux_add_channel_tags`.
+fn index_tags<K>(by_tag: &mut HashMap<Id<TagId>, HashSet<Id<K>>>, id: &Id<K>, tags: &HashSet<Id<TagId>>)
+    where Id<K>: Eq + Hash + Clone
+{
+    for tag in tags {
+        by_tag.entry(tag.clone()).or_insert_with(HashSet::new).insert(id.clone());
+    }
+}
+
+/// Remove `id` from `by_tag`'s set for every tag in `tags`, dropping any tag whose set becomes
+/// empty so the index doesn't accumulate entries for tags nothing carries any more.
+fn deindex_tags<K>(by_tag: &mut HashMap<Id<TagId>, HashSet<Id<K>>>, id: &Id<K>, tags: &HashSet<Id<TagId>>)
+    where Id<K>: Eq + Hash + Clone
+{
+    for tag in tags {
+        let now_empty = match by_tag.get_mut(tag) {
+            Some(set) => { set.remove(id); set.is_empty() }
+            None => false,
+        };
+        if now_empty {
+            by_tag.remove(tag);
+        }
+    }
+}
+
 impl<T> Tagged for Channel<T> where T: IOMechanism {
     fn insert_tags(&mut self, tags: &[Id<TagId>]) {
         for tag in tags {
@@ -137,32 +367,89 @@ impl Deref for SetterData {
 
 struct WatcherData {
     watch: Vec<(Vec<GetterSelector>, Exactly<Range>)>,
-    on_event: Sender<WatchEvent>,
+    /// Every event ever delivered to this watcher - whether pushed live from an adapter's own
+    /// `register_watch` callback, reattached retroactively by `attach_matching_watchers`, or
+    /// injected out-of-band (an `InitializationError` raised at registration time,
+    /// `notify_polled_value`) - goes through this same sink rather than straight onto a raw
+    /// channel. The forwarding thread spawned in `register_channel_watch` calls `mark_delivered`
+    /// on this exact sink for every message it reads, so any path that placed a message on the
+    /// channel without going through `sink.send` first would leave its pending/in-flight
+    /// bookkeeping out of sync - in `GuardedSender`'s case, counting down from zero.
+    sink: DebouncedSink,
     key: usize,
-    guards: RefCell<Vec<Box<AdapterWatchGuard>>>,
+    /// Keyed by getter, so that a getter which disconnects at runtime can have just its own
+    /// adapter watch torn down without disturbing the others this watcher is following.
+    guards: RefCell<HashMap<Id<Getter>, Box<AdapterWatchGuard>>>,
     getters: RefCell<HashSet<Id<Getter>>>,
+    /// Whether the last value `notify_polled_value` delivered for a given getter matched this
+    /// watcher's range filter, so it can tell an actual `EnterRange`/`ExitRange` transition from
+    /// a poll that came back still on the same side of the filter. The adapter-push path needs
+    /// no equivalent: it only ever calls back on a transition in the first place (see
+    /// `AdapterWatchEvent::Enter`/`Exit`).
+    poll_matched: RefCell<HashMap<Id<Getter>, bool>>,
     is_dropped: Arc<AtomicBool>,
 }
 
 impl WatcherData {
-    fn new(key: usize, watch: Vec<(Vec<GetterSelector>, Exactly<Range>)>, is_dropped: &Arc<AtomicBool>, on_event: Sender<WatchEvent>) -> Self {
+    fn new(key: usize, watch: Vec<(Vec<GetterSelector>, Exactly<Range>)>, is_dropped: &Arc<AtomicBool>,
+        sink: DebouncedSink) -> Self
+    {
         WatcherData {
             key: key,
-            on_event: on_event,
+            sink: sink,
             watch: watch,
             is_dropped: is_dropped.clone(),
-            guards: RefCell::new(Vec::new()),
+            guards: RefCell::new(HashMap::new()),
             getters: RefCell::new(HashSet::new()),
+            poll_matched: RefCell::new(HashMap::new()),
         }
     }
 
-    fn push_guard(&self, guard: Box<AdapterWatchGuard>) {
-        self.guards.borrow_mut().push(guard);
+    fn push_guard(&self, id: Id<Getter>, guard: Box<AdapterWatchGuard>) {
+        self.guards.borrow_mut().insert(id, guard);
+    }
+
+    /// Drop the adapter watch guard for a single getter, if any, e.g. because that getter was
+    /// just removed from the system. Leaves the watcher's other getters untouched.
+    fn drop_guard(&self, id: &Id<Getter>) {
+        self.guards.borrow_mut().remove(id);
+    }
+
+    fn remove_getter(&self, id: &Id<Getter>) {
+        self.getters.borrow_mut().remove(id);
+        self.poll_matched.borrow_mut().remove(id);
+    }
+
+    /// Record whether `id`'s polled value matched this watcher's range filter, returning `true`
+    /// only if that differs from what was last recorded for it - an actual Enter/Exit
+    /// transition - so `notify_polled_value` can skip redelivering the same membership on every
+    /// tick a still-changing value happens to stay on.
+    fn poll_transitioned(&self, id: &Id<Getter>, matches: bool) -> bool {
+        let mut poll_matched = self.poll_matched.borrow_mut();
+        let changed = poll_matched.get(id) != Some(&matches);
+        poll_matched.insert(id.clone(), matches);
+        changed
     }
 
     fn push_getter(&self, id: &Id<Getter>) {
         self.getters.borrow_mut().insert(id.clone());
     }
+
+    fn status(&self) -> WatchStatus {
+        WatchStatus {
+            is_dropped: self.is_dropped.load(Ordering::Relaxed),
+            getters: self.getters.borrow().len(),
+            guards: self.guards.borrow().len(),
+        }
+    }
+
+    /// Deliver `event` to this watcher through its sink, exactly as a live adapter push would:
+    /// see the `sink` field for why every delivery path has to go through here rather than a raw
+    /// channel send. The sink itself is responsible for tearing the watcher down if its delivery
+    /// channel turns out to be gone.
+    fn notify(&self, event: WatchEvent) {
+        self.sink.send(event);
+    }
 }
 
 pub struct WatchMap {
@@ -178,12 +465,16 @@ impl WatchMap {
             watchers: HashMap::new()
         }
     }
-    fn create(&mut self, watch: Vec<(Vec<GetterSelector>, Exactly<Range>)>, is_dropped: &Arc<AtomicBool>, on_event: Sender<WatchEvent>) -> Arc<WatcherData> {
+    /// Allocate the key a soon-to-be-created `WatcherData` will use, ahead of actually
+    /// constructing it: `register_channel_watch` needs this key to build the `GuardedSender`
+    /// that the watcher's own sink wraps, before the watcher (which holds that sink) can exist.
+    fn reserve_key(&mut self) -> usize {
         let id = self.counter;
         self.counter += 1;
-        let watcher = Arc::new(WatcherData::new(id, watch, is_dropped, on_event));
-        self.watchers.insert(id, watcher.clone());
-        watcher
+        id
+    }
+    fn insert(&mut self, key: usize, watcher: Arc<WatcherData>) {
+        self.watchers.insert(key, watcher);
     }
     fn remove(&mut self, key: usize) -> Option<Arc<WatcherData>> {
         self.watchers.remove(&key)
@@ -196,6 +487,26 @@ impl Default for WatchMap {
     }
 }
 
+/// A point-in-time snapshot of a single registered watch's internal bookkeeping. Returned by
+/// `WatchGuard::status`/`AdapterManagerState::watch_status`, primarily so that tests and
+/// diagnostics can assert that `unregister_channel_watch` really released everything, rather
+/// than having to infer it from the absence of further `WatchEvent`s.
+#[derive(Debug, Clone)]
+pub struct WatchStatus {
+    /// Whether this watch has already been torn down, e.g. because its `WatchGuard` was dropped
+    /// or its delivery channel was found closed. A watch reaching this state is in the process
+    /// of, or has already finished, releasing every adapter guard it held.
+    pub is_dropped: bool,
+
+    /// Number of getters currently attached to this watch, i.e. currently matching its
+    /// selectors.
+    pub getters: usize,
+
+    /// Number of live adapter watch guards currently held by this watch, one per attached getter
+    /// whose underlying adapter watch has not yet been torn down.
+    pub guards: usize,
+}
+
 /// A data structure that causes cancellation of a watch when dropped.
 pub struct WatchGuard {
     /// The channel used to request unregistration.
@@ -221,200 +532,1450 @@ impl WatchGuard {
             is_dropped: is_dropped
         }
     }
+
+    /// A snapshot of this watch's internal bookkeeping at the moment of the call. Returns `None`
+    /// if the watch has already been fully unregistered (its entry in the `WatchMap` removed),
+    /// which can briefly outlast `is_dropped` being set while `close`'s background thread runs.
+    pub fn status(&self) -> Option<WatchStatus> {
+        let owner = match self.owner.lock() {
+            Ok(owner) => owner,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        owner.watch_status(self.key)
+    }
 }
 impl Drop for WatchGuard {
     fn drop(&mut self) {
         self.is_dropped.store(true, Ordering::Relaxed);
-        self.owner.lock().unwrap().unregister_channel_watch(self.key)
+        let mut owner = match self.owner.lock() {
+            Ok(owner) => owner,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        owner.unregister_channel_watch(self.key)
     }
 }
 
-pub struct AdapterManagerState {
-    /// Adapters, indexed by their id.
-    adapter_by_id: HashMap<Id<AdapterId>, AdapterData>,
-
-    /// Services, indexed by their id.
-    service_by_id: HashMap<Id<ServiceId>, Rc<RefCell<Service>>>,
-
-    /// Getters, indexed by their id. // FIXME: We have two copies of each setter, the other one in Service!
-    getter_by_id: HashMap<Id<Getter>, GetterData>,
+/// The kind of range transition a pending, not-yet-delivered `WatchEvent` represents.
+/// Used to detect an `Enter` immediately cancelled by a later `Exit` (or vice-versa)
+/// within a debounce window.
+#[derive(PartialEq)]
+enum PendingKind {
+    Enter,
+    Exit,
+}
 
-    /// Setters, indexed by their id. // FIXME: We have two copies of each setter, the other one in Service!
-    setter_by_id: HashMap<Id<Setter>, SetterData>,
+struct Pending {
+    generation: usize,
+    kind: PendingKind,
+    event: WatchEvent,
+}
 
-    /// The set of watchers registered. Used both when we add/remove channels
-    /// and a when a new value is available from a getter channel.
-    watchers: Arc<Mutex<WatchMap>>,
+/// A `Sender<WatchEvent>`-like sink that coalesces rapid `EnterRange`/`ExitRange` events
+/// for a given getter: each incoming event resets a per-getter quiet timer to `delay`, and
+/// only the event still pending once the timer expires is forwarded. An `Enter` followed by
+/// an `Exit` for the same getter within the window (or vice-versa) cancels out and nothing
+/// is sent. A `delay` of zero degrades to passing every event straight through.
+#[derive(Clone)]
+struct DebouncedSink {
+    tx: FinalSink,
+    delay: Duration,
+    pending: Arc<Mutex<HashMap<Id<Getter>, Pending>>>,
+    /// Monotonic, scoped to this `DebouncedSink` rather than restarted from the per-getter
+    /// `pending` map: a flush timer must only act on the exact pending write it was spawned for,
+    /// never on a later, unrelated one that happens to land in the map after the map entry this
+    /// timer was tracking has already been removed (e.g. by a prior flush). Resetting the counter
+    /// to 0 whenever the entry is momentarily absent would let a stale timer, captured before
+    /// that removal, spuriously match and flush the new write early.
+    next_generation: Arc<AtomicUsize>,
 }
+impl DebouncedSink {
+    fn new(tx: FinalSink, delay: Duration) -> Self {
+        DebouncedSink {
+            tx: tx,
+            delay: delay,
+            pending: Arc::new(Mutex::new(HashMap::new())),
+            next_generation: Arc::new(AtomicUsize::new(0)),
+        }
+    }
 
-impl AdapterManagerState {
-    /// Auxiliary function to remove a service, once the mutex has been acquired.
-    /// Clients should rather use AdapterManager::remove_service.
-    fn aux_remove_service(&mut self, id: &Id<ServiceId>) -> Result<Id<AdapterId>, Error> {
-        let (adapter, service) = match self.service_by_id.remove(&id) {
-            None => return Err(Error::InternalError(InternalError::NoSuchService(id.clone()))),
-            Some(service) => {
-                let adapter = service.borrow().adapter.clone();
-                (adapter, service)
+    fn send(&self, event: WatchEvent) {
+        if self.delay == Duration::from_secs(0) {
+            self.tx.send(event);
+            return;
+        }
+        let (getter_id, kind) = match event {
+            WatchEvent::EnterRange { ref from, .. } => (from.clone(), PendingKind::Enter),
+            WatchEvent::ExitRange { ref from, .. } => (from.clone(), PendingKind::Exit),
+            _ => {
+                self.tx.send(event);
+                return;
             }
         };
-        for id in service.borrow().getters.keys() {
-            let _ignored = self.getter_by_id.remove(id);
-        }
-        for id in service.borrow().setters.keys() {
-            let _ignored = self.setter_by_id.remove(id);
+
+        let mut pending = self.pending.lock().unwrap();
+        let cancels_out = match pending.get(&getter_id) {
+            Some(previous) => previous.kind != kind,
+            None => false,
+        };
+        if cancels_out {
+            pending.remove(&getter_id);
+            return;
         }
-        Ok(adapter)
-    }
 
-    fn with_services<F>(&self, selectors: &[ServiceSelector], mut cb: F) where F: FnMut(&Rc<RefCell<Service>>) {
-        for service in self.service_by_id.values() {
-            let matches = selectors.iter().find(|selector| {
-                selector.matches(&*service.borrow())
-            }).is_some();
-            if matches {
-                cb(service);
+        let generation = self.next_generation.fetch_add(1, Ordering::SeqCst);
+        pending.insert(getter_id.clone(), Pending { generation: generation, kind: kind, event: event });
+        drop(pending);
+
+        let delay = self.delay;
+        let tx = self.tx.clone();
+        let map = self.pending.clone();
+        thread::spawn(move || {
+            thread::sleep(delay);
+            let mut pending = map.lock().unwrap();
+            let should_flush = match pending.get(&getter_id) {
+                Some(p) if p.generation == generation => true,
+                _ => false,
+            };
+            if should_flush {
+                if let Some(p) = pending.remove(&getter_id) {
+                    tx.send(p.event);
+                }
             }
-        };
+        });
     }
+}
 
-    /// Iterate over all channels that match any selector in a slice.
-    fn with_channels<S, K, V, F>(selectors: &[S], map: &HashMap<Id<K>, V>, mut cb: F)
-        where F: FnMut(&V),
-              V: SelectedBy<S>,
-    {
-        for (_, data) in map.iter() {
-            let matches = selectors.iter().find(|selector| {
-                data.matches(selector)
-            }).is_some();
-            if matches {
-                cb(data);
+/// Reported once when a watch's event queue overflows because its consumer (the callback
+/// passed to `register_channel_watch`) is not draining fast enough to keep up with incoming
+/// events. The watch is evicted immediately afterwards, exactly as if its `WatchGuard` had
+/// been dropped: no further events will be delivered and the underlying adapter watches are
+/// released.
+pub struct WatchQueueLagged {
+    /// How many events had to be discarded to detect the overflow.
+    pub dropped: usize,
+}
+
+/// A `Sender<WatchEvent>` wrapper that optionally enforces a bound on the number of events
+/// in flight for a watch (queued in the channel, not yet handed to the consumer's callback).
+/// Once the bound is exceeded, the watch is evicted: `is_dropped` is set so no further event
+/// is accepted, `on_lagged` fires exactly once, and the watch is unregistered from `owner`.
+#[derive(Clone)]
+struct GuardedSender {
+    tx: Sender<WatchEvent>,
+    pending: Arc<AtomicUsize>,
+    max_pending: Option<usize>,
+    is_dropped: Arc<AtomicBool>,
+    lagged: Arc<AtomicBool>,
+    on_lagged: Arc<Box<Fn(WatchQueueLagged) + Send>>,
+    owner: Arc<Mutex<AdapterManagerState>>,
+    key: usize,
+}
+impl GuardedSender {
+    fn send(&self, event: WatchEvent) {
+        if self.is_dropped.load(Ordering::Relaxed) {
+            return;
+        }
+        if let Some(cap) = self.max_pending {
+            let in_flight = self.pending.fetch_add(1, Ordering::SeqCst) + 1;
+            if in_flight > cap {
+                self.pending.fetch_sub(1, Ordering::SeqCst);
+                self.evict(in_flight - cap);
+                return;
             }
         }
+        if self.tx.send(event).is_err() {
+            self.close();
+        }
     }
 
-    /// Iterate mutably over all channels that match any selector in a slice.
-    fn with_channels_mut<S, K, V, F>(selectors: &[S], map: &mut HashMap<Id<K>, V>, mut cb: F)
-        where F: FnMut(&mut V),
-              V: SelectedBy<S>,
-    {
-        for (_, data) in map.iter_mut() {
-            let matches = selectors.iter().find(|selector| {
-                data.matches(selector)
-            }).is_some();
-            if matches {
-                cb(data);
-            }
+    /// Called by the forwarding thread once it has handed an event to the consumer callback.
+    fn mark_delivered(&self) {
+        if self.max_pending.is_some() {
+            self.pending.fetch_sub(1, Ordering::SeqCst);
         }
     }
 
-    /// Iterate over all channels that match any selector in a slice.
-    fn aux_get_channels<S, K, V, T>(selectors: &[S], map: &HashMap<Id<K>, V>) -> Vec<Channel<T>>
-        where V: SelectedBy<S> + Deref<Target = Channel<T>>,
-              T: IOMechanism,
-              Channel<T>: Clone
-    {
-        let mut result = Vec::new();
-        Self::with_channels(&selectors, map, |data| {
-            result.push((*data.deref()).clone());
+    /// Tear the watch down, exactly as `WatchGuard::drop` would, once `tx`'s receiver has gone
+    /// away, e.g. the forwarding thread in `register_channel_watch` panicked mid-callback.
+    /// Unlike `evict`, there is no dropped-event count to report, so `on_lagged` is not fired.
+    fn close(&self) {
+        if self.is_dropped.swap(true, Ordering::Relaxed) {
+            // Already being torn down, e.g. racing `evict` or `WatchGuard::drop`.
+            return;
+        }
+        let owner = self.owner.clone();
+        let key = self.key;
+        thread::spawn(move || {
+            let mut owner = match owner.lock() {
+                Ok(owner) => owner,
+                Err(poisoned) => poisoned.into_inner(),
+            };
+            owner.unregister_channel_watch(key);
         });
-        result
     }
 
-    fn aux_add_channel_tags<S, K, V>(selectors: &[S], tags: &[Id<TagId>], map: &mut HashMap<Id<K>, V>) -> usize
-        where V: SelectedBy<S> + Tagged
-    {
-        let mut result = 0;
-        Self::with_channels_mut(&selectors, map, |mut data| {
-            data.insert_tags(&tags);
-            result += 1;
+    fn evict(&self, dropped: usize) {
+        if self.lagged.swap(true, Ordering::SeqCst) {
+            // Already evicted by another racing sender.
+            return;
+        }
+        self.is_dropped.store(true, Ordering::Relaxed);
+        (self.on_lagged)(WatchQueueLagged { dropped: dropped });
+        let owner = self.owner.clone();
+        let key = self.key;
+        thread::spawn(move || {
+            owner.lock().unwrap().unregister_channel_watch(key);
         });
-        result
     }
+}
 
-    fn aux_remove_channel_tags<S, K, V>(selectors: &[S], tags: &[Id<TagId>], map: &mut HashMap<Id<K>, V>) -> usize
-        where V: SelectedBy<S> + Tagged
-    {
-        let mut result = 0;
-        Self::with_channels_mut(&selectors, map, |mut data| {
-            data.remove_tags(&tags);
-            result += 1;
-        });
-        result
-    }
+/// How much data a `RingBufferedSender` may hold for a single watch before it starts dropping
+/// the oldest buffered data event to make room for the newest. `max_events` bounds the number
+/// of buffered events; `max_bytes` bounds an approximate total byte size, the shallow
+/// (`mem::size_of_val`) size of each buffered `WatchEvent`, since the `Value` payload is opaque
+/// to this crate and its heap usage cannot be measured more precisely. `None` leaves the
+/// corresponding bound unconstrained.
+#[derive(Clone, Copy)]
+pub struct WatchBufferBudget {
+    pub max_events: Option<usize>,
+    pub max_bytes: Option<usize>,
+}
 
-    /*
-        fn iter_channels<'a, S, K, V>(selectors: &[S], map: &HashMap<Id<K>, V>) ->
-            Filter<Values<'a, Id<K>, V>, &'a (Fn(&'a V) -> bool)>
-            where V: SelectedBy<S>
-        {
-            let cb : &'a Fn(&'a V) -> bool + 'a = |data: &'a V| {
-                selectors.iter().find(|selector| {
-                    data.matches(selector)
-                }).is_some()
-            };
-            map.values()
-                .filter(cb)
+impl Default for WatchBufferBudget {
+    /// A conservative default: buffer at most 256 events per watch before dropping the oldest,
+    /// with no explicit byte cap.
+    fn default() -> Self {
+        WatchBufferBudget {
+            max_events: Some(256),
+            max_bytes: None,
         }
-    */
+    }
+}
 
+/// Reported once buffered data has been dropped and the buffer has fully drained: the number
+/// of data events discarded since the last report (or since the watch started, for the first
+/// report). Delivered out-of-band rather than as a `WatchEvent` variant, since
+/// `foxbox_taxonomy::api::WatchEvent` is defined in an external crate and cannot be extended
+/// from here.
+pub struct WatchBufferDropped {
+    pub count: usize,
 }
 
-impl AdapterManagerState {
-    pub fn new() -> Self {
-        AdapterManagerState {
-           adapter_by_id: HashMap::new(),
-           service_by_id: HashMap::new(),
-           getter_by_id: HashMap::new(),
-           setter_by_id: HashMap::new(),
-           watchers: Arc::new(Mutex::new(WatchMap::new())),
-       }
+/// A `Sender<WatchEvent>`-like sink that buffers the events of a watch in a bounded `VecDeque`
+/// rather than handing them straight to the unbounded `tx` read by the forwarding thread: once
+/// `budget` is exceeded, the oldest buffered `EnterRange`/`ExitRange` event is dropped to make
+/// room for the newest (any other event, e.g. `InitializationError`, is never dropped), instead of growing memory without bound (the previous behavior) or
+/// evicting the whole watch as `GuardedSender` does. At most one event is ever in flight inside
+/// `tx` at a time; `mark_delivered` (called once the forwarding thread hands an event to the
+/// consumer) releases the next buffered event, so `tx` itself never grows unbounded. Once the
+/// buffer drains back to empty, `on_dropped` fires once with the number of events lost since
+/// the last report.
+#[derive(Clone)]
+struct RingBufferedSender {
+    tx: Sender<WatchEvent>,
+    budget: WatchBufferBudget,
+    buffer: Arc<Mutex<VecDeque<WatchEvent>>>,
+    dropped: Arc<AtomicUsize>,
+    in_flight: Arc<AtomicBool>,
+    on_dropped: Arc<Box<Fn(WatchBufferDropped) + Send>>,
+}
+impl RingBufferedSender {
+    fn new(tx: Sender<WatchEvent>, budget: WatchBufferBudget, on_dropped: Box<Fn(WatchBufferDropped) + Send>) -> Self {
+        RingBufferedSender {
+            tx: tx,
+            budget: budget,
+            buffer: Arc::new(Mutex::new(VecDeque::new())),
+            dropped: Arc::new(AtomicUsize::new(0)),
+            in_flight: Arc::new(AtomicBool::new(false)),
+            on_dropped: Arc::new(on_dropped),
+        }
     }
 
-    /// Add an adapter to the system.
-    ///
-    /// # Errors
-    ///
-    /// Returns an error if an adapter with the same id is already present.
-    pub fn add_adapter(&mut self, adapter: Box<Adapter>) -> Result<(), Error> {
-        match self.adapter_by_id.entry(adapter.id()) {
-            Entry::Occupied(_) => return Err(Error::InternalError(InternalError::DuplicateAdapter(adapter.id()))),
-            Entry::Vacant(entry) => {
-                entry.insert(AdapterData::new(adapter));
+    fn approx_size(event: &WatchEvent) -> usize {
+        mem::size_of_val(event)
+    }
+
+    fn send(&self, event: WatchEvent) {
+        let mut buffer = self.buffer.lock().unwrap();
+        let size = Self::approx_size(&event);
+        loop {
+            let over_count = self.budget.max_events.map_or(false, |max| buffer.len() >= max);
+            let over_bytes = self.budget.max_bytes.map_or(false, |max| {
+                buffer.iter().map(Self::approx_size).sum::<usize>() + size > max
+            });
+            if !over_count && !over_bytes {
+                break;
+            }
+            let oldest_data = buffer.iter().position(|event| match *event {
+                WatchEvent::EnterRange { .. } | WatchEvent::ExitRange { .. } => true,
+                _ => false,
+            });
+            match oldest_data {
+                Some(index) => { buffer.remove(index); }
+                None => break,
             }
+            self.dropped.fetch_add(1, Ordering::SeqCst);
         }
-        Ok(())
+        buffer.push_back(event);
+        self.pump(&mut buffer);
     }
 
-    /// Remove an adapter from the system, including all its services and channels.
-    ///
-    /// # Errors
-    ///
-    /// Returns an error if no adapter with this identifier exists. Otherwise, attempts
-    /// to cleanup as much as possible, even if for some reason the system is in an
-    /// inconsistent state.
-    pub fn remove_adapter(&mut self, id: &Id<AdapterId>) -> Result<(), Error> {
-        let mut services = match self.adapter_by_id.remove(id) {
-            Some(AdapterData {services: adapter_services, ..}) => {
-                adapter_services
+    /// Called by the forwarding thread once it has handed an event to the consumer callback:
+    /// release the next buffered event, if any, now that `tx` has room for it.
+    fn mark_delivered(&self) {
+        self.in_flight.store(false, Ordering::SeqCst);
+        let mut buffer = self.buffer.lock().unwrap();
+        self.pump(&mut buffer);
+    }
+
+    /// Hand the oldest buffered event to `tx`, unless one is already in flight. Once the buffer
+    /// is found empty, reports any drops accumulated since the last report.
+    fn pump(&self, buffer: &mut VecDeque<WatchEvent>) {
+        if self.in_flight.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        match buffer.pop_front() {
+            Some(event) => {
+                let _ = self.tx.send(event);
+            }
+            None => {
+                self.in_flight.store(false, Ordering::SeqCst);
+                let dropped = self.dropped.swap(0, Ordering::SeqCst);
+                if dropped > 0 {
+                    (self.on_dropped)(WatchBufferDropped { count: dropped });
+                }
             }
-            None => return Err(Error::InternalError(InternalError::NoSuchAdapter(id.clone()))),
-        };
-        for (service_id, _) in services.drain() {
-            let _ignored = self.aux_remove_service(&service_id);
         }
-        Ok(())
     }
+}
 
-    /// Add a service to the system. Called by the adapter when a new
-    /// service (typically a new device) has been detected/configured.
-    ///
-    /// The `service` must NOT have any channels yet. Channels must be added through
-    /// `add_channel`.
-    ///
-    /// # Requirements
-    ///
-    /// The adapter is in charge of making sure that identifiers persist across reboots.
+/// The final sink a watch's events are delivered through before reaching `tx`: either a hard
+/// bound that evicts the whole watch on overflow (`GuardedSender`), or a soft, memory-bounded
+/// ring buffer that keeps the watch alive and reports drops (`RingBufferedSender`). Chosen once
+/// per `register_channel_watch` call depending on whether a `WatchBufferBudget` was supplied.
+#[derive(Clone)]
+enum FinalSink {
+    Guarded(GuardedSender),
+    RingBuffered(RingBufferedSender),
+}
+impl FinalSink {
+    fn send(&self, event: WatchEvent) {
+        match *self {
+            FinalSink::Guarded(ref sink) => sink.send(event),
+            FinalSink::RingBuffered(ref sink) => sink.send(event),
+        }
+    }
+
+    fn mark_delivered(&self) {
+        match *self {
+            FinalSink::Guarded(ref sink) => sink.mark_delivered(),
+            FinalSink::RingBuffered(ref sink) => sink.mark_delivered(),
+        }
+    }
+}
+
+/// What a freshly registered watch should deliver before/instead of future range transitions.
+/// See `AdapterManagerState::register_channel_watch`.
+pub enum StreamMode {
+    /// Only ever deliver future range transitions. The previous, and still default, behavior.
+    Subscribe,
+    /// Deliver the current value of every currently-matching getter that satisfies the filter,
+    /// as an `EnterRange`, then `StreamEvent::SnapshotDone`, then nothing else: no live watch
+    /// is attached.
+    Snapshot,
+    /// Deliver the same snapshot as `Snapshot`, followed by `StreamEvent::SnapshotDone`, then
+    /// continue exactly as `Subscribe` would.
+    SnapshotThenSubscribe,
+}
+
+/// A channel watch event, extended with an out-of-band marker for the snapshot/subscribe
+/// boundary requested via `StreamMode`. `foxbox_taxonomy::api::WatchEvent` is defined in an
+/// external crate and cannot gain a `SnapshotDone` variant directly, so it is wrapped here
+/// instead.
+pub enum StreamEvent {
+    Value(WatchEvent),
+    /// Fired exactly once per `register_channel_watch` call whose `mode` is `Snapshot` or
+    /// `SnapshotThenSubscribe`, once every currently-matching getter has been given a chance
+    /// to report its value. Never fired for `StreamMode::Subscribe`.
+    SnapshotDone,
+}
+
+/// An event fired towards a topology watcher whenever a service or channel
+/// is added, removed or (un)tagged, so that clients can maintain a live
+/// device list without polling `get_services`/`get_*_channels`.
+#[derive(Clone)]
+pub enum TopologyEvent {
+    ServiceAdded(Service),
+    ServiceRemoved(Service),
+    ServiceTagged(Service),
+    ServiceUntagged(Service),
+    GetterAdded(Channel<Getter>),
+    GetterRemoved(Channel<Getter>),
+    GetterTagged(Channel<Getter>),
+    GetterUntagged(Channel<Getter>),
+    SetterAdded(Channel<Setter>),
+    SetterRemoved(Channel<Setter>),
+    SetterTagged(Channel<Setter>),
+    SetterUntagged(Channel<Setter>),
+}
+
+struct TopologyWatcherData {
+    service_selectors: Vec<ServiceSelector>,
+    getter_selectors: Vec<GetterSelector>,
+    setter_selectors: Vec<SetterSelector>,
+    /// Forwards to the dedicated thread `TopologyWatchMap::create` spawns to run `on_event`. See
+    /// `AdapterManagerState::notify_topology` for why it cannot be called inline.
+    tx: Sender<TopologyEvent>,
+    key: usize,
+}
+impl TopologyWatcherData {
+    fn matches(&self, event: &TopologyEvent) -> bool {
+        use self::TopologyEvent::*;
+        match *event {
+            ServiceAdded(ref service) | ServiceRemoved(ref service) |
+            ServiceTagged(ref service) | ServiceUntagged(ref service) =>
+                self.service_selectors.iter().find(|sel| sel.matches(service)).is_some(),
+            GetterAdded(ref channel) | GetterRemoved(ref channel) |
+            GetterTagged(ref channel) | GetterUntagged(ref channel) =>
+                self.getter_selectors.iter().find(|sel| channel.matches(sel)).is_some(),
+            SetterAdded(ref channel) | SetterRemoved(ref channel) |
+            SetterTagged(ref channel) | SetterUntagged(ref channel) =>
+                self.setter_selectors.iter().find(|sel| channel.matches(sel)).is_some(),
+        }
+    }
+}
+
+/// The registry of topology watchers currently outstanding on an `AdapterManagerState`.
+pub struct TopologyWatchMap {
+    counter: usize,
+    watchers: HashMap<usize, Arc<TopologyWatcherData>>,
+}
+impl TopologyWatchMap {
+    fn new() -> Self {
+        TopologyWatchMap {
+            counter: 0,
+            watchers: HashMap::new(),
+        }
+    }
+    fn create(&mut self, service_selectors: Vec<ServiceSelector>, getter_selectors: Vec<GetterSelector>,
+        setter_selectors: Vec<SetterSelector>, on_event: Box<Fn(TopologyEvent) + Send>) -> usize
+    {
+        let key = self.counter;
+        self.counter += 1;
+        let (tx, rx) = channel();
+        thread::spawn(move || {
+            // This thread is destroyed when we drop `tx`, i.e. when the watcher is removed. See
+            // `AdapterManagerState::notify_topology` for why `on_event` must run here rather
+            // than inline while `topology_watchers` - and whatever outer lock a caller reached
+            // it through - is held.
+            for event in rx {
+                on_event(event);
+            }
+        });
+        let watcher = Arc::new(TopologyWatcherData {
+            service_selectors: service_selectors,
+            getter_selectors: getter_selectors,
+            setter_selectors: setter_selectors,
+            tx: tx,
+            key: key,
+        });
+        self.watchers.insert(key, watcher);
+        key
+    }
+    fn remove(&mut self, key: usize) -> Option<Arc<TopologyWatcherData>> {
+        self.watchers.remove(&key)
+    }
+}
+impl Default for TopologyWatchMap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A data structure that causes cancellation of a topology watch when dropped.
+pub struct TopologyWatchGuard {
+    /// The state from which to unregister the watch.
+    owner: Arc<Mutex<AdapterManagerState>>,
+
+    /// The cancellation key.
+    key: usize,
+}
+impl TopologyWatchGuard {
+    pub fn new(owner: Arc<Mutex<AdapterManagerState>>, key: usize) -> Self {
+        TopologyWatchGuard {
+            owner: owner,
+            key: key,
+        }
+    }
+}
+impl Drop for TopologyWatchGuard {
+    fn drop(&mut self) {
+        self.owner.lock().unwrap().unregister_topology_watch(self.key)
+    }
+}
+
+pub struct AdapterManagerState {
+    /// Adapters, indexed by their id.
+    adapter_by_id: HashMap<Id<AdapterId>, AdapterData>,
+
+    /// Services, indexed by their id.
+    service_by_id: HashMap<Id<ServiceId>, Rc<RefCell<Service>>>,
+
+    /// Getters, indexed by their id. // FIXME: We have two copies of each setter, the other one in Service!
+    getter_by_id: HashMap<Id<Getter>, GetterData>,
+
+    /// Setters, indexed by their id. // FIXME: We have two copies of each setter, the other one in Service!
+    setter_by_id: HashMap<Id<Setter>, SetterData>,
+
+    /// Inverted index from tag to the getters currently carrying it, kept in sync by
+    /// `add_getter`/`remove_getter`/`add_getter_tags`/`remove_getter_tags`. Lets `with_channels`
+    /// narrow its candidate set for a tag-constrained selector instead of scanning every getter.
+    getter_by_tag: HashMap<Id<TagId>, HashSet<Id<Getter>>>,
+
+    /// As `getter_by_tag`, for setters.
+    setter_by_tag: HashMap<Id<TagId>, HashSet<Id<Setter>>>,
+
+    /// The set of watchers registered. Used both when we add/remove channels
+    /// and a when a new value is available from a getter channel.
+    watchers: Arc<Mutex<WatchMap>>,
+
+    /// The set of topology watchers registered. Notified whenever a service or
+    /// channel is added, removed or (un)tagged.
+    topology_watchers: Arc<Mutex<TopologyWatchMap>>,
+
+    /// A monotonically increasing counter, used to stamp each newly (re)registered service or
+    /// channel slot with a fresh generation, so that stale `*Handle`s can be told apart from
+    /// handles obtained after the slot was replaced.
+    next_generation: u64,
+
+    /// A monotonically increasing counter, used to stamp each `send_values_verified` call with
+    /// a fresh `RequestId` so its `SetterVerification` events can be told apart from those of a
+    /// concurrent call to the same setter.
+    next_request_id: u64,
+
+    /// The generation currently occupying the `service_by_id` slot of a given id, if any.
+    service_generation: HashMap<Id<ServiceId>, u64>,
+
+    /// The generation currently occupying the `getter_by_id` slot of a given id, if any.
+    getter_generation: HashMap<Id<Getter>, u64>,
+
+    /// The generation currently occupying the `setter_by_id` slot of a given id, if any.
+    setter_generation: HashMap<Id<Setter>, u64>,
+
+    /// The most recent value observed for a getter, along with the time it was obtained.
+    /// Updated by every successful `fetch_values`, and seeded at startup by
+    /// `persistence::PersistenceStore::restore` so that the taxonomy API is warm immediately
+    /// after a reboot, before any adapter has been re-probed.
+    last_known: HashMap<Id<Getter>, CachedValue>,
+
+    /// The last value successfully written to a setter through `send_values_checked`, consulted
+    /// as the setter's "current value" to check a `Precondition` against when the adapter itself
+    /// has no way to do so atomically. Unlike `last_known`, not updated by plain `send_values`,
+    /// so mixing the two on the same setter can make a precondition check stale.
+    last_known_setter: HashMap<Id<Setter>, Value>,
+
+    /// Adapter factories registered with `register_adapter_factory`, not yet instantiated
+    /// through `ensure_adapter`.
+    providers: AdapterProviderRegistry,
+
+    /// Worker threads used to dispatch `fetch_values`/`send_values` to several adapters
+    /// concurrently. See `WorkerPool` and `new_with_pool_size`.
+    pool: WorkerPool,
+
+    /// Every value successfully applied through a `send_values*` call is published here,
+    /// independently of whichever call produced it. See `subscribe_effects`.
+    effects: EffectBus,
+
+    /// Logical channels registered with `add_logical_channel`, indexed by their id. See
+    /// `send_to_logical_channel`.
+    logical_channels: HashMap<Id<LogicalChannelId>, LogicalChannel>,
+}
+
+/// A value observed for a getter at some point in time, together with whether it is a fresh
+/// read or one that was merely seeded (from persisted state, or from an older `fetch_values`
+/// call) and may now be out of date.
+#[derive(Clone)]
+pub struct CachedValue {
+    pub value: Option<Value>,
+    pub timestamp: SystemTime,
+
+    /// `true` if this entry was not obtained from a live `fetch_values` call, and should be
+    /// treated as possibly stale by callers that care about freshness.
+    pub seeded: bool,
+}
+
+/// A lightweight, copyable reference to a registered service, stamped with the generation of
+/// the slot at the time the handle was obtained.
+///
+/// Services and channels are keyed purely by string `Id<...>`, so removing a service (e.g. a
+/// device is unplugged) and later registering a different service under the same persisted
+/// identifier would otherwise make an outstanding reference silently address the wrong
+/// resource. Comparing the handle's `generation` against the live slot's generation (see
+/// `*_checked` methods below) detects this case instead of papering over it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ServiceHandle {
+    pub id: Id<ServiceId>,
+    generation: u64,
+}
+
+/// A lightweight, copyable reference to a registered getter. See `ServiceHandle`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GetterHandle {
+    pub id: Id<Getter>,
+    generation: u64,
+}
+
+/// A lightweight, copyable reference to a registered setter. See `ServiceHandle`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SetterHandle {
+    pub id: Id<Setter>,
+    generation: u64,
+}
+
+/// An error produced by the generation-stamped handle methods (the `*_checked` family).
+///
+/// Kept as a crate-local type rather than a new variant of `foxbox_taxonomy::api::Error`,
+/// since that enum is defined in an external crate and cannot be extended from here.
+#[derive(Debug)]
+pub enum HandleError {
+    /// The handle no longer points at the live slot: the resource it named has since been
+    /// removed, and the identifier may have been reused by an unrelated resource.
+    StaleHandle,
+
+    /// The generation matched the live slot, but the underlying operation itself failed.
+    Other(Error),
+}
+
+/// Classifies an error produced by a `send_values*` call into the `RejectionKind` its
+/// `Effect::ValueRejected` is published with. Implemented for `Error` itself and for every
+/// crate-local wrapper built around it, so `publish_sent_effects` can report a distinct kind
+/// without needing any of them to be `Clone`.
+trait RejectionKindOf {
+    fn rejection_kind(&self) -> RejectionKind;
+}
+impl RejectionKindOf for Error {
+    fn rejection_kind(&self) -> RejectionKind {
+        match *self {
+            Error::TypeError(_) => RejectionKind::TypeError,
+            Error::InternalError(_) => RejectionKind::InternalError,
+            _ => RejectionKind::Other,
+        }
+    }
+}
+impl RejectionKindOf for Arc<Error> {
+    fn rejection_kind(&self) -> RejectionKind {
+        (**self).rejection_kind()
+    }
+}
+impl RejectionKindOf for CancellationError {
+    fn rejection_kind(&self) -> RejectionKind {
+        match *self {
+            CancellationError::Inner(ref err) => err.rejection_kind(),
+            CancellationError::Cancelled => RejectionKind::Cancelled,
+        }
+    }
+}
+impl RejectionKindOf for TimeoutError {
+    fn rejection_kind(&self) -> RejectionKind {
+        match *self {
+            TimeoutError::Inner(ref err) => err.rejection_kind(),
+            TimeoutError::Timeout => RejectionKind::Timeout,
+        }
+    }
+}
+impl RejectionKindOf for ConditionalWriteError {
+    fn rejection_kind(&self) -> RejectionKind {
+        match *self {
+            ConditionalWriteError::Inner(ref err) => err.rejection_kind(),
+            ConditionalWriteError::PreconditionFailed { .. } => RejectionKind::PreconditionFailed,
+        }
+    }
+}
+impl RejectionKindOf for UpdateError {
+    fn rejection_kind(&self) -> RejectionKind {
+        match *self {
+            UpdateError::Inner(ref err) => err.rejection_kind(),
+            UpdateError::UnsupportedUpdate => RejectionKind::UnsupportedUpdate,
+        }
+    }
+}
+
+/// Publish an `Effect::ValueSent`/`Effect::ValueRejected` to `effects` for every `id` in
+/// `results`, reporting `ValueSent` only where a value is also known in `sent_values`. A free
+/// function, rather than an `AdapterManagerState` method, so `send_values_with_handle` can call
+/// it from its background resolver thread, which only holds a cloned `EffectBus` and no longer
+/// has access to `self`.
+fn publish_sent_effects<E: RejectionKindOf>(effects: &EffectBus, sent_values: &HashMap<Id<Setter>, Value>,
+    results: &[(Id<Setter>, Result<(), E>)])
+{
+    for &(ref id, ref result) in results {
+        match *result {
+            Ok(()) => {
+                if let Some(value) = sent_values.get(id) {
+                    effects.publish(Effect::ValueSent(id.clone(), value.clone()));
+                }
+            }
+            Err(ref err) => effects.publish(Effect::ValueRejected(id.clone(), err.rejection_kind())),
+        }
+    }
+}
+
+/// How often `send_values_with_handle`'s background resolver re-checks a still-pending setter's
+/// `JobToken` for cancellation while waiting on its adapter's reply.
+const CANCELLATION_POLL_INTERVAL_MS: u64 = 50;
+
+/// Shared between a `JobHandle` and the background resolver thread `send_values_with_handle`
+/// spawns: set to `true` once the setter(s) it was handed out for should be reported as
+/// cancelled.
+type JobToken = Arc<AtomicBool>;
+
+/// A handle on the still-pending setters of a `send_values_with_handle` call. Cancelling a
+/// setter - or dropping the handle, which cancels every setter still outstanding - does not
+/// interrupt its adapter mid-call; as with `TimeoutError::Timeout`, there is no way to do that.
+/// It only stops `ResultsFuture` from waiting on it: the setter is reported as
+/// `CancellationError::Cancelled` instead, unless its real result had already arrived, in which
+/// case that result is kept.
+pub struct JobHandle {
+    tokens: HashMap<Id<Setter>, JobToken>,
+}
+impl JobHandle {
+    /// Cancel every setter from this call that has not yet resolved.
+    pub fn cancel(&self) {
+        for token in self.tokens.values() {
+            token.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// Cancel a single setter from this call, leaving the others to resolve normally. Does
+    /// nothing if `id` was not part of this call.
+    pub fn cancel_setter(&self, id: &Id<Setter>) {
+        if let Some(token) = self.tokens.get(id) {
+            token.store(true, Ordering::Relaxed);
+        }
+    }
+}
+impl Drop for JobHandle {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+/// The eventual result of a `send_values_with_handle` call. This crate predates
+/// `std::future::Future` (see `manager::Subscriber`'s doc comment for why), so despite the name
+/// this has no poll/wake machinery of its own: `wait` is the only way to drain it, blocking until
+/// every setter in the call has either resolved or been cancelled.
+pub struct ResultsFuture {
+    rx: Receiver<(Id<Setter>, Result<(), CancellationError>)>,
+    remaining: usize,
+}
+impl ResultsFuture {
+    /// Block until every setter in this call has either resolved or been cancelled, and return
+    /// their combined result.
+    pub fn wait(self) -> ResultMap<Id<Setter>, (), CancellationError> {
+        let mut results = HashMap::new();
+        for _ in 0 .. self.remaining {
+            match self.rx.recv() {
+                Ok((id, result)) => { results.insert(id, result); }
+                Err(_) => break, // The resolver thread is gone; nothing more will ever arrive.
+            }
+        }
+        results
+    }
+}
+
+/// Wait for `rx`'s adapter reply, polling `tokens` every `CANCELLATION_POLL_INTERVAL_MS` so a
+/// cancellation raised while still waiting is noticed promptly instead of only once the adapter
+/// happens to reply. Every id in `ids` is submitted to the same adapter call as a single batch
+/// and so can only be resolved together, but each has its own `JobToken`: once a given id's token
+/// is set, that id alone is reported as `CancellationError::Cancelled` once `rx`'s real reply
+/// finally arrives, exactly as `JobHandle::cancel_setter`'s doc promises "leaving the others to
+/// resolve normally". Only once every id in `ids` has been cancelled is waiting for the batch's
+/// own reply abandoned - that reply, if it still arrives, is simply discarded - exactly as
+/// `TimeoutError::Timeout` discards a late reply after its own deadline.
+fn resolve_cancellable_job(adapter_id: &Id<AdapterId>, ids: &[Id<Setter>],
+    rx: &Receiver<Vec<(Id<Setter>, Result<(), Error>)>>, tokens: &HashMap<Id<Setter>, JobToken>)
+    -> Vec<(Id<Setter>, Result<(), CancellationError>)>
+{
+    let poll = Duration::from_millis(CANCELLATION_POLL_INTERVAL_MS);
+    let mut cancelled = HashSet::new();
+    loop {
+        match rx.recv_timeout(poll) {
+            Ok(got) => {
+                return got.into_iter()
+                    .map(|(id, result)| {
+                        if cancelled.contains(&id) {
+                            (id, Err(CancellationError::Cancelled))
+                        } else {
+                            (id, result.map_err(CancellationError::Inner))
+                        }
+                    })
+                    .collect();
+            }
+            Err(RecvTimeoutError::Timeout) => {
+                for id in ids {
+                    if let Some(token) = tokens.get(id) {
+                        if token.load(Ordering::Relaxed) {
+                            cancelled.insert(id.clone());
+                        }
+                    }
+                }
+                if cancelled.len() == ids.len() {
+                    return ids.iter().cloned().map(|id| (id, Err(CancellationError::Cancelled))).collect();
+                }
+            }
+            Err(RecvTimeoutError::Disconnected) => {
+                // The worker thread handling this adapter never replied - most likely it
+                // panicked mid-send. Every setter submitted to it still gets a deterministic
+                // result instead of silently vanishing from the result map.
+                return ids.iter().cloned()
+                    .map(|id| (id, Err(CancellationError::Inner(
+                        Error::InternalError(InternalError::NoSuchAdapter(adapter_id.clone()))))))
+                    .collect();
+            }
+        }
+    }
+}
+
+impl AdapterManagerState {
+    /// Auxiliary function to remove a service, once the mutex has been acquired.
+    /// Clients should rather use AdapterManager::remove_service.
+    fn aux_remove_service(&mut self, id: &Id<ServiceId>) -> Result<Id<AdapterId>, Error> {
+        let (adapter, service) = match self.service_by_id.remove(&id) {
+            None => return Err(Error::InternalError(InternalError::NoSuchService(id.clone()))),
+            Some(service) => {
+                let adapter = service.borrow().adapter.clone();
+                (adapter, service)
+            }
+        };
+        for id in service.borrow().getters.keys() {
+            if let Some(removed) = self.getter_by_id.remove(id) {
+                deindex_tags(&mut self.getter_by_tag, id, &removed.getter.tags);
+            }
+            self.getter_generation.remove(id);
+        }
+        for id in service.borrow().setters.keys() {
+            if let Some(removed) = self.setter_by_id.remove(id) {
+                deindex_tags(&mut self.setter_by_tag, id, &removed.setter.tags);
+            }
+            self.setter_generation.remove(id);
+        }
+        self.service_generation.remove(&id);
+        Ok(adapter)
+    }
+
+    fn with_services<F>(&self, selectors: &[ServiceSelector], mut cb: F) where F: FnMut(&Rc<RefCell<Service>>) {
+        for service in self.service_by_id.values() {
+            let matches = selectors.iter().find(|selector| {
+                selector.matches(&*service.borrow())
+            }).is_some();
+            if matches {
+                cb(service);
+            }
+        };
+    }
+
+    /// Like `with_services`, but for `Filtered<ServiceSelector>`: a service must both match the
+    /// wrapped selector and satisfy its `TagPredicate`.
+    fn with_services_filtered<F>(&self, selectors: &[Filtered<ServiceSelector>], mut cb: F)
+        where F: FnMut(&Rc<RefCell<Service>>)
+    {
+        for service in self.service_by_id.values() {
+            let matches = selectors.iter().find(|selector| {
+                let service = service.borrow();
+                selector.selector.matches(&*service) && selector.predicate.matches(&service.tags)
+            }).is_some();
+            if matches {
+                cb(service);
+            }
+        };
+    }
+
+    /// Iterate over all channels that match any selector in a slice. Uses `by_tag` (see
+    /// `candidate_ids`) to visit only the channels that could possibly match when every selector
+    /// is constrained by id or tag, instead of scanning the whole map.
+    fn with_channels<S, K, V, F>(selectors: &[S], map: &HashMap<Id<K>, V>,
+        by_tag: &HashMap<Id<TagId>, HashSet<Id<K>>>, mut cb: F)
+        where F: FnMut(&V),
+              V: SelectedBy<S>,
+              S: IndexedSelector<K>,
+              Id<K>: Eq + Hash + Clone,
+    {
+        match candidate_ids(selectors, by_tag) {
+            Some(candidates) => {
+                for id in &candidates {
+                    if let Some(data) = map.get(id) {
+                        if selectors.iter().find(|selector| data.matches(selector)).is_some() {
+                            cb(data);
+                        }
+                    }
+                }
+            }
+            None => {
+                for (_, data) in map.iter() {
+                    let matches = selectors.iter().find(|selector| {
+                        data.matches(selector)
+                    }).is_some();
+                    if matches {
+                        cb(data);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Iterate mutably over all channels that match any selector in a slice. See `with_channels`.
+    fn with_channels_mut<S, K, V, F>(selectors: &[S], map: &mut HashMap<Id<K>, V>,
+        by_tag: &HashMap<Id<TagId>, HashSet<Id<K>>>, mut cb: F)
+        where F: FnMut(&mut V),
+              V: SelectedBy<S>,
+              S: IndexedSelector<K>,
+              Id<K>: Eq + Hash + Clone,
+    {
+        match candidate_ids(selectors, by_tag) {
+            Some(candidates) => {
+                for id in &candidates {
+                    if let Some(data) = map.get_mut(id) {
+                        if selectors.iter().find(|selector| data.matches(selector)).is_some() {
+                            cb(data);
+                        }
+                    }
+                }
+            }
+            None => {
+                for (_, data) in map.iter_mut() {
+                    let matches = selectors.iter().find(|selector| {
+                        data.matches(selector)
+                    }).is_some();
+                    if matches {
+                        cb(data);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Iterate over all channels that match any selector in a slice.
+    fn aux_get_channels<S, K, V, T>(selectors: &[S], map: &HashMap<Id<K>, V>,
+        by_tag: &HashMap<Id<TagId>, HashSet<Id<K>>>) -> Vec<Channel<T>>
+        where V: SelectedBy<S> + Deref<Target = Channel<T>>,
+              T: IOMechanism,
+              Channel<T>: Clone,
+              S: IndexedSelector<K>,
+              Id<K>: Eq + Hash + Clone,
+    {
+        let mut result = Vec::new();
+        Self::with_channels(&selectors, map, by_tag, |data| {
+            result.push((*data.deref()).clone());
+        });
+        result
+    }
+
+    fn aux_add_channel_tags<S, K, V, T>(selectors: &[S], tags: &[Id<TagId>], map: &mut HashMap<Id<K>, V>,
+        by_tag: &mut HashMap<Id<TagId>, HashSet<Id<K>>>) -> usize
+        where V: SelectedBy<S> + Tagged + Deref<Target = Channel<T>>,
+              T: IOMechanism,
+              S: IndexedSelector<K>,
+              Id<K>: Eq + Hash + Clone,
+    {
+        let matched: Vec<Id<K>> = {
+            let mut ids = Vec::new();
+            Self::with_channels(&selectors, map, by_tag, |data| {
+                ids.push(data.deref().id.clone());
+            });
+            ids
+        };
+        for id in &matched {
+            if let Some(data) = map.get_mut(id) {
+                data.insert_tags(tags);
+            }
+            for tag in tags {
+                by_tag.entry(tag.clone()).or_insert_with(HashSet::new).insert(id.clone());
+            }
+        }
+        matched.len()
+    }
+
+    fn aux_remove_channel_tags<S, K, V, T>(selectors: &[S], tags: &[Id<TagId>], map: &mut HashMap<Id<K>, V>,
+        by_tag: &mut HashMap<Id<TagId>, HashSet<Id<K>>>) -> usize
+        where V: SelectedBy<S> + Tagged + Deref<Target = Channel<T>>,
+              T: IOMechanism,
+              S: IndexedSelector<K>,
+              Id<K>: Eq + Hash + Clone,
+    {
+        let matched: Vec<Id<K>> = {
+            let mut ids = Vec::new();
+            Self::with_channels(&selectors, map, by_tag, |data| {
+                ids.push(data.deref().id.clone());
+            });
+            ids
+        };
+        for id in &matched {
+            if let Some(data) = map.get_mut(id) {
+                data.remove_tags(tags);
+            }
+            for tag in tags {
+                let now_empty = match by_tag.get_mut(tag) {
+                    Some(set) => { set.remove(id); set.is_empty() }
+                    None => false,
+                };
+                if now_empty {
+                    by_tag.remove(tag);
+                }
+            }
+        }
+        matched.len()
+    }
+
+    /*
+        fn iter_channels<'a, S, K, V>(selectors: &[S], map: &HashMap<Id<K>, V>) ->
+            Filter<Values<'a, Id<K>, V>, &'a (Fn(&'a V) -> bool)>
+            where V: SelectedBy<S>
+        {
+            let cb : &'a Fn(&'a V) -> bool + 'a = |data: &'a V| {
+                selectors.iter().find(|selector| {
+                    data.matches(selector)
+                }).is_some()
+            };
+            map.values()
+                .filter(cb)
+        }
+    */
+
+}
+
+impl AdapterManagerState {
+    pub fn new() -> Self {
+        Self::new_with_pool_size(DEFAULT_POOL_SIZE)
+    }
+
+    /// As `new`, but with `pool_size` worker threads backing `fetch_values`/`send_values`
+    /// instead of `DEFAULT_POOL_SIZE`, for embedders that want to bound thread usage (or widen
+    /// it, for a system with many adapters that each block for a while).
+    pub fn new_with_pool_size(pool_size: usize) -> Self {
+        AdapterManagerState {
+           adapter_by_id: HashMap::new(),
+           service_by_id: HashMap::new(),
+           getter_by_id: HashMap::new(),
+           setter_by_id: HashMap::new(),
+           getter_by_tag: HashMap::new(),
+           setter_by_tag: HashMap::new(),
+           watchers: Arc::new(Mutex::new(WatchMap::new())),
+           topology_watchers: Arc::new(Mutex::new(TopologyWatchMap::new())),
+           next_generation: 0,
+           next_request_id: 0,
+           service_generation: HashMap::new(),
+           getter_generation: HashMap::new(),
+           setter_generation: HashMap::new(),
+           last_known: HashMap::new(),
+           last_known_setter: HashMap::new(),
+           providers: AdapterProviderRegistry::new(),
+           pool: WorkerPool::new(pool_size),
+           effects: EffectBus::new(),
+           logical_channels: HashMap::new(),
+       }
+    }
+
+    /// Subscribe to every value successfully applied through any `send_values*` call, as a
+    /// single retained stream independent of which call produced it. See `effects::EffectBus`.
+    pub fn subscribe_effects(&self) -> EffectReceiver {
+        self.effects.subscribe()
+    }
+
+    /// The last value observed for `id`, whether from a live `fetch_values` or seeded from
+    /// persisted state, if any is known yet.
+    pub fn cached_value(&self, id: &Id<Getter>) -> Option<CachedValue> {
+        self.last_known.get(id).cloned()
+    }
+
+    /// Prime the cache for `id` without performing a live fetch. Used by
+    /// `persistence::PersistenceStore::restore` to seed last-known values at startup.
+    pub fn seed_cached_value(&mut self, id: Id<Getter>, value: Option<Value>, timestamp: SystemTime) {
+        self.last_known.insert(id, CachedValue { value: value, timestamp: timestamp, seeded: true });
+    }
+
+    /// A snapshot of live counts and watcher fan-out, for operators to scrape or inspect.
+    /// See `metrics::Metrics`.
+    pub fn metrics(&self) -> Metrics {
+        let services_per_adapter = self.adapter_by_id.iter()
+            .map(|(id, data)| (id.clone(), data.services.len()))
+            .collect();
+        let watchers_per_getter = self.getter_by_id.iter()
+            .map(|(id, data)| (id.clone(), data.watchers.len()))
+            .collect();
+        Metrics {
+            adapters: self.adapter_by_id.len(),
+            services_per_adapter: services_per_adapter,
+            getters: self.getter_by_id.len(),
+            setters: self.setter_by_id.len(),
+            active_watchers: self.watchers.lock().unwrap().watchers.len(),
+            watchers_per_getter: watchers_per_getter,
+        }
+    }
+
+    /// Number of watches currently registered, i.e. `register_channel_watch` calls whose
+    /// `WatchGuard` has not been dropped yet. Same count as `metrics().active_watchers`, without
+    /// building a full `Metrics` snapshot.
+    pub fn watcher_count(&self) -> usize {
+        self.watchers.lock().unwrap().watchers.len()
+    }
+
+    /// Number of registered watchers currently matching `getter`. Returns 0 if `getter` is not
+    /// registered at all.
+    pub fn watchers_for(&self, getter: &Id<Getter>) -> usize {
+        self.getter_by_id.get(getter).map_or(0, |data| data.watchers.len())
+    }
+
+    /// Whether any watch currently matches `getter`, so e.g. an adapter can skip polling
+    /// hardware nobody is listening to.
+    pub fn is_watched(&self, getter: &Id<Getter>) -> bool {
+        self.watchers_for(getter) > 0
+    }
+
+    /// Every getter that currently has at least one watcher, has no native push support
+    /// (`mechanism.watch == false`) and declares a `poll` interval, paired with that interval and
+    /// the getter's `ChannelKind`. Consulted by `scheduler::PollScheduler` on every tick, so a
+    /// getter starts/stops being polled in step with its watchers gaining/losing their last one,
+    /// without this state having to be tracked separately from `GetterData::watchers` itself.
+    pub fn pollable_getters(&self) -> Vec<(Id<Getter>, Duration, ChannelKind)> {
+        self.getter_by_id.values()
+            .filter(|data| !data.watchers.is_empty() && !data.getter.mechanism.watch)
+            .filter_map(|data| data.getter.mechanism.poll
+                .map(|interval| (data.getter.id.clone(), interval, data.getter.mechanism.kind.clone())))
+            .collect()
+    }
+
+    /// Deliver a value obtained outside the normal `fetch_values` path (currently only
+    /// `scheduler::PollScheduler`, for a getter whose mechanism has no native push) to every
+    /// watcher currently registered for `id`, exactly as `attach_matching_watchers` delivers a
+    /// value pushed by an adapter's own `register_watch` callback. Does nothing if `id` is not
+    /// registered, or has no watcher.
+    ///
+    /// Unlike the adapter-push path, where the adapter itself decides when to call back (for a
+    /// ranged watch, only on an actual range-membership transition), this is called again every
+    /// time the scheduler polls a changing value - so
+    /// each watcher's own last-delivered membership (`WatcherData::poll_transitioned`) is
+    /// consulted to only redeliver on a genuine Enter/Exit, rather than flooding a watcher with
+    /// one event per tick while a value keeps changing without ever leaving its range.
+    pub fn notify_polled_value(&self, id: &Id<Getter>, value: Value) {
+        let getter_data = match self.getter_by_id.get(id) {
+            None => return,
+            Some(getter_data) => getter_data,
+        };
+        for watcher in &getter_data.watchers {
+            let filter = watcher.watch.iter()
+                .find(|&&(ref selectors, _)| selectors.iter().find(|selector| getter_data.matches(selector)).is_some())
+                .map(|&(_, ref filter)| filter.clone());
+            let range = match filter {
+                Some(Exactly::Exactly(range)) => Some(range),
+                Some(Exactly::Always) => None,
+                _ => continue, // Don't deliver a value to a topology-only watch.
+            };
+            let matches = match range {
+                Some(ref range) => range.contains(&value),
+                None => true,
+            };
+            // An unfiltered watch (`Exactly::Always`) always "matches": the scheduler itself
+            // already only calls this once a value has actually changed (see
+            // `scheduler::Tracked::last_delivered`), so every call here is a genuine update worth
+            // delivering. The transition check below only matters for a range filter, where
+            // `matches` can otherwise stay `true` across many calls while the value keeps
+            // drifting around inside the range.
+            if range.is_some() && !watcher.poll_transitioned(id, matches) {
+                continue;
+            }
+            let event = if matches {
+                WatchEvent::EnterRange { from: id.clone(), value: value.clone() }
+            } else {
+                WatchEvent::ExitRange { from: id.clone(), value: value.clone() }
+            };
+            watcher.notify(event);
+        }
+    }
+
+    /// A snapshot of the watch registered under `key` (the same key a `WatchGuard` holds
+    /// privately), for `WatchGuard::status`. Returns `None` if `key` doesn't currently refer to
+    /// a registered watch.
+    fn watch_status(&self, key: usize) -> Option<WatchStatus> {
+        self.watchers.lock().unwrap().watchers.get(&key).map(|watcher| watcher.status())
+    }
+
+    /// Attach `getter_data` to every registered watcher whose selectors already match it, as if
+    /// it had been present when `register_channel_watch` ran. This is what lets a channel added
+    /// after a watch is already in place (e.g. a device discovered later) start contributing
+    /// `WatchEvent`s without the caller having to re-register the watch.
+    ///
+    /// `cached` is consulted only for a watcher with no range filter (`Exactly::Always`): since
+    /// there is nothing to range-match against, the getter's last known value (if any) is
+    /// delivered immediately as an `EnterRange`, the same event a freshly fetched value would
+    /// produce.
+    fn attach_matching_watchers(watchers: &Arc<Mutex<WatchMap>>, adapter_by_id: &HashMap<Id<AdapterId>, AdapterData>,
+        cached: &Option<CachedValue>, getter_data: &mut GetterData)
+    {
+        let candidates: Vec<_> = watchers.lock().unwrap().watchers.values().cloned().collect();
+        for watcher in candidates {
+            let filter = watcher.watch.iter()
+                .find(|&&(ref selectors, _)| selectors.iter().find(|selector| getter_data.matches(selector)).is_some())
+                .map(|&(_, ref filter)| filter.clone());
+            let filter = match filter {
+                None => continue,
+                Some(filter) => filter,
+            };
+
+            getter_data.watchers.insert(watcher.clone());
+            watcher.push_getter(&getter_data.id);
+
+            let range = match filter {
+                Exactly::Exactly(range) => Some(range),
+                Exactly::Always => None,
+                _ => continue, // Don't watch data, just topology.
+            };
+
+            if let Some(adapter) = adapter_by_id.get(&getter_data.adapter) {
+                let watcher_for_cb = watcher.clone();
+                let cb = move |event| {
+                    let event = match event {
+                        AdapterWatchEvent::Enter { id, value } => WatchEvent::EnterRange { from: id, value: value },
+                        AdapterWatchEvent::Exit { id, value } => WatchEvent::ExitRange { from: id, value: value },
+                    };
+                    watcher_for_cb.notify(event);
+                };
+                let request = vec![(getter_data.id.clone(), range.clone())];
+                for (id, result) in adapter.register_watch(request, Box::new(cb)) {
+                    match result {
+                        Err(err) => {
+                            watcher.notify(WatchEvent::InitializationError { channel: id.clone(), error: err });
+                        },
+                        Ok(guard) => watcher.push_guard(id, guard)
+                    }
+                }
+            }
+
+            if range.is_none() {
+                if let Some(ref cached) = *cached {
+                    if let Some(ref value) = cached.value {
+                        watcher.notify(WatchEvent::EnterRange { from: getter_data.id.clone(), value: value.clone() });
+                    }
+                }
+            }
+        }
+    }
+
+    /// Stamp a freshly (re)registered slot with a fresh, never-reused generation.
+    fn next_generation(&mut self) -> u64 {
+        let generation = self.next_generation;
+        self.next_generation += 1;
+        generation
+    }
+
+    /// A fresh, never-reused id for a `send_values_verified` call. See `RequestId`.
+    fn next_request_id(&mut self) -> RequestId {
+        let id = self.next_request_id;
+        self.next_request_id += 1;
+        RequestId(id)
+    }
+
+    /// Look up the handle currently backing a registered service, if any.
+    pub fn service_handle(&self, id: &Id<ServiceId>) -> Option<ServiceHandle> {
+        self.service_generation.get(id).map(|generation| {
+            ServiceHandle { id: id.clone(), generation: *generation }
+        })
+    }
+
+    /// Look up the handle currently backing a registered getter, if any.
+    pub fn getter_handle(&self, id: &Id<Getter>) -> Option<GetterHandle> {
+        self.getter_generation.get(id).map(|generation| {
+            GetterHandle { id: id.clone(), generation: *generation }
+        })
+    }
+
+    /// Look up the handle currently backing a registered setter, if any.
+    pub fn setter_handle(&self, id: &Id<Setter>) -> Option<SetterHandle> {
+        self.setter_generation.get(id).map(|generation| {
+            SetterHandle { id: id.clone(), generation: *generation }
+        })
+    }
+
+    /// Make sure that `handle` still points at the live slot.
+    fn check_service_handle(&self, handle: &ServiceHandle) -> Result<(), HandleError> {
+        match self.service_generation.get(&handle.id) {
+            Some(generation) if *generation == handle.generation => Ok(()),
+            _ => Err(HandleError::StaleHandle)
+        }
+    }
+
+    /// Make sure that `handle` still points at the live slot.
+    fn check_getter_handle(&self, handle: &GetterHandle) -> Result<(), HandleError> {
+        match self.getter_generation.get(&handle.id) {
+            Some(generation) if *generation == handle.generation => Ok(()),
+            _ => Err(HandleError::StaleHandle)
+        }
+    }
+
+    /// Make sure that `handle` still points at the live slot.
+    fn check_setter_handle(&self, handle: &SetterHandle) -> Result<(), HandleError> {
+        match self.setter_generation.get(&handle.id) {
+            Some(generation) if *generation == handle.generation => Ok(()),
+            _ => Err(HandleError::StaleHandle)
+        }
+    }
+
+    /// Remove a service previously registered on the system, validating that `handle` still
+    /// designates the live slot. See `remove_service` and `Error::StaleHandle`'s crate-local
+    /// equivalent, `HandleError::StaleHandle`.
+    pub fn remove_service_checked(&mut self, handle: &ServiceHandle) -> Result<(), HandleError> {
+        try!(self.check_service_handle(handle));
+        self.remove_service(&handle.id).map_err(HandleError::Other)
+    }
+
+    /// Remove a getter previously registered on the system, validating that `handle` still
+    /// designates the live slot.
+    pub fn remove_getter_checked(&mut self, handle: &GetterHandle) -> Result<(), HandleError> {
+        try!(self.check_getter_handle(handle));
+        self.remove_getter(&handle.id).map_err(HandleError::Other)
+    }
+
+    /// Remove a setter previously registered on the system, validating that `handle` still
+    /// designates the live slot.
+    pub fn remove_setter_checked(&mut self, handle: &SetterHandle) -> Result<(), HandleError> {
+        try!(self.check_setter_handle(handle));
+        self.remove_setter(&handle.id).map_err(HandleError::Other)
+    }
+
+    /// Fetch a single value, validating that `handle` still designates the live slot.
+    pub fn fetch_value_checked(&mut self, handle: &GetterHandle) -> Result<Option<Value>, HandleError> {
+        try!(self.check_getter_handle(handle));
+        let selector = GetterSelector::new().with_id(handle.id.clone());
+        match self.fetch_values(&[selector]).drain(..).next() {
+            Some((_, Ok(value))) => Ok(value),
+            Some((_, Err(err))) => Err(HandleError::Other(err)),
+            None => Err(HandleError::StaleHandle)
+        }
+    }
+
+    /// Send a single value, validating that `handle` still designates the live slot.
+    pub fn send_value_checked(&mut self, handle: &SetterHandle, value: Value) -> Result<(), HandleError> {
+        try!(self.check_setter_handle(handle));
+        let selector = SetterSelector::new().with_id(handle.id.clone());
+        match self.send_values(vec![(vec![selector], value)]).drain(..).next() {
+            Some((_, Ok(()))) => Ok(()),
+            Some((_, Err(err))) => Err(HandleError::Other(err)),
+            None => Err(HandleError::StaleHandle)
+        }
+    }
+
+    /// Notify any topology watcher whose selectors match `event`.
+    ///
+    /// This only ever sends `event` to each matching watcher's forwarding thread - it never
+    /// calls a watcher's `on_event` inline. Every call site reaches `notify_topology` through
+    /// `add_service`/`add_getter`/`add_setter`, `remove_service`/`remove_getter`/`remove_setter`
+    /// or the `*_tags` family, all of which run under the
+    /// outer `back_end: Arc<Mutex<AdapterManagerState>>` lock `AdapterManager` already holds; a
+    /// callback that calls back into `AdapterManager` (e.g. `get_services`, or simply dropping a
+    /// `TopologyWatchGuard`) would self-deadlock on that non-reentrant `Mutex` if it ran here.
+    /// See `TopologyWatchMap::create`, which mirrors the same dispatch-to-a-thread pattern
+    /// `register_channel_watch` uses for value watches.
+    fn notify_topology(&self, event: TopologyEvent) {
+        let watchers = self.topology_watchers.lock().unwrap();
+        for watcher in watchers.watchers.values() {
+            if watcher.matches(&event) {
+                let _ = watcher.tx.send(event.clone());
+            }
+        }
+    }
+
+    /// Register a watch for topology changes (services and channels appearing, disappearing
+    /// or being (un)tagged) matching any of the given selectors.
+    ///
+    /// Returns the key used to unregister the watch through `unregister_topology_watch`.
+    pub fn register_topology_watch(&mut self, service_selectors: Vec<ServiceSelector>,
+        getter_selectors: Vec<GetterSelector>, setter_selectors: Vec<SetterSelector>,
+        on_event: Box<Fn(TopologyEvent) + Send>) -> usize
+    {
+        self.topology_watchers.lock().unwrap().create(service_selectors, getter_selectors,
+            setter_selectors, on_event)
+    }
+
+    /// Unregister a topology watch previously registered with `register_topology_watch`.
+    ///
+    /// This method is dispatched from `TopologyWatchGuard::drop()`.
+    pub fn unregister_topology_watch(&mut self, key: usize) {
+        let _ = self.topology_watchers.lock().unwrap().remove(key);
+    }
+
+    /// Add an adapter to the system.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if an adapter with the same id is already present.
+    pub fn add_adapter(&mut self, adapter: Box<Adapter>) -> Result<(), Error> {
+        match self.adapter_by_id.entry(adapter.id()) {
+            Entry::Occupied(_) => return Err(Error::InternalError(InternalError::DuplicateAdapter(adapter.id()))),
+            Entry::Vacant(entry) => {
+                entry.insert(AdapterData::new(adapter));
+            }
+        }
+        Ok(())
+    }
+
+    /// Register `factory` for later, lazy instantiation through `ensure_adapter`, instead of
+    /// constructing the adapter and calling `add_adapter` right away.
+    pub fn register_adapter_factory(&mut self, factory: Box<AdapterFactory>) {
+        self.providers.register(factory);
+    }
+
+    /// Ids of every registered adapter factory, not yet instantiated, that declares
+    /// `capability`. Does not instantiate any of the matching adapters; call `ensure_adapter`
+    /// on the ids actually needed.
+    pub fn adapters_with_capability(&self, capability: &str) -> Vec<Id<AdapterId>> {
+        self.providers.with_capability(capability)
+    }
+
+    /// Make sure `id` is present in `adapter_by_id`, instantiating it from a registered factory
+    /// if it isn't already. Does nothing if `id` is already a live adapter, whether it was
+    /// added directly through `add_adapter` or by an earlier call to `ensure_adapter`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `id` is neither a live adapter nor a registered factory.
+    pub fn ensure_adapter(&mut self, id: &Id<AdapterId>) -> Result<(), Error> {
+        if self.adapter_by_id.contains_key(id) {
+            return Ok(());
+        }
+        match self.providers.take(id) {
+            None => Err(Error::InternalError(InternalError::NoSuchAdapter(id.clone()))),
+            Some(adapter) => self.add_adapter(adapter),
+        }
+    }
+
+    /// Remove an adapter from the system, including all its services and channels.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no adapter with this identifier exists. Otherwise, attempts
+    /// to cleanup as much as possible, even if for some reason the system is in an
+    /// inconsistent state.
+    pub fn remove_adapter(&mut self, id: &Id<AdapterId>) -> Result<(), Error> {
+        let mut services = match self.adapter_by_id.remove(id) {
+            Some(AdapterData {services: adapter_services, ..}) => {
+                adapter_services
+            }
+            None => return Err(Error::InternalError(InternalError::NoSuchAdapter(id.clone()))),
+        };
+        for (service_id, _) in services.drain() {
+            let _ignored = self.aux_remove_service(&service_id);
+        }
+        Ok(())
+    }
+
+    /// Add a service to the system. Called by the adapter when a new
+    /// service (typically a new device) has been detected/configured.
+    ///
+    /// The `service` must NOT have any channels yet. Channels must be added through
+    /// `add_channel`.
+    ///
+    /// # Requirements
+    ///
+    /// The adapter is in charge of making sure that identifiers persist across reboots.
     ///
     /// # Errors
     ///
@@ -422,11 +1983,16 @@ impl AdapterManagerState {
     /// - `service` has channels;
     /// - a service with id `service.id` is already installed on the system;
     /// - there is no adapter with id `service.adapter`.
-    pub fn add_service(&mut self, service: Service) -> Result<(), Error> {
+    ///
+    /// On success, returns a handle to the newly-registered service, stamped with its
+    /// generation, so the caller never needs a separate `service_handle` call that a concurrent
+    /// remove/re-add of the same id could race.
+    pub fn add_service(&mut self, service: Service) -> Result<ServiceHandle, Error> {
         // Make sure that there are no channels.
         if !service.getters.is_empty() || !service.setters.is_empty() {
             return Err(Error::InternalError(InternalError::InvalidInitialService));
         }
+        let service_for_event = service.clone();
         let mut services_for_this_adapter =
             match self.adapter_by_id.get_mut(&service.adapter) {
                 None => return Err(Error::InternalError(InternalError::NoSuchAdapter(service.adapter.clone()))),
@@ -451,7 +2017,10 @@ impl AdapterManagerState {
         // If we haven't bailed out yet, leave all this stuff in the maps and sets.
         insert_in_adapters.commit();
         insert_in_services.commit();
-        Ok(())
+        let generation = self.next_generation();
+        self.service_generation.insert(id.clone(), generation);
+        self.notify_topology(TopologyEvent::ServiceAdded(service_for_event));
+        Ok(ServiceHandle { id: id, generation: generation })
     }
 
     /// Remove a service previously registered on the system. Typically, called by
@@ -464,6 +2033,7 @@ impl AdapterManagerState {
     /// - there is an internal inconsistency, in which case this method will still attempt to
     /// cleanup before returning an error.
     pub fn remove_service(&mut self, service_id: &Id<ServiceId>) -> Result<(), Error> {
+        let service_for_event = self.service_by_id.get(service_id).map(|service| service.borrow().clone());
         let adapter = try!(self.aux_remove_service(service_id));
         match self.adapter_by_id.get_mut(&adapter) {
             None => Err(Error::InternalError(InternalError::NoSuchAdapter(adapter.clone()))),
@@ -471,6 +2041,10 @@ impl AdapterManagerState {
                 if data.services.remove(&service_id).is_none() {
                     Err(Error::InternalError(InternalError::NoSuchService(service_id.clone())))
                 } else {
+                    self.service_generation.remove(service_id);
+                    if let Some(service) = service_for_event {
+                        self.notify_topology(TopologyEvent::ServiceRemoved(service));
+                    }
                     Ok(())
                 }
             }
@@ -490,7 +2064,11 @@ impl AdapterManagerState {
     /// Returns an error if the adapter is not registered, the parent service is not
     /// registered, or a channel with the same identifier is already registered.
     /// In either cases, this method reverts all its changes.
-    pub fn add_getter(&mut self, getter: Channel<Getter>) -> Result<(), Error> {
+    ///
+    /// On success, returns a handle to the newly-registered getter. See `add_service`.
+    pub fn add_getter(&mut self, getter: Channel<Getter>) -> Result<GetterHandle, Error> {
+        let getter_for_event = getter.clone();
+        let cached = self.last_known.get(&getter.id).cloned();
         let getter_by_id = &mut self.getter_by_id;
         let service = match self.service_by_id.get_mut(&getter.service) {
             None => return Err(Error::InternalError(InternalError::NoSuchService(getter.service.clone()))),
@@ -506,21 +2084,11 @@ impl AdapterManagerState {
             Err(id) => return Err(Error::InternalError(InternalError::DuplicateGetter(id)))
         };
 
-        /*
-                // FIXME: Check whether we match an ongoing watcher.
-                for watcher in &mut watchers.lock().unwrap().watchers.values() {
-                    let matches = watcher.selectors.iter().find(|selector| {
-                        getter_data.matches(selector)
-                    }).is_some();
-                    if matches {
-                        getter_data.watchers.insert(watcher.clone());
-                        watcher.push_getter(&getter_data.id);
-                        // FIXME: Notify WatchEvent of topology change
-                        // FIXME: register_single_channel_watch_values
-                    };
-                }
-        */
-        let getter_data = GetterData::new(getter);
+        let mut getter_data = GetterData::new(getter);
+        // Attach to any watcher already registered whose selectors match this getter, exactly as
+        // if it had been present when that watch was set up.
+        Self::attach_matching_watchers(&self.watchers, &self.adapter_by_id, &cached, &mut getter_data);
+
         let insert_in_getters = match InsertInMap::start(getter_by_id, vec![(getter_data.id.clone(), getter_data)]) {
             Ok(transaction) => transaction,
             Err(id) => return Err(Error::InternalError(InternalError::DuplicateGetter(id)))
@@ -528,7 +2096,12 @@ impl AdapterManagerState {
 
         insert_in_service.commit();
         insert_in_getters.commit();
-        Ok(())
+        index_tags(&mut self.getter_by_tag, &getter_for_event.id, &getter_for_event.tags);
+        let generation = self.next_generation();
+        self.getter_generation.insert(getter_for_event.id.clone(), generation);
+        let handle = GetterHandle { id: getter_for_event.id.clone(), generation: generation };
+        self.notify_topology(TopologyEvent::GetterAdded(getter_for_event));
+        Ok(handle)
     }
 
     /// Remove a setter previously registered on the system. Typically, called by
@@ -544,140 +2117,667 @@ impl AdapterManagerState {
             None => return Err(Error::InternalError(InternalError::NoSuchGetter(id.clone()))),
             Some(getter) => getter
         };
+        // Detach from every watcher that was following this getter, so it stops being considered
+        // for future adapter callbacks and its per-getter adapter watch is torn down.
+        for watcher in &getter.watchers {
+            watcher.remove_getter(id);
+            watcher.drop_guard(id);
+        }
+        deindex_tags(&mut self.getter_by_tag, id, &getter.getter.tags);
+        let getter_for_event = getter.getter.clone();
         match self.service_by_id.get_mut(&getter.getter.service) {
             None => Err(Error::InternalError(InternalError::NoSuchService(getter.getter.service))),
             Some(service) => {
                 if service.borrow_mut().getters.remove(&id).is_none() {
                     Err(Error::InternalError(InternalError::NoSuchGetter(id.clone())))
                 } else {
+                    self.getter_generation.remove(id);
+                    self.notify_topology(TopologyEvent::GetterRemoved(getter_for_event));
+                    Ok(())
+                }
+            }
+        }
+    }
+
+    /// Add a setter to the system. Typically, this is called by the adapter when a new
+    /// service has been detected/configured. Some services may gain/lose setters at
+    /// runtime depending on their configuration.
+    ///
+    /// # Requirements
+    ///
+    /// The adapter is in charge of making sure that identifiers persist across reboots.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the adapter is not registered, the parent service is not
+    /// registered, or a channel with the same identifier is already registered.
+    /// In either cases, this method reverts all its changes.
+    ///
+    /// On success, returns a handle to the newly-registered setter. See `add_service`.
+    pub fn add_setter(&mut self, setter: Channel<Setter>) -> Result<SetterHandle, Error> {
+        let setter_for_event = setter.clone();
+        let service = match self.service_by_id.get_mut(&setter.service) {
+            None => return Err(Error::InternalError(InternalError::NoSuchService(setter.service.clone()))),
+            Some(service) => service
+        };
+        if service.borrow().adapter != setter.adapter {
+            return Err(Error::InternalError(InternalError::ConflictingAdapter(service.borrow().adapter.clone(), setter.adapter)));
+        }
+        let setters = &mut service.borrow_mut().setters;
+        let insert_in_service = match InsertInMap::start(setters, vec![(setter.id.clone(), setter.clone())]) {
+            Ok(transaction) => transaction,
+            Err(id) => return Err(Error::InternalError(InternalError::DuplicateSetter(id)))
+        };
+        let insert_in_setters = match InsertInMap::start(&mut self.setter_by_id, vec![(setter.id.clone(), SetterData::new(setter))]) {
+            Ok(transaction) => transaction,
+            Err(id) => return Err(Error::InternalError(InternalError::DuplicateSetter(id)))
+        };
+        insert_in_service.commit();
+        insert_in_setters.commit();
+        index_tags(&mut self.setter_by_tag, &setter_for_event.id, &setter_for_event.tags);
+        let generation = self.next_generation();
+        self.setter_generation.insert(setter_for_event.id.clone(), generation);
+        let handle = SetterHandle { id: setter_for_event.id.clone(), generation: generation };
+        self.notify_topology(TopologyEvent::SetterAdded(setter_for_event));
+        Ok(handle)
+    }
+
+    /// Remove a setter previously registered on the system. Typically, called by
+    /// an adapter when a service is reconfigured to remove one of its setters.
+    ///
+    /// # Error
+    ///
+    /// This method returns an error if the setter is not registered or if the service
+    /// is not registered. In either case, it attemps to clean as much as possible, even
+    /// if the state is inconsistent.
+    pub fn remove_setter(&mut self, id: &Id<Setter>) -> Result<(), Error> {
+        let setter = match self.setter_by_id.remove(id) {
+            None => return Err(Error::InternalError(InternalError::NoSuchSetter(id.clone()))),
+            Some(setter) => setter
+        };
+        deindex_tags(&mut self.setter_by_tag, id, &setter.setter.tags);
+        let setter_for_event = setter.setter.clone();
+        match self.service_by_id.get_mut(&setter.setter.service) {
+            None => Err(Error::InternalError(InternalError::NoSuchService(setter.setter.service))),
+            Some(service) => {
+                if service.borrow_mut().setters.remove(id).is_none() {
+                    Err(Error::InternalError(InternalError::NoSuchSetter(id.clone())))
+                } else {
+                    self.setter_generation.remove(id);
+                    self.notify_topology(TopologyEvent::SetterRemoved(setter_for_event));
                     Ok(())
                 }
             }
         }
     }
 
-    /// Add a setter to the system. Typically, this is called by the adapter when a new
-    /// service has been detected/configured. Some services may gain/lose setters at
-    /// runtime depending on their configuration.
-    ///
-    /// # Requirements
+    pub fn get_services(&self, selectors: &[ServiceSelector]) -> Vec<Service> {
+        // This implementation is not nearly optimal, but it should be sufficient in a system
+        // with relatively few services.
+        let mut result = Vec::new();
+        self.with_services(selectors, |service| {
+            result.push(service.borrow().clone())
+        });
+        result
+    }
+
+    /// Like `get_services`, but each selector is a `Filtered<ServiceSelector>`: see `Filtered`
+    /// for the tag negation/disjunction this adds on top of plain `.with_tags(..)` matching.
+    pub fn get_services_matching(&self, selectors: &[Filtered<ServiceSelector>]) -> Vec<Service> {
+        let mut result = Vec::new();
+        self.with_services_filtered(selectors, |service| {
+            result.push(service.borrow().clone())
+        });
+        result
+    }
+
+    pub fn add_service_tags(&self, selectors: &[ServiceSelector], tags: &[Id<TagId>]) -> usize {
+        let mut result = 0;
+        self.with_services(selectors, |service| {
+            {
+                let tag_set = &mut service.borrow_mut().tags;
+                for tag in tags {
+                    let _ = tag_set.insert(tag.clone());
+                }
+            }
+            result += 1;
+            self.notify_topology(TopologyEvent::ServiceTagged(service.borrow().clone()));
+        });
+        result
+    }
+
+    pub fn remove_service_tags(&self, selectors: &[ServiceSelector], tags: &[Id<TagId>]) -> usize {
+        let mut result = 0;
+        self.with_services(selectors, |service| {
+            {
+                let tag_set = &mut service.borrow_mut().tags;
+                for tag in tags {
+                    let _ = tag_set.remove(tag);
+                }
+            }
+            result += 1;
+            self.notify_topology(TopologyEvent::ServiceUntagged(service.borrow().clone()));
+        });
+        result
+    }
+
+    /// Like `add_service_tags`, but each selector is a `Filtered<ServiceSelector>`. See `Filtered`.
+    pub fn add_service_tags_matching(&self, selectors: &[Filtered<ServiceSelector>], tags: &[Id<TagId>]) -> usize {
+        let mut result = 0;
+        self.with_services_filtered(selectors, |service| {
+            {
+                let tag_set = &mut service.borrow_mut().tags;
+                for tag in tags {
+                    let _ = tag_set.insert(tag.clone());
+                }
+            }
+            result += 1;
+            self.notify_topology(TopologyEvent::ServiceTagged(service.borrow().clone()));
+        });
+        result
+    }
+
+    /// Like `remove_service_tags`, but each selector is a `Filtered<ServiceSelector>`. See
+    /// `Filtered`.
+    pub fn remove_service_tags_matching(&self, selectors: &[Filtered<ServiceSelector>], tags: &[Id<TagId>]) -> usize {
+        let mut result = 0;
+        self.with_services_filtered(selectors, |service| {
+            {
+                let tag_set = &mut service.borrow_mut().tags;
+                for tag in tags {
+                    let _ = tag_set.remove(tag);
+                }
+            }
+            result += 1;
+            self.notify_topology(TopologyEvent::ServiceUntagged(service.borrow().clone()));
+        });
+        result
+    }
+
+    pub fn get_getter_channels(&self, selectors: &[GetterSelector]) -> Vec<Channel<Getter>>
+    {
+        Self::aux_get_channels(selectors, &self.getter_by_id, &self.getter_by_tag)
+    }
+    pub fn get_setter_channels(&self, selectors: &[SetterSelector]) -> Vec<Channel<Setter>>
+    {
+        Self::aux_get_channels(selectors, &self.setter_by_id, &self.setter_by_tag)
+    }
+
+    /// Like `get_getter_channels`, but each selector is a `Filtered<GetterSelector>`. See
+    /// `Filtered`.
+    pub fn get_getter_channels_matching(&self, selectors: &[Filtered<GetterSelector>]) -> Vec<Channel<Getter>>
+    {
+        Self::aux_get_channels(selectors, &self.getter_by_id, &self.getter_by_tag)
+    }
+    /// Like `get_setter_channels`, but each selector is a `Filtered<SetterSelector>`. See
+    /// `Filtered`.
+    pub fn get_setter_channels_matching(&self, selectors: &[Filtered<SetterSelector>]) -> Vec<Channel<Setter>>
+    {
+        Self::aux_get_channels(selectors, &self.setter_by_id, &self.setter_by_tag)
+    }
+
+
+    pub fn add_getter_tags(&mut self, selectors: &[GetterSelector], tags: &[Id<TagId>]) -> usize {
+        let result = Self::aux_add_channel_tags(selectors, tags, &mut self.getter_by_id, &mut self.getter_by_tag);
+        for channel in Self::aux_get_channels(selectors, &self.getter_by_id, &self.getter_by_tag) {
+            self.notify_topology(TopologyEvent::GetterTagged(channel));
+        }
+        result
+    }
+    pub fn add_setter_tags(&mut self, selectors: &[SetterSelector], tags: &[Id<TagId>]) -> usize {
+        let result = Self::aux_add_channel_tags(selectors, tags, &mut self.setter_by_id, &mut self.setter_by_tag);
+        for channel in Self::aux_get_channels(selectors, &self.setter_by_id, &self.setter_by_tag) {
+            self.notify_topology(TopologyEvent::SetterTagged(channel));
+        }
+        result
+    }
+    pub fn remove_getter_tags(&mut self, selectors: &[GetterSelector], tags: &[Id<TagId>]) -> usize {
+        // Selected before the removal: a selector naming a removed tag matches nothing after it.
+        // Each event carries the channel as it was just before being untagged.
+        let channels = Self::aux_get_channels(selectors, &self.getter_by_id, &self.getter_by_tag);
+        let result = Self::aux_remove_channel_tags(selectors, tags, &mut self.getter_by_id, &mut self.getter_by_tag);
+        for channel in channels {
+            self.notify_topology(TopologyEvent::GetterUntagged(channel));
+        }
+        result
+    }
+    pub fn remove_setter_tags(&mut self, selectors: &[SetterSelector], tags: &[Id<TagId>]) -> usize {
+        let channels = Self::aux_get_channels(selectors, &self.setter_by_id, &self.setter_by_tag);
+        let result = Self::aux_remove_channel_tags(selectors, tags, &mut self.setter_by_id, &mut self.setter_by_tag);
+        for channel in channels {
+            self.notify_topology(TopologyEvent::SetterUntagged(channel));
+        }
+        result
+    }
+
+    /// Like `add_getter_tags`, but each selector is a `Filtered<GetterSelector>`. See `Filtered`.
+    pub fn add_getter_tags_matching(&mut self, selectors: &[Filtered<GetterSelector>], tags: &[Id<TagId>]) -> usize {
+        let result = Self::aux_add_channel_tags(selectors, tags, &mut self.getter_by_id, &mut self.getter_by_tag);
+        for channel in Self::aux_get_channels(selectors, &self.getter_by_id, &self.getter_by_tag) {
+            self.notify_topology(TopologyEvent::GetterTagged(channel));
+        }
+        result
+    }
+    /// Like `add_setter_tags`, but each selector is a `Filtered<SetterSelector>`. See `Filtered`.
+    pub fn add_setter_tags_matching(&mut self, selectors: &[Filtered<SetterSelector>], tags: &[Id<TagId>]) -> usize {
+        let result = Self::aux_add_channel_tags(selectors, tags, &mut self.setter_by_id, &mut self.setter_by_tag);
+        for channel in Self::aux_get_channels(selectors, &self.setter_by_id, &self.setter_by_tag) {
+            self.notify_topology(TopologyEvent::SetterTagged(channel));
+        }
+        result
+    }
+    /// Like `remove_getter_tags`, but each selector is a `Filtered<GetterSelector>`. See
+    /// `Filtered`.
+    pub fn remove_getter_tags_matching(&mut self, selectors: &[Filtered<GetterSelector>], tags: &[Id<TagId>]) -> usize {
+        let channels = Self::aux_get_channels(selectors, &self.getter_by_id, &self.getter_by_tag);
+        let result = Self::aux_remove_channel_tags(selectors, tags, &mut self.getter_by_id, &mut self.getter_by_tag);
+        for channel in channels {
+            self.notify_topology(TopologyEvent::GetterUntagged(channel));
+        }
+        result
+    }
+    /// Like `remove_setter_tags`, but each selector is a `Filtered<SetterSelector>`. See
+    /// `Filtered`.
+    pub fn remove_setter_tags_matching(&mut self, selectors: &[Filtered<SetterSelector>], tags: &[Id<TagId>]) -> usize {
+        let channels = Self::aux_get_channels(selectors, &self.setter_by_id, &self.setter_by_tag);
+        let result = Self::aux_remove_channel_tags(selectors, tags, &mut self.setter_by_id, &mut self.setter_by_tag);
+        for channel in channels {
+            self.notify_topology(TopologyEvent::SetterUntagged(channel));
+        }
+        result
+    }
+
+    /// Read the latest value from a set of channels
+    pub fn fetch_values(&mut self, selectors: &[GetterSelector]) -> ResultSet<Id<Getter>, Option<Value>, Error> {
+        // First group per adapter, so as to let adapters optimize fetches.
+        let mut per_adapter = HashMap::new();
+        Self::with_channels(selectors, &self.getter_by_id, &self.getter_by_tag, |data| {
+            use std::collections::hash_map::Entry::*;
+            match per_adapter.entry(data.getter.adapter.clone()) {
+                Vacant(entry) => {
+                    entry.insert(vec![data.getter.id.clone()]);
+                }
+                Occupied(mut entry) => {
+                    entry.get_mut().push(data.getter.id.clone());
+                }
+            }
+        });
+
+        // Now fetch the values, one job per adapter submitted to the worker pool so a slow
+        // adapter doesn't stall the others; each job reports back through its own one-shot
+        // channel rather than sharing a single `Sender` across adapters.
+        let mut pending = Vec::new();
+        for (adapter_id, getters) in per_adapter {
+            let adapter = match self.adapter_by_id.get(&adapter_id) {
+                None => continue, // Internal inconsistency. FIXME: Log this somewhere.
+                Some(adapter_data) => adapter_data.adapter.clone(),
+            };
+            let ids = getters.clone();
+            let (tx, rx) = channel();
+            self.pool.submit(Box::new(move || {
+                let _ = tx.send(adapter.fetch_values(getters));
+            }));
+            pending.push((adapter_id, ids, rx));
+        }
+
+        let mut results = vec![];
+        for (adapter_id, ids, rx) in pending {
+            let mut got = match rx.recv() {
+                Ok(got) => got,
+                Err(_) => {
+                    // The worker thread handling this adapter never replied - most likely it
+                    // panicked mid-fetch. Every getter submitted to it still gets a deterministic
+                    // result instead of silently vanishing from the result set.
+                    ids.into_iter()
+                        .map(|id| (id, Err(Error::InternalError(InternalError::NoSuchAdapter(adapter_id.clone())))))
+                        .collect()
+                }
+            };
+            for &(ref id, ref result) in &got {
+                if let Ok(ref value) = *result {
+                    self.last_known.insert(id.clone(), CachedValue {
+                        value: value.clone(),
+                        timestamp: SystemTime::now(),
+                        seeded: false,
+                    });
+                    if let Some(ref value) = *value {
+                        self.effects.publish(Effect::ValueRead(id.clone(), value.clone()));
+                    }
+                }
+            }
+            results.append(&mut got);
+        }
+        results
+    }
+
+    /// Resolve `keyvalues` into a `setter id -> (adapter id, value)` map, coalescing
+    /// last-writer-wins onto the setter id: if several entries in the same `send_values*` batch
+    /// resolve to the same setter - whether one selector matches it more than once or two
+    /// different entries target it - only the last value is kept, so a batch only ever
+    /// dispatches (and reports) a single send per setter. Shared by every `send_values*` call
+    /// that targets a plain `SetterSelector`/`Value` pair; `send_values_checked` keeps its own
+    /// grouping instead, since coalescing there could silently drop an earlier entry's
+    /// `Precondition`.
+    fn coalesce_setter_values(&self, keyvalues: Vec<(Vec<SetterSelector>, Value)>)
+        -> HashMap<Id<Setter>, (Id<AdapterId>, Value)>
+    {
+        let mut coalesced = HashMap::new();
+        for (selectors, value) in keyvalues {
+            Self::with_channels(&selectors, &self.setter_by_id, &self.setter_by_tag, |data| {
+                coalesced.insert(data.setter.id.clone(), (data.setter.adapter.clone(), value.clone()));
+            })
+        }
+        coalesced
+    }
+
+    /// Send values to a set of channels
+    pub fn send_values(&self, keyvalues: Vec<(Vec<SetterSelector>, Value)>) -> ResultMap<Id<Setter>, (), Error> {
+        // First determine the channels and group them by adapter, coalescing repeated sends to
+        // the same setter within this batch down to the last value (see `coalesce_setter_values`).
+        let mut per_adapter: HashMap<Id<AdapterId>, Vec<(Id<Setter>, Value)>> = HashMap::new();
+        let mut sent_values = HashMap::new();
+        for (id, (adapter_id, value)) in self.coalesce_setter_values(keyvalues) {
+            sent_values.insert(id.clone(), value.clone());
+            per_adapter.entry(adapter_id).or_insert_with(Vec::new).push((id, value));
+        }
+
+
+        // Dispatch to adapter, one job per adapter submitted to the worker pool so a slow
+        // adapter doesn't stall the others.
+        let mut pending = Vec::new();
+        for (adapter_id, payload) in per_adapter.drain() {
+            let adapter = match self.adapter_by_id.get(&adapter_id) {
+                None => continue, // That's an internal inconsistency. FIXME: Log this somewhere.
+                Some(adapter_data) => adapter_data.adapter.clone(),
+            };
+            let ids: Vec<_> = payload.iter().map(|&(ref id, _)| id.clone()).collect();
+            let (tx, rx) = channel();
+            self.pool.submit(Box::new(move || {
+                let _ = tx.send(adapter.send_values(payload));
+            }));
+            pending.push((adapter_id, ids, rx));
+        }
+
+        let mut results = Vec::new();
+        for (adapter_id, ids, rx) in pending {
+            match rx.recv() {
+                Ok(mut got) => results.append(&mut got),
+                Err(_) => {
+                    // The worker thread handling this adapter never replied - most likely it
+                    // panicked mid-send. Every setter submitted to it still gets a deterministic
+                    // result instead of silently vanishing from the result map.
+                    results.extend(ids.into_iter()
+                        .map(|id| (id, Err(Error::InternalError(InternalError::NoSuchAdapter(adapter_id.clone()))))));
+                }
+            }
+        }
+
+        self.publish_sent_effects(&sent_values, &results);
+        results
+    }
+
+    /// Like `send_values`, but never blocks: dispatch is submitted to the worker pool and this
+    /// method returns immediately, handing back a `JobHandle` that can cancel any still-pending
+    /// setter and a `ResultsFuture` that eventually resolves to the same result map `send_values`
+    /// would have returned. Useful when one targeted adapter may be wedged and the caller cannot
+    /// afford to have the whole batch - or the manager's own internal lock - block on it.
     ///
-    /// The adapter is in charge of making sure that identifiers persist across reboots.
-    ///
-    /// # Errors
-    ///
-    /// Returns an error if the adapter is not registered, the parent service is not
-    /// registered, or a channel with the same identifier is already registered.
-    /// In either cases, this method reverts all its changes.
-    pub fn add_setter(&mut self, setter: Channel<Setter>) -> Result<(), Error> {
-        let service = match self.service_by_id.get_mut(&setter.service) {
-            None => return Err(Error::InternalError(InternalError::NoSuchService(setter.service.clone()))),
-            Some(service) => service
-        };
-        if service.borrow().adapter != setter.adapter {
-            return Err(Error::InternalError(InternalError::ConflictingAdapter(service.borrow().adapter.clone(), setter.adapter)));
+    /// A cancelled setter whose adapter has not yet replied is reported as
+    /// `CancellationError::Cancelled`; one that already resolved (successfully or not) before
+    /// being cancelled keeps its real result instead, exactly as `JobHandle::cancel` promises.
+    pub fn send_values_with_handle(&self, keyvalues: Vec<(Vec<SetterSelector>, Value)>)
+        -> (JobHandle, ResultsFuture)
+    {
+        let mut per_adapter: HashMap<Id<AdapterId>, Vec<(Id<Setter>, Value)>> = HashMap::new();
+        let mut sent_values = HashMap::new();
+        for (id, (adapter_id, value)) in self.coalesce_setter_values(keyvalues) {
+            sent_values.insert(id.clone(), value.clone());
+            per_adapter.entry(adapter_id).or_insert_with(Vec::new).push((id, value));
         }
-        let setters = &mut service.borrow_mut().setters;
-        let insert_in_service = match InsertInMap::start(setters, vec![(setter.id.clone(), setter.clone())]) {
-            Ok(transaction) => transaction,
-            Err(id) => return Err(Error::InternalError(InternalError::DuplicateSetter(id)))
-        };
-        let insert_in_setters = match InsertInMap::start(&mut self.setter_by_id, vec![(setter.id.clone(), SetterData::new(setter))]) {
-            Ok(transaction) => transaction,
-            Err(id) => return Err(Error::InternalError(InternalError::DuplicateSetter(id)))
-        };
-        insert_in_service.commit();
-        insert_in_setters.commit();
-        Ok(())
-    }
 
-    /// Remove a setter previously registered on the system. Typically, called by
-    /// an adapter when a service is reconfigured to remove one of its setters.
-    ///
-    /// # Error
-    ///
-    /// This method returns an error if the setter is not registered or if the service
-    /// is not registered. In either case, it attemps to clean as much as possible, even
-    /// if the state is inconsistent.
-    pub fn remove_setter(&mut self, id: &Id<Setter>) -> Result<(), Error> {
-        let setter = match self.setter_by_id.remove(id) {
-            None => return Err(Error::InternalError(InternalError::NoSuchSetter(id.clone()))),
-            Some(setter) => setter
-        };
-        match self.service_by_id.get_mut(&setter.setter.service) {
-            None => Err(Error::InternalError(InternalError::NoSuchService(setter.setter.service))),
-            Some(service) => {
-                if service.borrow_mut().setters.remove(id).is_none() {
-                    Err(Error::InternalError(InternalError::NoSuchSetter(id.clone())))
-                } else {
-                    Ok(())
-                }
+        let mut tokens = HashMap::new();
+        let mut pending = Vec::new();
+        for (adapter_id, payload) in per_adapter.drain() {
+            let adapter = match self.adapter_by_id.get(&adapter_id) {
+                None => continue, // That's an internal inconsistency. FIXME: Log this somewhere.
+                Some(adapter_data) => adapter_data.adapter.clone(),
+            };
+            let ids: Vec<_> = payload.iter().map(|&(ref id, _)| id.clone()).collect();
+            let mut batch_tokens = HashMap::new();
+            for id in &ids {
+                let token: JobToken = Arc::new(AtomicBool::new(false));
+                tokens.insert(id.clone(), token.clone());
+                batch_tokens.insert(id.clone(), token);
             }
+            let (tx, rx) = channel();
+            self.pool.submit(Box::new(move || {
+                let _ = tx.send(adapter.send_values(payload));
+            }));
+            pending.push((adapter_id, ids, rx, batch_tokens));
         }
-    }
-
-    pub fn get_services(&self, selectors: &[ServiceSelector]) -> Vec<Service> {
-        // This implementation is not nearly optimal, but it should be sufficient in a system
-        // with relatively few services.
-        let mut result = Vec::new();
-        self.with_services(selectors, |service| {
-            result.push(service.borrow().clone())
-        });
-        result
-    }
 
-    pub fn add_service_tags(&self, selectors: &[ServiceSelector], tags: &[Id<TagId>]) -> usize {
-        let mut result = 0;
-        self.with_services(selectors, |service| {
-            let tag_set = &mut service.borrow_mut().tags;
-            for tag in tags {
-                let _ = tag_set.insert(tag.clone());
+        let remaining = tokens.len();
+        let (result_tx, result_rx) = channel();
+        let effects = self.effects.clone();
+        thread::spawn(move || {
+            for (adapter_id, ids, rx, batch_tokens) in pending {
+                let results = resolve_cancellable_job(&adapter_id, &ids, &rx, &batch_tokens);
+                publish_sent_effects(&effects, &sent_values, &results);
+                for (id, result) in results {
+                    let _ = result_tx.send((id, result));
+                }
             }
-            result += 1;
         });
-        result
-    }
 
-    pub fn remove_service_tags(&self, selectors: &[ServiceSelector], tags: &[Id<TagId>]) -> usize {
-        let mut result = 0;
-        self.with_services(selectors, |service| {
-            let tag_set = &mut service.borrow_mut().tags;
-            for tag in tags {
-                let _ = tag_set.remove(tag);
-            }
-            result += 1;
-        });
-        result
+        (JobHandle { tokens: tokens }, ResultsFuture { rx: result_rx, remaining: remaining })
     }
 
-    pub fn get_getter_channels(&self, selectors: &[GetterSelector]) -> Vec<Channel<Getter>>
+    /// Publish an `Effect::ValueSent`/`Effect::ValueRejected` to `self.effects` for every `id` in
+    /// `results`, reporting `ValueSent` only where a value is also known in `sent_values`. Shared
+    /// by every `send_values*` call that resolves selectors to a plain `Id<Setter> -> Value`
+    /// mapping before dispatch.
+    fn publish_sent_effects<E: RejectionKindOf>(&self, sent_values: &HashMap<Id<Setter>, Value>,
+        results: &[(Id<Setter>, Result<(), E>)])
     {
-        Self::aux_get_channels(selectors, &self.getter_by_id)
+        publish_sent_effects(&self.effects, sent_values, results)
     }
-    pub fn get_setter_channels(&self, selectors: &[SetterSelector]) -> Vec<Channel<Setter>>
+
+    /// Like `send_values`, but all-or-nothing: every selector group is resolved to its setters
+    /// and every resulting `Value` is checked against its target's `ChannelKind` before anything
+    /// is dispatched, so a caller applying a multi-device scene either sees it fully applied or
+    /// gets back every reason it couldn't be, with nothing sent to any adapter either way. Use
+    /// `send_values` instead for the older best-effort semantics, where a well-typed entry is
+    /// still sent even if another entry in the same batch is ill-typed.
+    pub fn send_values_atomic(&self, keyvalues: Vec<(Vec<SetterSelector>, Value)>)
+        -> Result<ResultMap<Id<Setter>, (), Error>, AtomicSendRejection>
     {
-        Self::aux_get_channels(selectors, &self.setter_by_id)
-    }
+        // Coalesce last-writer-wins onto the setter id as `send_values` does (see
+        // `coalesce_setter_values`), but keep type-checking inline since an ill-typed entry must
+        // reject the whole batch rather than simply losing a coalescing race.
+        let mut coalesced: HashMap<Id<Setter>, (Id<AdapterId>, Value)> = HashMap::new();
+        let mut type_errors = HashMap::new();
+        let mut unmatched = Vec::new();
+
+        for (selectors, value) in keyvalues {
+            let mut matched_any = false;
+            Self::with_channels(&selectors, &self.setter_by_id, &self.setter_by_tag, |data| {
+                matched_any = true;
+                let expected = data.setter.mechanism.kind.get_type();
+                let got = value.get_type();
+                if got == expected {
+                    coalesced.insert(data.setter.id.clone(), (data.setter.adapter.clone(), value.clone()));
+                } else {
+                    type_errors.insert(data.setter.id.clone(), TypeError { got: got, expected: expected });
+                }
+            });
+            if !matched_any {
+                unmatched.push(selectors);
+            }
+        }
 
+        if !type_errors.is_empty() || !unmatched.is_empty() {
+            return Err(AtomicSendRejection { type_errors: type_errors, unmatched: unmatched });
+        }
 
-    pub fn add_getter_tags(&mut self, selectors: &[GetterSelector], tags: &[Id<TagId>]) -> usize {
-        Self::aux_add_channel_tags(selectors, tags, &mut self.getter_by_id)
-    }
-    pub fn add_setter_tags(&mut self, selectors: &[SetterSelector], tags: &[Id<TagId>]) -> usize {
-        Self::aux_add_channel_tags(selectors, tags, &mut self.setter_by_id)
+        let mut per_adapter: HashMap<Id<AdapterId>, Vec<(Id<Setter>, Value)>> = HashMap::new();
+        let mut sent_values = HashMap::new();
+        for (id, (adapter_id, value)) in coalesced {
+            sent_values.insert(id.clone(), value.clone());
+            per_adapter.entry(adapter_id).or_insert_with(Vec::new).push((id, value));
+        }
+
+        // Every target validated: dispatch exactly as `send_values` does, one job per adapter
+        // submitted to the worker pool so a slow adapter doesn't stall the others.
+        let mut pending = Vec::new();
+        for (adapter_id, payload) in per_adapter {
+            let adapter = match self.adapter_by_id.get(&adapter_id) {
+                None => continue, // That's an internal inconsistency. FIXME: Log this somewhere.
+                Some(adapter_data) => adapter_data.adapter.clone(),
+            };
+            let ids: Vec<_> = payload.iter().map(|&(ref id, _)| id.clone()).collect();
+            let (tx, rx) = channel();
+            self.pool.submit(Box::new(move || {
+                let _ = tx.send(adapter.send_values(payload));
+            }));
+            pending.push((adapter_id, ids, rx));
+        }
+
+        let mut results = Vec::new();
+        for (adapter_id, ids, rx) in pending {
+            match rx.recv() {
+                Ok(mut got) => results.append(&mut got),
+                Err(_) => {
+                    // The worker thread handling this adapter never replied - most likely it
+                    // panicked mid-send. Every setter submitted to it still gets a deterministic
+                    // result instead of silently vanishing from the result map.
+                    results.extend(ids.into_iter()
+                        .map(|id| (id, Err(Error::InternalError(InternalError::NoSuchAdapter(adapter_id.clone()))))));
+                }
+            }
+        }
+
+        self.publish_sent_effects(&sent_values, &results);
+        Ok(results)
     }
-    pub fn remove_getter_tags(&mut self, selectors: &[GetterSelector], tags: &[Id<TagId>]) -> usize {
-        Self::aux_remove_channel_tags(selectors, tags, &mut self.getter_by_id)
+
+    /// Register a logical channel backed by `backing`, acknowledged once at least `quorum` of
+    /// those setters accept a write. See `LogicalChannel`/`send_to_logical_channel`.
+    pub fn add_logical_channel(&mut self, id: Id<LogicalChannelId>, backing: Vec<Id<Setter>>, quorum: usize)
+        -> Result<(), LogicalChannelError>
+    {
+        if quorum == 0 || quorum > backing.len() {
+            return Err(LogicalChannelError::InvalidQuorum { quorum: quorum, backing: backing.len() });
+        }
+        for setter_id in &backing {
+            if !self.setter_by_id.contains_key(setter_id) {
+                return Err(LogicalChannelError::NoSuchSetter(setter_id.clone()));
+            }
+        }
+        use std::collections::hash_map::Entry::*;
+        match self.logical_channels.entry(id.clone()) {
+            Occupied(_) => Err(LogicalChannelError::DuplicateChannel(id)),
+            Vacant(entry) => {
+                entry.insert(LogicalChannel { id: id, backing: backing, quorum: quorum });
+                Ok(())
+            }
+        }
     }
-    pub fn remove_setter_tags(&mut self, selectors: &[SetterSelector], tags: &[Id<TagId>]) -> usize {
-        Self::aux_remove_channel_tags(selectors, tags, &mut self.setter_by_id)
+
+    /// Fan `value` out to every setter backing the logical channel `id`, and report success once
+    /// at least its quorum of them acknowledge; otherwise an aggregated `QuorumError::QuorumFailed`
+    /// lists every backing setter's individual result. If the backing setters do not agree on a
+    /// `Type`, the value is never sent to any of them and `QuorumError::Diverged` is returned
+    /// instead - there is no single value that could be valid for all of them.
+    ///
+    /// `foxbox_taxonomy::selector::SetterSelector` has no notion of a channel backed by more
+    /// than one physical setter, so this is a dedicated method rather than a new case
+    /// `send_values` itself dispatches to - there is no selector a caller could use to reach a
+    /// `LogicalChannel` in the first place.
+    pub fn send_to_logical_channel(&self, id: &Id<LogicalChannelId>, value: Value) -> Result<(), QuorumError> {
+        let logical_channel = match self.logical_channels.get(id) {
+            None => return Err(QuorumError::NoSuchChannel(id.clone())),
+            Some(logical_channel) => logical_channel,
+        };
+
+        // Detect divergence before sending anything to any adapter.
+        let mut expected: Option<Type> = None;
+        for setter_id in &logical_channel.backing {
+            if let Some(data) = self.setter_by_id.get(setter_id) {
+                let kind = data.setter.mechanism.kind.get_type();
+                match expected {
+                    None => expected = Some(kind),
+                    Some(ref first) if *first == kind => {},
+                    Some(ref first) => {
+                        return Err(QuorumError::Diverged(TypeError { got: kind, expected: first.clone() }));
+                    }
+                }
+            }
+        }
+
+        // Dispatch to adapter, grouping backing setters by adapter as `send_values` does.
+        let mut per_adapter: HashMap<Id<AdapterId>, Vec<(Id<Setter>, Value)>> = HashMap::new();
+        let mut missing = Vec::new();
+        for setter_id in &logical_channel.backing {
+            match self.setter_by_id.get(setter_id) {
+                None => missing.push(setter_id.clone()),
+                Some(data) => {
+                    per_adapter.entry(data.setter.adapter.clone()).or_insert_with(Vec::new)
+                        .push((setter_id.clone(), value.clone()));
+                }
+            }
+        }
+
+        let mut pending = Vec::new();
+        for (adapter_id, payload) in per_adapter {
+            let adapter = match self.adapter_by_id.get(&adapter_id) {
+                None => continue, // That's an internal inconsistency. FIXME: Log this somewhere.
+                Some(adapter_data) => adapter_data.adapter.clone(),
+            };
+            let ids: Vec<_> = payload.iter().map(|&(ref id, _)| id.clone()).collect();
+            let (tx, rx) = channel();
+            self.pool.submit(Box::new(move || {
+                let _ = tx.send(adapter.send_values(payload));
+            }));
+            pending.push((adapter_id, ids, rx));
+        }
+
+        let mut results = Vec::new();
+        for (adapter_id, ids, rx) in pending {
+            match rx.recv() {
+                Ok(mut got) => results.append(&mut got),
+                Err(_) => {
+                    // The worker thread handling this adapter never replied - most likely it
+                    // panicked mid-send. Every setter submitted to it still gets a deterministic
+                    // result instead of silently vanishing from the result map.
+                    results.extend(ids.into_iter()
+                        .map(|id| (id, Err(Error::InternalError(InternalError::NoSuchAdapter(adapter_id.clone()))))));
+                }
+            }
+        }
+        results.extend(missing.into_iter()
+            .map(|id| (id.clone(), Err(Error::InternalError(InternalError::NoSuchSetter(id))))));
+
+        let sent_values: HashMap<_, _> = logical_channel.backing.iter()
+            .map(|id| (id.clone(), value.clone())).collect();
+        self.publish_sent_effects(&sent_values, &results);
+
+        let acked = results.iter().filter(|&&(_, ref result)| result.is_ok()).count();
+        let quorum = logical_channel.quorum;
+        if acked >= quorum {
+            Ok(())
+        } else {
+            Err(QuorumError::QuorumFailed { quorum: quorum, acked: acked, results: results })
+        }
     }
 
-    /// Read the latest value from a set of channels
-    pub fn fetch_values(&mut self, selectors: &[GetterSelector]) -> ResultSet<Id<Getter>, Option<Value>, Error> {
-        // First group per adapter, so as to let adapters optimize fetches.
+    /// Like `fetch_values`, but abandons any adapter that has not replied by the time `timeout`
+    /// elapses: every channel it owns is reported as `Err(TimeoutError::Timeout)` instead of
+    /// blocking the whole batch on it. See `TimeoutError`.
+    pub fn fetch_values_with_timeout(&mut self, selectors: &[GetterSelector], timeout: Duration)
+        -> ResultSet<Id<Getter>, Option<Value>, TimeoutError>
+    {
         let mut per_adapter = HashMap::new();
-        Self::with_channels(selectors, &self.getter_by_id, |data| {
+        Self::with_channels(selectors, &self.getter_by_id, &self.getter_by_tag, |data| {
             use std::collections::hash_map::Entry::*;
             match per_adapter.entry(data.getter.adapter.clone()) {
                 Vacant(entry) => {
@@ -689,71 +2789,420 @@ impl AdapterManagerState {
             }
         });
 
-        // Now fetch the values
-        let mut results = vec![];
+        let mut pending = Vec::new();
         for (adapter_id, getters) in per_adapter {
-            match self.adapter_by_id.get(&adapter_id) {
-                None => {}, // Internal inconsistency. FIXME: Log this somewhere.
-                Some(ref adapter_data) => {
-                    let mut got = adapter_data
-                        .adapter
-                        .fetch_values(getters);
+            let adapter = match self.adapter_by_id.get(&adapter_id) {
+                None => continue, // Internal inconsistency. FIXME: Log this somewhere.
+                Some(adapter_data) => adapter_data.adapter.clone(),
+            };
+            let timed_out = getters.clone();
+            let (tx, rx) = channel();
+            self.pool.submit(Box::new(move || {
+                let _ = tx.send(adapter.fetch_values(getters));
+            }));
+            pending.push((timed_out, rx));
+        }
 
-                    results.append(&mut got);
+        let mut results = vec![];
+        for (timed_out, rx) in pending {
+            match rx.recv_timeout(timeout) {
+                Ok(got) => {
+                    for &(ref id, ref result) in &got {
+                        if let Ok(ref value) = *result {
+                            self.last_known.insert(id.clone(), CachedValue {
+                                value: value.clone(),
+                                timestamp: SystemTime::now(),
+                                seeded: false,
+                            });
+                        }
+                    }
+                    results.extend(got.into_iter().map(|(id, result)| (id, result.map_err(TimeoutError::Inner))));
+                }
+                Err(_) => {
+                    // Either the deadline elapsed or the worker thread handling this adapter
+                    // panicked before replying; `rx` is dropped here either way, so a reply sent
+                    // afterwards is simply discarded.
+                    results.extend(timed_out.into_iter().map(|id| (id, Err(TimeoutError::Timeout))));
                 }
             }
         }
         results
     }
 
-    /// Send values to a set of channels
-    pub fn send_values(&self, mut keyvalues: Vec<(Vec<SetterSelector>, Value)>) -> ResultMap<Id<Setter>, (), Error> {
+    /// Like `send_values`, but abandons any adapter that has not replied by the time `timeout`
+    /// elapses: every channel it owns is reported as `Err(TimeoutError::Timeout)` instead of
+    /// blocking the whole batch on it. See `TimeoutError`.
+    pub fn send_values_with_timeout(&self, keyvalues: Vec<(Vec<SetterSelector>, Value)>, timeout: Duration)
+        -> ResultMap<Id<Setter>, (), TimeoutError>
+    {
+        let mut per_adapter: HashMap<Id<AdapterId>, Vec<(Id<Setter>, Value)>> = HashMap::new();
+        let mut sent_values = HashMap::new();
+        for (id, (adapter_id, value)) in self.coalesce_setter_values(keyvalues) {
+            sent_values.insert(id.clone(), value.clone());
+            per_adapter.entry(adapter_id).or_insert_with(Vec::new).push((id, value));
+        }
+
+        let mut pending = Vec::new();
+        for (adapter_id, payload) in per_adapter.drain() {
+            let adapter = match self.adapter_by_id.get(&adapter_id) {
+                None => continue, // That's an internal inconsistency. FIXME: Log this somewhere.
+                Some(adapter_data) => adapter_data.adapter.clone(),
+            };
+            let timed_out: Vec<_> = payload.iter().map(|&(ref id, _)| id.clone()).collect();
+            let (tx, rx) = channel();
+            self.pool.submit(Box::new(move || {
+                let _ = tx.send(adapter.send_values(payload));
+            }));
+            pending.push((timed_out, rx));
+        }
+
+        let mut results = Vec::new();
+        for (timed_out, rx) in pending {
+            match rx.recv_timeout(timeout) {
+                Ok(got) => {
+                    results.extend(got.into_iter().map(|(id, result)| (id, result.map_err(TimeoutError::Inner))));
+                }
+                Err(_) => {
+                    // Either the deadline elapsed or the worker thread handling this adapter
+                    // panicked before replying; `rx` is dropped here either way, so a reply sent
+                    // afterwards is simply discarded.
+                    results.extend(timed_out.into_iter().map(|id| (id, Err(TimeoutError::Timeout))));
+                }
+            }
+        }
+
+        self.publish_sent_effects(&sent_values, &results);
+        results
+    }
+
+    /// Like `send_values`, but also reports staged progress through `on_event` for every setter
+    /// accepted: `SetterVerification::Accepted` as soon as its selector resolves, `Started` once
+    /// it is handed to its adapter, and exactly one `Completed` once it reaches a terminal
+    /// outcome - even if the worker thread handling its adapter never replies (most likely
+    /// because the adapter panicked, or was removed from the registry mid-flight), in which case
+    /// `Completed` reports `InternalError::NoSuchAdapter` rather than leaving the setter hanging
+    /// forever. All three events for a given call share the same `RequestId`, so a caller with
+    /// several concurrent calls to the same setter can tell their events apart. See
+    /// `SetterVerification`.
+    pub fn send_values_verified(&mut self, keyvalues: Vec<(Vec<SetterSelector>, Value)>,
+        on_event: Box<ExtSender<SetterVerification> + Send>) -> ResultMap<Id<Setter>, (), Arc<Error>>
+    {
+        let request = self.next_request_id();
+
+        let mut per_adapter: HashMap<Id<AdapterId>, Vec<(Id<Setter>, Value)>> = HashMap::new();
+        let mut sent_values = HashMap::new();
+        for (id, (adapter_id, value)) in self.coalesce_setter_values(keyvalues) {
+            sent_values.insert(id.clone(), value.clone());
+            per_adapter.entry(adapter_id).or_insert_with(Vec::new).push((id, value));
+        }
+
+        for payload in per_adapter.values() {
+            for &(ref id, _) in payload {
+                let _ = on_event.send(SetterVerification::Accepted { request: request, setter: id.clone() });
+            }
+        }
+
+        let mut results = Vec::new();
+        let mut pending = Vec::new();
+        for (adapter_id, payload) in per_adapter.drain() {
+            let adapter = match self.adapter_by_id.get(&adapter_id) {
+                None => {
+                    // Internal inconsistency (matches `send_values`'s own handling), but every
+                    // `Accepted` setter here still needs its guaranteed terminal event.
+                    for (id, _) in payload {
+                        let result: Result<(), Arc<Error>> =
+                            Err(Arc::new(Error::InternalError(InternalError::NoSuchAdapter(adapter_id.clone()))));
+                        let _ = on_event.send(SetterVerification::Completed {
+                            request: request, setter: id.clone(), result: result.clone(),
+                        });
+                        results.push((id, result));
+                    }
+                    continue;
+                }
+                Some(adapter_data) => adapter_data.adapter.clone(),
+            };
+
+            for &(ref id, _) in &payload {
+                let _ = on_event.send(SetterVerification::Started { request: request, setter: id.clone() });
+            }
+
+            let ids: Vec<_> = payload.iter().map(|&(ref id, _)| id.clone()).collect();
+            let (tx, rx) = channel();
+            self.pool.submit(Box::new(move || {
+                let _ = tx.send(adapter.send_values(payload));
+            }));
+            pending.push((adapter_id, ids, rx));
+        }
+
+        for (adapter_id, ids, rx) in pending {
+            match rx.recv() {
+                Ok(got) => {
+                    for (id, result) in got {
+                        let result = result.map_err(Arc::new);
+                        let _ = on_event.send(SetterVerification::Completed {
+                            request: request, setter: id.clone(), result: result.clone(),
+                        });
+                        results.push((id, result));
+                    }
+                }
+                Err(_) => {
+                    // The worker thread handling this adapter never replied - most likely it
+                    // panicked, or the adapter was removed from the registry mid-flight. Either
+                    // way, every setter already marked `Started` for this job still gets its
+                    // guaranteed terminal event.
+                    for id in ids {
+                        let result: Result<(), Arc<Error>> =
+                            Err(Arc::new(Error::InternalError(InternalError::NoSuchAdapter(adapter_id.clone()))));
+                        let _ = on_event.send(SetterVerification::Completed {
+                            request: request, setter: id.clone(), result: result.clone(),
+                        });
+                        results.push((id, result));
+                    }
+                }
+            }
+        }
+
+        self.publish_sent_effects(&sent_values, &results);
+        results
+    }
+
+    /// Send values to a set of channels, honoring each entry's `Precondition` (see
+    /// `Adapter::send_values_checked`). A precondition is checked against the last value this
+    /// method itself wrote to the setter (plain `send_values` does not update that record), and
+    /// any entry whose precondition fails is reported as
+    /// `ConditionalWriteError::PreconditionFailed` without ever reaching the adapter. The
+    /// remaining entries (including those with no precondition) are forwarded to
+    /// `Adapter::send_values_checked`, so an adapter able to check and apply atomically still
+    /// gets a chance to do so against its own view of the setter.
+    pub fn send_values_checked(&mut self,
+        mut keyvalues: Vec<(Vec<SetterSelector>, Value, Option<Precondition>)>)
+        -> ResultMap<Id<Setter>, (), ConditionalWriteError>
+    {
         // First determine the channels and group them by adapter.
         let mut per_adapter = HashMap::new();
-        for (selectors, value) in keyvalues.drain(..) {
-            Self::with_channels(&selectors, &self.setter_by_id, |data| {
+        for (selectors, value, precondition) in keyvalues.drain(..) {
+            Self::with_channels(&selectors, &self.setter_by_id, &self.setter_by_tag, |data| {
                 use std::collections::hash_map::Entry::*;
+                let entry = (data.setter.id.clone(), value.clone(), precondition.clone());
                 match per_adapter.entry(data.setter.adapter.clone()) {
-                    Vacant(entry) => {
-                        entry.insert(vec![(data.setter.id.clone(), value.clone())]);
+                    Vacant(slot) => {
+                        slot.insert(vec![entry]);
                     }
-                    Occupied(mut entry) => {
-                        entry.get_mut().push((data.setter.id.clone(), value.clone()));
+                    Occupied(mut slot) => {
+                        slot.get_mut().push(entry);
                     }
                 }
             })
         }
 
-
-        // Dispatch to adapter
+        // Reject, without ever reaching an adapter, any entry whose precondition already fails
+        // against our own record of the last value we wrote to that setter.
         let mut results = Vec::new();
+        for payload in per_adapter.values_mut() {
+            for index in (0 .. payload.len()).rev() {
+                let holds = match payload[index].2 {
+                    Some(ref precondition) => {
+                        let current = self.last_known_setter.get(&payload[index].0).cloned();
+                        Self::precondition_holds(precondition, &current)
+                    }
+                    None => true,
+                };
+                if !holds {
+                    let (id, _, _) = payload.remove(index);
+                    let current = self.last_known_setter.get(&id).cloned();
+                    results.push((id, Err(ConditionalWriteError::PreconditionFailed { current: current })));
+                }
+            }
+        }
+
+        // Dispatch the remaining entries to their adapter.
         for (adapter_id, payload) in per_adapter.drain() {
             let adapter = match self.adapter_by_id.get(&adapter_id) {
                 None => continue, // That's an internal inconsistency. FIXME: Log this somewhere.
                 Some(adapter) => adapter
             };
-            let mut got = adapter.adapter.send_values(payload);
+            let values: HashMap<_, _> = payload.iter()
+                .map(|&(ref id, ref value, _)| (id.clone(), value.clone()))
+                .collect();
+            let mut got = adapter.adapter.send_values_checked(payload);
+            for &(ref id, ref result) in &got {
+                match *result {
+                    Ok(()) => {
+                        if let Some(value) = values.get(id) {
+                            self.last_known_setter.insert(id.clone(), value.clone());
+                            self.effects.publish(Effect::ValueSent(id.clone(), value.clone()));
+                        }
+                    }
+                    Err(ref err) => self.effects.publish(Effect::ValueRejected(id.clone(), err.rejection_kind())),
+                }
+            }
             results.append(&mut got);
         }
 
         results
     }
 
+    /// Whether `current` (the last value this backend recorded for a setter, if any) satisfies
+    /// `precondition`. See `send_values_checked`.
+    fn precondition_holds(precondition: &Precondition, current: &Option<Value>) -> bool {
+        match *precondition {
+            Precondition::IfEqual(ref expected) => current.as_ref() == Some(expected),
+            Precondition::IfRangeMatches(ref range) =>
+                current.as_ref().map_or(false, |value| range.contains(value)),
+            Precondition::IfUnset => current.is_none(),
+        }
+    }
+
+    /// Send structured partial updates to a set of channels (see `UpdateKind`), by resolving
+    /// each entry to a materialized `Value` and dispatching it down the same per-adapter path
+    /// as `send_values`.
+    ///
+    /// `UpdateKind::Merge`/`UpdateKind::Patch` need a generic, structured view of the setter's
+    /// current value to overlay or patch against, which `foxbox_taxonomy::values::Value` does
+    /// not provide (it is a closed set of concrete, typed variants, not a composite record), so
+    /// those entries are rejected with `UpdateError::UnsupportedUpdate` rather than silently
+    /// behaving like `Replace`. Only `UpdateKind::Replace` is actually materialized today.
+    pub fn send_values_updated(&mut self, mut keyvalues: Vec<(Vec<SetterSelector>, UpdateKind)>)
+        -> ResultMap<Id<Setter>, (), UpdateError>
+    {
+        let mut replacements = Vec::new();
+        let mut results = Vec::new();
+        for (selectors, update) in keyvalues.drain(..) {
+            match update {
+                UpdateKind::Replace(value) => replacements.push((selectors, value)),
+                UpdateKind::Merge(_) | UpdateKind::Patch(_) => {
+                    Self::with_channels(&selectors, &self.setter_by_id, &self.setter_by_tag, |data| {
+                        let err = UpdateError::UnsupportedUpdate;
+                        self.effects.publish(Effect::ValueRejected(data.setter.id.clone(), err.rejection_kind()));
+                        results.push((data.setter.id.clone(), Err(err)));
+                    })
+                }
+            }
+        }
+
+        let mut got = self.send_values(replacements).into_iter()
+            .map(|(id, result)| (id, result.map_err(UpdateError::Inner)))
+            .collect();
+        results.append(&mut got);
+        results
+    }
+
+    /// Fetch and deliver the current value of every getter in `watch` that satisfies its
+    /// filter, as `StreamEvent::Value(WatchEvent::EnterRange { .. })`, then
+    /// `StreamEvent::SnapshotDone`. Runs under the same lock as the live-watch attachment that
+    /// follows it in `register_channel_watch`, so no change to a getter's value can be lost or
+    /// double-delivered across the snapshot/subscribe boundary.
+    fn snapshot_channel_watch(&mut self, watch: &[(Vec<GetterSelector>, Exactly<Range>)],
+        on_event: &Box<Fn(StreamEvent) + Send>)
+    {
+        for &(ref selectors, ref filter) in watch {
+            let range = match *filter {
+                Exactly::Exactly(ref range) => Some(range.clone()),
+                Exactly::Always => None,
+                _ => continue, // Don't snapshot topology-only watches.
+            };
+            for (id, result) in self.fetch_values(selectors) {
+                let value = match result {
+                    Ok(Some(value)) => value,
+                    _ => continue,
+                };
+                let matches = match range {
+                    Some(ref range) => range.contains(&value),
+                    None => true,
+                };
+                if matches {
+                    on_event(StreamEvent::Value(WatchEvent::EnterRange { from: id, value: value }));
+                }
+            }
+        }
+        on_event(StreamEvent::SnapshotDone);
+    }
+
+    /// Register a watch for value changes, as per `API::register_channel_watch`.
+    ///
+    /// `mode` chooses what is delivered relative to each matching getter's current value, see
+    /// `StreamMode`.
+    ///
+    /// `debounce` lets the caller hold a chatty getter's events for a quiet interval before
+    /// delivering only the settled state: an `EnterRange` immediately followed (within the
+    /// window) by an `ExitRange` for the same getter (or vice-versa) cancels out and nothing
+    /// is emitted. A `debounce` of zero preserves the previous, undebounced behavior.
+    ///
+    /// `max_pending` bounds the number of events buffered for the consumer: once exceeded,
+    /// the watch is evicted (as though its `WatchGuard` had been dropped) and `on_lagged`
+    /// fires once with the number of events that had to be dropped to detect the overflow.
+    /// `None` preserves the previous, unbounded behavior. Ignored if `buffer` is `Some`.
+    ///
+    /// `buffer`, if supplied, replaces the `max_pending`/`on_lagged` hard eviction with a
+    /// memory-bounded ring buffer (see `WatchBufferBudget`): once the budget is exceeded, the
+    /// oldest buffered data event is dropped to make room for the newest rather than evicting
+    /// the whole watch, and the attached callback fires once the buffer next drains, with the
+    /// number of events lost.
+    ///
+    /// `owner` is the shared state used to unregister the watch on eviction.
+    pub fn register_channel_watch(&mut self, mut watch: Vec<(Vec<GetterSelector>, Exactly<Range>)>,
+        on_event: Box<Fn(StreamEvent) + Send>, mode: StreamMode, debounce: Duration,
+        max_pending: Option<usize>, on_lagged: Box<Fn(WatchQueueLagged) + Send>,
+        buffer: Option<(WatchBufferBudget, Box<Fn(WatchBufferDropped) + Send>)>,
+        owner: Arc<Mutex<AdapterManagerState>>)
+        -> (Sender<WatchEvent>, usize, Arc<AtomicBool>)
+    {
+        match mode {
+            StreamMode::Snapshot | StreamMode::SnapshotThenSubscribe => {
+                self.snapshot_channel_watch(&watch, &on_event);
+            }
+            StreamMode::Subscribe => {}
+        }
 
-    pub fn register_channel_watch(&mut self, mut watch: Vec<(Vec<GetterSelector>, Exactly<Range>)>, on_event: Box<Fn(WatchEvent) + Send>) -> (Sender<WatchEvent>, usize, Arc<AtomicBool>) {
         let (tx, rx) = channel();
+        let is_dropped = Arc::new(AtomicBool::new(false));
+        let key = self.watchers.lock().unwrap().reserve_key();
+
+        let final_sink = match buffer {
+            Some((budget, on_dropped)) => {
+                FinalSink::RingBuffered(RingBufferedSender::new(tx.clone(), budget, on_dropped))
+            }
+            None => {
+                FinalSink::Guarded(GuardedSender {
+                    tx: tx.clone(),
+                    pending: Arc::new(AtomicUsize::new(0)),
+                    max_pending: max_pending,
+                    is_dropped: is_dropped.clone(),
+                    lagged: Arc::new(AtomicBool::new(false)),
+                    on_lagged: Arc::new(on_lagged),
+                    owner: owner,
+                    key: key,
+                })
+            }
+        };
+        let delivery = final_sink.clone();
         thread::spawn(move || {
             // This thread will be destroyed when we drop `tx`, i.e. when we drop `watcher`
             for msg in rx {
-                on_event(msg)
+                on_event(StreamEvent::Value(msg));
+                delivery.mark_delivered();
             }
         });
-        // Store the watcher. This will serve when we new channels are added, to hook them up
-        // to this watcher.
-        let is_dropped = Arc::new(AtomicBool::new(false));
-        let watcher = self.watchers.lock().unwrap().create(watch.clone(),
-            &is_dropped, tx.clone());
-        let key = watcher.key;
+        let sink = DebouncedSink::new(final_sink, debounce);
+
+        // Built only now that `sink` exists: every event this watcher ever receives - including
+        // the out-of-band ones below, `InitializationError` and `attach_matching_watchers` - goes
+        // through it, so the `pending`/`in-flight` bookkeeping `sink` tracks never sees a message
+        // that bypassed it. See the `sink` field on `WatcherData`.
+        // A snapshot-only watcher is registered with no selectors, so `attach_matching_watchers`
+        // never attaches a getter added later to it.
+        let registered_watch = match mode {
+            StreamMode::Snapshot => Vec::new(),
+            _ => watch.clone(),
+        };
+        let watcher = Arc::new(WatcherData::new(key, registered_watch, &is_dropped, sink.clone()));
+        self.watchers.lock().unwrap().insert(key, watcher.clone());
+
+        if let StreamMode::Snapshot = mode {
+            // Nothing else to do: the snapshot above is the whole of what was requested, no
+            // live watch is attached and `watch` is simply left to be dropped.
+            return (tx, key, is_dropped);
+        }
 
         // Regroup per adapter.
         let mut per_adapter = HashMap::new();
@@ -762,7 +3211,7 @@ impl AdapterManagerState {
             // Find out which channels already match the selectors and attach
             // the watcher immediately.
             let filter = &filter;
-            Self::with_channels_mut(&selectors, &mut self.getter_by_id, |mut getter_data| {
+            Self::with_channels_mut(&selectors, &mut self.getter_by_id, &self.getter_by_tag, |mut getter_data| {
                 use std::collections::hash_map::Entry::*;
                 getter_data.watchers.insert(watcher.clone());
                 watcher.push_getter(&getter_data.id);
@@ -796,8 +3245,7 @@ impl AdapterManagerState {
             };
 
             let is_dropped = is_dropped.clone();
-            let tx = tx.clone();
-            let tx_err = tx.clone();
+            let sink = sink.clone();
             let cb = move |event| {
                 if is_dropped.load(Ordering::Relaxed) {
                     return;
@@ -814,7 +3262,7 @@ impl AdapterManagerState {
                             value: value
                         },
                 };
-                let _ = tx.send(event);
+                sink.send(event);
             };
 
             let watcher = watcher.clone();
@@ -825,9 +3273,9 @@ impl AdapterManagerState {
                             channel: id.clone(),
                             error: err
                         };
-                        let _ = tx_err.send(event);
+                        watcher.notify(event);
                     },
-                    Ok(guard) => watcher.push_guard(guard)
+                    Ok(guard) => watcher.push_guard(id.clone(), guard)
                 }
             }
         }
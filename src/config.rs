@@ -0,0 +1,290 @@
+//! Hot-reloadable TOML configuration for adapters and tags.
+//!
+//! `AdapterManager` otherwise has no declarative way to say "service X always has tag
+//! `room:kitchen`" — every tag has to be pushed imperatively through `add_service_tags` and
+//! friends, typically from a UI or a one-off script. A `ConfigStore` loads a TOML file such as
+//!
+//! ```toml
+//! [services.my-service-id]
+//! tags = ["room:kitchen"]
+//!
+//! [getters.my-getter-id]
+//! tags = ["display:false"]
+//!
+//! [adapters.my-adapter-id]
+//! poll_interval_ms = "5000"
+//! ```
+//!
+//! applies the `services`/`getters`/`setters` tables as tags through the existing
+//! `add_*_tags`/`remove_*_tags` calls, and exposes the `adapters` table as free-form per-adapter
+//! options for adapters to consult. `watch()` then polls the file for edits and, once it has
+//! settled (stopped changing for `settle_delay`, to coalesce a multi-write save), re-reads it
+//! and converges the live tags to match with the minimal set of adds/removes rather than
+//! blindly re-applying everything.
+
+use backend::AdapterManagerState;
+
+use foxbox_taxonomy::selector::{ GetterSelector, ServiceSelector, SetterSelector };
+use foxbox_taxonomy::util::Id;
+
+use std::collections::{ HashMap, HashSet };
+use std::fs::File;
+use std::io::{ self, Read };
+use std::path::PathBuf;
+use std::sync::{ Arc, Mutex };
+use std::sync::atomic::{ AtomicBool, Ordering };
+use std::thread;
+use std::time::{ Duration, SystemTime };
+
+/// The tags and per-adapter options parsed from a config file. Any id not mentioned in a
+/// section is simply left alone: this is a set of constraints to converge towards, not a full
+/// description of the system.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Config {
+    pub services: HashMap<String, HashSet<String>>,
+    pub getters: HashMap<String, HashSet<String>>,
+    pub setters: HashMap<String, HashSet<String>>,
+    pub adapters: HashMap<String, HashMap<String, String>>,
+}
+
+impl Config {
+    /// Parse a TOML document into a `Config`. Unknown top-level keys are ignored, so that a
+    /// file can be shared with other tools without upsetting this parser.
+    pub fn parse(text: &str) -> Result<Config, String> {
+        let value = try!(text.parse::<toml::Value>().map_err(|err| format!("{}", err)));
+        let table = match value.as_table() {
+            Some(table) => table,
+            None => return Err("expected a TOML table at the top level".to_owned()),
+        };
+        Ok(Config {
+            services: read_tags_section(table, "services"),
+            getters: read_tags_section(table, "getters"),
+            setters: read_tags_section(table, "setters"),
+            adapters: read_adapters_section(table, "adapters"),
+        })
+    }
+}
+
+fn read_tags_section(table: &toml::value::Table, section: &str) -> HashMap<String, HashSet<String>> {
+    let mut out = HashMap::new();
+    let section = match table.get(section).and_then(|value| value.as_table()) {
+        Some(section) => section,
+        None => return out,
+    };
+    for (id, entry) in section {
+        let tags = match entry.get("tags").and_then(|value| value.as_array()) {
+            Some(tags) => tags,
+            None => continue,
+        };
+        let tags = tags.iter().filter_map(|tag| tag.as_str()).map(|tag| tag.to_owned()).collect();
+        out.insert(id.clone(), tags);
+    }
+    out
+}
+
+fn read_adapters_section(table: &toml::value::Table, section: &str) -> HashMap<String, HashMap<String, String>> {
+    let mut out = HashMap::new();
+    let section = match table.get(section).and_then(|value| value.as_table()) {
+        Some(section) => section,
+        None => return out,
+    };
+    for (id, entry) in section {
+        let options = match entry.as_table() {
+            Some(options) => options,
+            None => continue,
+        };
+        let options = options.iter()
+            .map(|(key, value)| (key.clone(), value.to_string()))
+            .collect();
+        out.insert(id.clone(), options);
+    }
+    out
+}
+
+/// A witness that a config file is being watched for changes. Dropping it stops the background
+/// polling thread; it does not affect tags already applied.
+pub struct ConfigWatchGuard {
+    stop: Arc<AtomicBool>,
+}
+impl Drop for ConfigWatchGuard {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Loads a TOML config file, applies it to an `AdapterManagerState`, and watches it for edits.
+pub struct ConfigStore {
+    path: PathBuf,
+
+    /// The config last successfully applied, used as the baseline for `reload`'s diff. `None`
+    /// until `load` has run once.
+    current: Mutex<Option<Config>>,
+}
+
+impl ConfigStore {
+    pub fn new(path: PathBuf) -> Self {
+        ConfigStore {
+            path: path,
+            current: Mutex::new(None),
+        }
+    }
+
+    /// Read `self.path`, apply every tag it lists to `state`, and remember it as the baseline
+    /// for the next `reload`. Called once at startup, before `watch` is attached.
+    pub fn load(&self, state: &mut AdapterManagerState) -> io::Result<()> {
+        let config = try!(self.read());
+        apply(state, &config);
+        *self.current.lock().unwrap() = Some(config);
+        Ok(())
+    }
+
+    /// Re-read `self.path` and converge `state`'s tags to match, emitting only the
+    /// `add_*_tags`/`remove_*_tags` calls needed to get from the last applied config to this
+    /// one. If `load` has not run yet, this behaves like `load`.
+    pub fn reload(&self, state: &mut AdapterManagerState) -> io::Result<()> {
+        let next = try!(self.read());
+        let mut current = self.current.lock().unwrap();
+        match *current {
+            Some(ref previous) => converge(state, previous, &next),
+            None => apply(state, &next),
+        }
+        *current = Some(next);
+        Ok(())
+    }
+
+    fn read(&self) -> io::Result<Config> {
+        let mut text = String::new();
+        try!(try!(File::open(&self.path)).read_to_string(&mut text));
+        Config::parse(&text).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    fn modified(&self) -> Option<SystemTime> {
+        self.path.metadata().ok().and_then(|metadata| metadata.modified().ok())
+    }
+
+    /// Poll `self.path` every `poll_interval`, and once it has gone `settle_delay` without a
+    /// further change, `reload` it into `state`. Coalesces a burst of writes (e.g. an editor's
+    /// save-to-temp-then-rename) into a single reload instead of reacting to every write.
+    pub fn watch(self: Arc<Self>, state: Arc<Mutex<AdapterManagerState>>, poll_interval: Duration,
+        settle_delay: Duration) -> ConfigWatchGuard
+    {
+        let stop = Arc::new(AtomicBool::new(false));
+        let should_stop = stop.clone();
+        let store = self;
+        thread::spawn(move || {
+            let mut last_modified = store.modified();
+            let mut pending_since: Option<SystemTime> = None;
+            while !should_stop.load(Ordering::Relaxed) {
+                thread::sleep(poll_interval);
+                let modified = store.modified();
+                if modified != last_modified {
+                    last_modified = modified;
+                    pending_since = Some(SystemTime::now());
+                    continue;
+                }
+                let settled = match pending_since {
+                    Some(since) => since.elapsed().unwrap_or(Duration::new(0, 0)) >= settle_delay,
+                    None => false,
+                };
+                if settled {
+                    pending_since = None;
+                    // A transient parse/IO error (e.g. caught mid-write) is not fatal: the
+                    // previous config stays in effect and the next settled edit gets another try.
+                    let _ = store.reload(&mut state.lock().unwrap());
+                }
+            }
+        });
+        ConfigWatchGuard { stop: stop }
+    }
+}
+
+/// Apply every tag in `config` unconditionally. Used by `load`, and by `reload` the first time
+/// it runs with no previous config to diff against.
+fn apply(state: &mut AdapterManagerState, config: &Config) {
+    for (id, tags) in &config.services {
+        let selectors = [ServiceSelector::new().with_id(Id::new(id.as_ref()))];
+        let tags: Vec<_> = tags.iter().map(|tag| Id::new(tag.as_ref())).collect();
+        state.add_service_tags(&selectors, &tags);
+    }
+    for (id, tags) in &config.getters {
+        let selectors = [GetterSelector::new().with_id(Id::new(id.as_ref()))];
+        let tags: Vec<_> = tags.iter().map(|tag| Id::new(tag.as_ref())).collect();
+        state.add_getter_tags(&selectors, &tags);
+    }
+    for (id, tags) in &config.setters {
+        let selectors = [SetterSelector::new().with_id(Id::new(id.as_ref()))];
+        let tags: Vec<_> = tags.iter().map(|tag| Id::new(tag.as_ref())).collect();
+        state.add_setter_tags(&selectors, &tags);
+    }
+}
+
+/// Diff `previous` against `next` and issue only the `add_*_tags`/`remove_*_tags` calls needed
+/// to converge, for each of the `services`/`getters`/`setters` sections.
+fn converge(state: &mut AdapterManagerState, previous: &Config, next: &Config) {
+    converge_services(state, &previous.services, &next.services);
+    converge_getters(state, &previous.getters, &next.getters);
+    converge_setters(state, &previous.setters, &next.setters);
+}
+
+fn all_ids<'a>(a: &'a HashMap<String, HashSet<String>>, b: &'a HashMap<String, HashSet<String>>) -> HashSet<&'a String> {
+    a.keys().chain(b.keys()).collect()
+}
+
+fn empty_tags() -> HashSet<String> { HashSet::new() }
+
+fn converge_services(state: &mut AdapterManagerState, previous: &HashMap<String, HashSet<String>>,
+    next: &HashMap<String, HashSet<String>>)
+{
+    let empty = empty_tags();
+    for id in all_ids(previous, next) {
+        let before = previous.get(id).unwrap_or(&empty);
+        let after = next.get(id).unwrap_or(&empty);
+        let selectors = [ServiceSelector::new().with_id(Id::new(id.as_ref()))];
+        let added: Vec<_> = after.difference(before).map(|tag| Id::new(tag.as_ref())).collect();
+        if !added.is_empty() { state.add_service_tags(&selectors, &added); }
+        let removed: Vec<_> = before.difference(after).map(|tag| Id::new(tag.as_ref())).collect();
+        if !removed.is_empty() { state.remove_service_tags(&selectors, &removed); }
+    }
+}
+
+fn converge_getters(state: &mut AdapterManagerState, previous: &HashMap<String, HashSet<String>>,
+    next: &HashMap<String, HashSet<String>>)
+{
+    let empty = empty_tags();
+    for id in all_ids(previous, next) {
+        let before = previous.get(id).unwrap_or(&empty);
+        let after = next.get(id).unwrap_or(&empty);
+        let selectors = [GetterSelector::new().with_id(Id::new(id.as_ref()))];
+        let added: Vec<_> = after.difference(before).map(|tag| Id::new(tag.as_ref())).collect();
+        if !added.is_empty() { state.add_getter_tags(&selectors, &added); }
+        let removed: Vec<_> = before.difference(after).map(|tag| Id::new(tag.as_ref())).collect();
+        if !removed.is_empty() { state.remove_getter_tags(&selectors, &removed); }
+    }
+}
+
+fn converge_setters(state: &mut AdapterManagerState, previous: &HashMap<String, HashSet<String>>,
+    next: &HashMap<String, HashSet<String>>)
+{
+    let empty = empty_tags();
+    for id in all_ids(previous, next) {
+        let before = previous.get(id).unwrap_or(&empty);
+        let after = next.get(id).unwrap_or(&empty);
+        let selectors = [SetterSelector::new().with_id(Id::new(id.as_ref()))];
+        let added: Vec<_> = after.difference(before).map(|tag| Id::new(tag.as_ref())).collect();
+        if !added.is_empty() { state.add_setter_tags(&selectors, &added); }
+        let removed: Vec<_> = before.difference(after).map(|tag| Id::new(tag.as_ref())).collect();
+        if !removed.is_empty() { state.remove_setter_tags(&selectors, &removed); }
+    }
+}
+
+/// Default poll interval used by callers of `AdapterManager::watch_config` that don't need a
+/// tighter responsiveness/overhead tradeoff.
+pub fn default_poll_interval() -> Duration {
+    Duration::from_millis(500)
+}
+
+/// Default settle delay: how long the file must stop changing before a reload fires, to
+/// coalesce a multi-write save into a single reload.
+pub fn default_settle_delay() -> Duration {
+    Duration::from_millis(300)
+}
@@ -0,0 +1,181 @@
+//! A manager-wide, multi-subscriber broadcast of `Effect`s.
+//!
+//! `register_channel_watch` and friends (see `backend.rs`) dispatch events to a callback chosen
+//! when the watch is registered, scoped to whatever getters its selectors match. `EffectBus` is
+//! orthogonal: it is a single retained stream of every write applied (or rejected) through any
+//! `AdapterManagerState::send_values*` call and every read returned by `fetch_values`, so
+//! logging, a dashboard, or a bridge adapter can each observe the same history independently,
+//! without registering a selector and without being able to stall `send_values`/`fetch_values`
+//! themselves.
+//!
+//! `EffectBus::publish` never blocks: it appends to a capped ring buffer and returns immediately.
+//! A subscriber that reads slower than the bus fills has its cursor fast-forwarded to the oldest
+//! entry still retained, and receives a single `Effect::Lagged` marker in place of whatever was
+//! discarded out from under it.
+
+use foxbox_taxonomy::services::{ Getter, Setter };
+use foxbox_taxonomy::util::Id;
+use foxbox_taxonomy::values::Value;
+
+use std::collections::VecDeque;
+use std::sync::{ Arc, Condvar, Mutex };
+
+/// How many `Effect`s `EffectBus::new` retains by default. See `EffectBus::with_capacity`.
+const DEFAULT_CAPACITY: usize = 256;
+
+/// A single effect published to every `EffectReceiver`: a setter write (applied or rejected) by
+/// any `send_values*` call, a getter read by `fetch_values`, or the `Lagged` marker of a
+/// receiver that fell behind.
+#[derive(Clone, Debug)]
+pub enum Effect {
+    /// A value was successfully written to a setter.
+    ValueSent(Id<Setter>, Value),
+
+    /// A setter write did not succeed. `RejectionKind` classifies why, but the underlying error
+    /// itself is not carried here: `foxbox_taxonomy::api::Error` is defined in an external crate
+    /// with no guaranteed `Clone`/`Debug`, and every `send_values*` call already reports its
+    /// concrete error to its own caller, so a subscriber wanting the full detail should read that
+    /// call's result map directly - the bus only attests to the kind of failure.
+    ValueRejected(Id<Setter>, RejectionKind),
+
+    /// A getter was successfully read via `fetch_values`.
+    ValueRead(Id<Getter>, Value),
+
+    /// This receiver fell behind the bus's retained history: `skipped` entries were discarded
+    /// before it could read them. Always delivered instead of the missed entries, never
+    /// alongside them.
+    Lagged(usize),
+}
+
+/// A coarse classification of why a setter write failed, carried by `Effect::ValueRejected`
+/// instead of the underlying error so this module does not need `foxbox_taxonomy::api::Error`
+/// (or any of the crate-local wrappers built around it, e.g. `adapter::CancellationError`) to be
+/// `Clone`. See `backend::RejectionKindOf`, implemented for every error type a `send_values*`
+/// call can produce, for how a concrete error is classified into one of these.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RejectionKind {
+    /// The value did not match the setter's expected type.
+    TypeError,
+    /// An inconsistency in this crate's own bookkeeping (e.g. a stale/removed setter), rather
+    /// than a problem with the value itself.
+    InternalError,
+    /// The write was cancelled before its adapter replied. See `adapter::CancellationError`.
+    Cancelled,
+    /// The adapter did not reply before the deadline elapsed. See `adapter::TimeoutError`.
+    Timeout,
+    /// The write's precondition did not hold. See `adapter::ConditionalWriteError`.
+    PreconditionFailed,
+    /// The write used `UpdateKind::Merge`/`UpdateKind::Patch`, which this crate cannot
+    /// materialize. See `adapter::UpdateError`.
+    UnsupportedUpdate,
+    /// Any failure not covered by a more specific kind above.
+    Other,
+}
+
+struct Entry {
+    sequence: u64,
+    effect: Effect,
+}
+
+struct BusState {
+    entries: VecDeque<Entry>,
+    capacity: usize,
+    next_sequence: u64,
+}
+
+/// The publishing half of an effect bus, held by `AdapterManagerState`. Cheap to clone: every
+/// clone shares the same underlying ring buffer.
+#[derive(Clone)]
+pub struct EffectBus {
+    state: Arc<Mutex<BusState>>,
+    changed: Arc<Condvar>,
+}
+
+impl EffectBus {
+    pub fn new() -> Self {
+        Self::with_capacity(DEFAULT_CAPACITY)
+    }
+
+    /// As `new`, but retain `capacity` entries instead of `DEFAULT_CAPACITY`, trading subscriber
+    /// catch-up room against the memory held for entries nobody has read yet.
+    pub fn with_capacity(capacity: usize) -> Self {
+        EffectBus {
+            state: Arc::new(Mutex::new(BusState {
+                entries: VecDeque::new(),
+                capacity: capacity,
+                next_sequence: 0,
+            })),
+            changed: Arc::new(Condvar::new()),
+        }
+    }
+
+    /// Append `effect` to the bus. Never blocks, regardless of how far behind any subscriber has
+    /// fallen: once more than `capacity` entries are retained, the oldest is discarded to make
+    /// room, and any subscriber still behind it receives `Effect::Lagged` on its next read.
+    pub fn publish(&self, effect: Effect) {
+        let mut state = self.state.lock().unwrap();
+        let sequence = state.next_sequence;
+        state.next_sequence += 1;
+        if state.entries.len() >= state.capacity {
+            state.entries.pop_front();
+        }
+        state.entries.push_back(Entry { sequence: sequence, effect: effect });
+        self.changed.notify_all();
+    }
+
+    /// Subscribe from this point in the stream onward: the returned `EffectReceiver` only ever
+    /// sees `Effect`s published after this call.
+    pub fn subscribe(&self) -> EffectReceiver {
+        let next_sequence = self.state.lock().unwrap().next_sequence;
+        EffectReceiver {
+            state: self.state.clone(),
+            changed: self.changed.clone(),
+            next_sequence: next_sequence,
+        }
+    }
+}
+impl Default for EffectBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A read cursor into an `EffectBus`, returned by `AdapterManager::subscribe_effects`. `recv`
+/// blocks until the next `Effect` is available; nothing an `EffectReceiver` does can block the
+/// publishing side.
+pub struct EffectReceiver {
+    state: Arc<Mutex<BusState>>,
+    changed: Arc<Condvar>,
+    next_sequence: u64,
+}
+impl EffectReceiver {
+    /// Block until the next `Effect` is available. Returns `Effect::Lagged(skipped)` instead of
+    /// blocking forever if this receiver fell behind the bus's retained history in the meantime.
+    pub fn recv(&mut self) -> Effect {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if let Some(oldest) = state.entries.front() {
+                if self.next_sequence < oldest.sequence {
+                    let skipped = oldest.sequence - self.next_sequence;
+                    self.next_sequence = oldest.sequence;
+                    return Effect::Lagged(skipped as usize);
+                }
+            }
+            let next_sequence = self.next_sequence;
+            let found = state.entries.iter()
+                .find(|entry| entry.sequence == next_sequence)
+                .map(|entry| entry.effect.clone());
+            if let Some(effect) = found {
+                self.next_sequence += 1;
+                return effect;
+            }
+            state = self.changed.wait(state).unwrap();
+        }
+    }
+}
+impl Iterator for EffectReceiver {
+    type Item = Effect;
+    fn next(&mut self) -> Option<Effect> {
+        Some(self.recv())
+    }
+}
@@ -0,0 +1,209 @@
+//! A bridge adapter that republishes every `effects::Effect` to an external Kafka topic as a
+//! JSON record, giving a downstream consumer (a dashboard, an auditor, another service) a
+//! complete log of every write and read applied through this crate.
+//!
+//! This tree declares only three `extern crate`s (`foxbox_taxonomy`, `transformable_channels`,
+//! `toml`; see `lib.rs`) and has no Kafka client or JSON/serde crate available, and policy here
+//! is to never vendor a new dependency in just to satisfy one adapter. So, exactly as
+//! `persistence::ValueCodec` leaves `Value`'s on-the-wire encoding pluggable rather than assumed,
+//! the actual wire protocol spoken to a Kafka broker is left to whatever `KafkaProducer` a real
+//! deployment plugs in (e.g. one backed by a `rdkafka` client crate added to that deployment's own
+//! `Cargo.toml`): `KafkaBridge` itself only ever hands it an already-partitioned, already-encoded
+//! payload, and the JSON records here are built by hand rather than through a serialization
+//! library.
+//!
+//! Reading off `effects::EffectBus` and writing to Kafka are split across two threads connected
+//! by a bounded channel sized by `KafkaBridgeConfig::send_buffer`, so a Kafka broker that is slow
+//! to accept writes backs up that channel rather than this bridge's own `EffectReceiver`: once
+//! the channel is full the reader thread blocks handing off its next record, and - exactly as
+//! intended for any other subscriber that falls behind (see `effects::Effect::Lagged`) - this
+//! bridge's cursor into the bus falls behind and catches up with a single `Effect::Lagged` marker
+//! of its own, rather than this bridge ever stalling `send_values`/`fetch_values` itself.
+
+use effects::{ Effect, EffectReceiver, RejectionKind };
+use persistence::ValueCodec;
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{ Hash, Hasher };
+use std::sync::Arc;
+use std::sync::atomic::{ AtomicBool, Ordering };
+use std::sync::mpsc::sync_channel;
+use std::thread;
+
+/// Static configuration for a `KafkaBridge`.
+pub struct KafkaBridgeConfig {
+    /// Addresses of the brokers to connect to, handed as-is to the `KafkaProducer`.
+    pub brokers: Vec<String>,
+
+    /// The topic every record is published to.
+    pub topic: String,
+
+    /// Identifies this bridge to the broker, e.g. for client-side quota/ACL purposes.
+    pub client_id: String,
+
+    /// How many encoded records may be queued between the thread draining `EffectReceiver` and
+    /// the thread handing records to `KafkaProducer`, before the former blocks. See this module's
+    /// doc comment for why that's the point at which this bridge starts lagging instead of the
+    /// reader thread piling up unbounded memory.
+    pub send_buffer: usize,
+
+    /// Number of partitions `topic` is created with, used by `partition_for` so that a given
+    /// setter/getter id always lands on the same partition.
+    pub partition_count: u32,
+}
+
+/// Publishes a single already-encoded, already-partitioned record to Kafka. This is the
+/// extension point a real deployment implements against an actual Kafka client crate; see this
+/// module's doc comment for why `KafkaBridge` cannot talk to a broker directly in this tree.
+pub trait KafkaProducer: Send + Sync {
+    fn send(&self, topic: &str, partition: u32, payload: Vec<u8>);
+}
+
+/// Which partition `key` lands on, stable across calls for the same key: the same setter or
+/// getter id always hashes to the same partition, so a consumer reading only one partition still
+/// sees every record for a given channel, in order.
+fn partition_for<T: Hash>(key: &T, partition_count: u32) -> u32 {
+    if partition_count == 0 {
+        return 0;
+    }
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    (hasher.finish() % partition_count as u64) as u32
+}
+
+fn escape_json(input: &str) -> String {
+    let mut escaped = String::with_capacity(input.len() + 2);
+    for c in input.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn hex_encode(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len() * 2);
+    for byte in bytes {
+        out.push_str(&format!("{:02x}", byte));
+    }
+    out
+}
+
+/// A witness that a `KafkaBridge`'s background threads are running. Dropping it stops the reader
+/// thread - though, since `EffectReceiver::recv` has no timeout, only once the next `Effect`
+/// wakes it up to notice, the same limitation `queue::SendQueue`/`scheduler::PollScheduler`
+/// accept for their own poll-driven background loops. The sender thread then stops on its own
+/// once the reader thread's half of `send_buffer` is dropped.
+pub struct KafkaBridgeGuard {
+    stop: Arc<AtomicBool>,
+}
+impl Drop for KafkaBridgeGuard {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+/// The JSON string identifying a `RejectionKind` in an error record's `"kind"` field.
+fn rejection_kind_name(kind: RejectionKind) -> &'static str {
+    match kind {
+        RejectionKind::TypeError => "type_error",
+        RejectionKind::InternalError => "internal_error",
+        RejectionKind::Cancelled => "cancelled",
+        RejectionKind::Timeout => "timeout",
+        RejectionKind::PreconditionFailed => "precondition_failed",
+        RejectionKind::UnsupportedUpdate => "unsupported_update",
+        RejectionKind::Other => "other",
+    }
+}
+
+/// Encode `effect` into a `(partition, payload)` pair, ready for `KafkaProducer::send`.
+/// `ValueRejected` is encoded as a `"type":"error"` record rather than dropped, so a consumer
+/// reading this bridge's topic sees a complete audit stream of the taxonomy, not just its
+/// successes; its `"kind"` field lets that consumer tell a type mismatch apart from an
+/// internal inconsistency, a timeout, and so on, without this bridge needing the full
+/// `foxbox_taxonomy::api::Error` (see `effects::RejectionKind`).
+fn encode(effect: &Effect, partition_count: u32, codec: &ValueCodec) -> (u32, Vec<u8>) {
+    match *effect {
+        Effect::ValueSent(ref id, ref value) => {
+            let partition = partition_for(id, partition_count);
+            let payload = format!(
+                "{{\"type\":\"value_sent\",\"setter\":\"{}\",\"value\":\"{}\"}}",
+                escape_json(&format!("{:?}", id)), hex_encode(&codec.encode(value)));
+            (partition, payload.into_bytes())
+        }
+        Effect::ValueRejected(ref id, kind) => {
+            let partition = partition_for(id, partition_count);
+            let payload = format!(
+                "{{\"type\":\"error\",\"setter\":\"{}\",\"kind\":\"{}\"}}",
+                escape_json(&format!("{:?}", id)), rejection_kind_name(kind));
+            (partition, payload.into_bytes())
+        }
+        Effect::ValueRead(ref id, ref value) => {
+            let partition = partition_for(id, partition_count);
+            let payload = format!(
+                "{{\"type\":\"value_read\",\"getter\":\"{}\",\"value\":\"{}\"}}",
+                escape_json(&format!("{:?}", id)), hex_encode(&codec.encode(value)));
+            (partition, payload.into_bytes())
+        }
+        Effect::Lagged(skipped) => {
+            let payload = format!("{{\"type\":\"lagged\",\"skipped\":{}}}", skipped);
+            (0, payload.into_bytes())
+        }
+    }
+}
+
+/// Forwards every `Effect` read off an `EffectReceiver` to Kafka as a JSON record.
+pub struct KafkaBridge {
+    config: KafkaBridgeConfig,
+    producer: Box<KafkaProducer>,
+    codec: Box<ValueCodec>,
+}
+
+impl KafkaBridge {
+    pub fn new(config: KafkaBridgeConfig, producer: Box<KafkaProducer>, codec: Box<ValueCodec>) -> Self {
+        KafkaBridge { config: config, producer: producer, codec: codec }
+    }
+
+    /// Start forwarding `effects` to Kafka on a pair of background threads. See this module's
+    /// doc comment for why reading off the bus and writing to Kafka are split across two threads
+    /// connected by a `send_buffer`-sized bounded channel, and `KafkaBridgeGuard` for the exact
+    /// stop semantics.
+    pub fn start(self, mut effects: EffectReceiver) -> KafkaBridgeGuard {
+        let KafkaBridge { config, producer, codec } = self;
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let stop_reader = stop.clone();
+
+        let (tx, rx) = sync_channel(config.send_buffer.max(1));
+        let topic = config.topic.clone();
+        let partition_count = config.partition_count;
+
+        thread::spawn(move || {
+            for (partition, payload) in rx {
+                producer.send(&topic, partition, payload);
+            }
+        });
+
+        thread::spawn(move || {
+            loop {
+                let effect = effects.recv();
+                if stop_reader.load(Ordering::Relaxed) {
+                    break;
+                }
+                let encoded = encode(&effect, partition_count, &*codec);
+                if tx.send(encoded).is_err() {
+                    // The sender thread is gone; nothing more will ever be published.
+                    break;
+                }
+            }
+        });
+
+        KafkaBridgeGuard { stop: stop }
+    }
+}
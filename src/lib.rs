@@ -3,6 +3,7 @@
 
 extern crate foxbox_taxonomy;
 extern crate transformable_channels;
+extern crate toml;
 
 /// The back-end thread, in charge of the heavy lifting of managing adapters.
 mod backend;
@@ -18,6 +19,34 @@ pub mod adapter;
 /// any error.
 pub mod transact;
 
+/// Persistence of tags and last-known channel values across reboots.
+pub mod persistence;
+
+/// Hot-reloadable TOML configuration of adapter tags and options.
+pub mod config;
+
+/// Read-only metrics and introspection for `AdapterManagerState`.
+pub mod metrics;
+
+/// A lazy, dependency-injection-style registry of adapter factories.
+pub mod provider;
+
+/// A durable, coalescing outgoing queue for setter writes, with bounded retry.
+pub mod queue;
+
+/// Background polling of getters whose adapter has no native push notification.
+pub mod scheduler;
+
+/// A manager-wide, multi-subscriber broadcast of setter writes and getter reads, with lag reporting for
+/// a subscriber that falls behind.
+pub mod effects;
+
+/// An integration adapter that republishes the effect bus to an external Kafka topic.
+pub mod kafka_bridge;
+
+/// Hot-reloadable TOML configuration of the service/getter/setter topology itself.
+pub mod topology_config;
+
 /// Implementation of a fake adapter, controlled entirely programmatically. Designed to be used
 /// as a component of tests.
 pub mod fake_adapter;
\ No newline at end of file
@@ -5,7 +5,22 @@
 //! - it exposes an implementation of the taxonomy API.
 
 use backend::*;
-use adapter::{ Adapter, AdapterManagerHandle };
+/// Re-exported because `backend` is a private module: these are part of this crate's public API
+/// (see `get_services_matching`, `send_values_with_handle`, and friends below) even though
+/// they're implemented alongside the rest of `backend.rs`.
+pub use backend::{ Filtered, GetterHandle, HandleError, JobHandle, ResultsFuture, ServiceHandle, SetterHandle,
+    TagPredicate, TopologyEvent, TopologyWatchGuard, WatchBufferBudget, WatchBufferDropped };
+use adapter::{ Adapter, AdapterManagerHandle, AtomicSendRejection, CancellationError, ConditionalWriteError,
+    LogicalChannelError, LogicalChannelId, Precondition, QuorumError, SetterVerification, TimeoutError,
+    UpdateError, UpdateKind };
+use config::{ ConfigStore, ConfigWatchGuard };
+use effects::EffectReceiver;
+use metrics::Metrics;
+use persistence::{ PersistenceRule, PersistenceStore, RestoreReport, ValueCodec };
+use provider::AdapterFactory;
+use queue::{ QueueHandle, SendQueue };
+use scheduler::{ PollScheduler, PollSchedulerGuard };
+use topology_config::{ TopologyConfigStore, TopologyConfigWatchGuard };
 
 use foxbox_taxonomy::api::{ AdapterError, API, Error as APIError, ResultMap, WatchEvent };
 use foxbox_taxonomy::selector::*;
@@ -13,11 +28,42 @@ use foxbox_taxonomy::services::*;
 use foxbox_taxonomy::util::*;
 use foxbox_taxonomy::values::{ Range, Value };
 
+use transformable_channels::mpsc::ExtSender;
+
+use std::io;
+use std::path::PathBuf;
 use std::sync::{ Arc, Mutex };
+use std::sync::mpsc::{ channel, Receiver };
+use std::time::Duration;
 
 /// An implementation of the AdapterManager.
 pub struct AdapterManager {
     back_end: Arc<Mutex<AdapterManagerState>>,
+
+    /// Set by `with_persistence`. `None` means tags and last-known values are not persisted
+    /// across reboots, which is still the default: most callers don't need it.
+    persistence: Option<PersistenceStore>,
+
+    /// Set by `with_config`. `None` means tags are only ever set imperatively, which is still
+    /// the default: most callers don't need a declarative config file.
+    config: Option<Arc<ConfigStore>>,
+
+    /// Set by `with_topology_config`. `None` means services and channels are only ever
+    /// registered imperatively by adapters, which is still the default.
+    topology_config: Option<Arc<TopologyConfigStore>>,
+
+    /// Backs `enqueue_send`/`pending_sends`. Always present: unlike persistence or declarative
+    /// config, a caller opts into the outgoing queue simply by using it.
+    queue: SendQueue,
+
+    /// Backs `set_default_poll_interval`/`set_poll_coalesce_window`. Always present and always running: a
+    /// getter only ever costs anything here once it both declares a `poll` interval and has a
+    /// live watcher, so there is no reason to make this opt-in the way persistence/config are.
+    scheduler: PollScheduler,
+
+    /// Keeps the scheduler's background thread alive for as long as this manager is; dropped
+    /// (stopping the thread) when the manager itself is.
+    _scheduler_guard: PollSchedulerGuard,
 }
 
 impl AdapterManager {
@@ -25,10 +71,142 @@ impl AdapterManager {
     /// This function does not attempt to load any state from the disk.
     pub fn new() -> Self {
         let back_end = Arc::new(Mutex::new(AdapterManagerState::new()));
+        let queue = SendQueue::new(back_end.clone());
+        let scheduler = PollScheduler::new();
+        let scheduler_guard = scheduler.watch(back_end.clone());
+        AdapterManager {
+            back_end: back_end,
+            persistence: None,
+            config: None,
+            topology_config: None,
+            queue: queue,
+            scheduler: scheduler,
+            _scheduler_guard: scheduler_guard,
+        }
+    }
+
+    /// Create an empty AdapterManager whose `fetch_values`/`send_values` dispatch to adapters
+    /// through `pool_size` worker threads instead of `AdapterManagerState`'s default, so an
+    /// embedder can bound (or widen) thread usage to match how many adapters it expects to run
+    /// concurrently.
+    pub fn with_pool_size(pool_size: usize) -> Self {
+        let back_end = Arc::new(Mutex::new(AdapterManagerState::new_with_pool_size(pool_size)));
+        let queue = SendQueue::new(back_end.clone());
+        let scheduler = PollScheduler::new();
+        let scheduler_guard = scheduler.watch(back_end.clone());
         AdapterManager {
             back_end: back_end,
+            persistence: None,
+            config: None,
+            topology_config: None,
+            queue: queue,
+            scheduler: scheduler,
+            _scheduler_guard: scheduler_guard,
         }
     }
+
+    /// Create an AdapterManager backed by a persistence store at `path`. Call `restore()`
+    /// once adapters and their expected tags are known, to repopulate them and seed the
+    /// last-known-value cache before the first live `fetch_values`. Nothing is persisted
+    /// until `rules` are attached with `configure_persistence`.
+    pub fn with_persistence(path: PathBuf) -> Self {
+        let mut manager = Self::new();
+        manager.persistence = Some(PersistenceStore::new(path, Vec::new(), Box::new(NoopCodec)));
+        manager
+    }
+
+    /// Replace the persistence rules and value codec used by `snapshot`/`restore`. Has no
+    /// effect unless this manager was created with `with_persistence`.
+    pub fn configure_persistence(&mut self, path: PathBuf, rules: Vec<PersistenceRule>, codec: Box<ValueCodec>) {
+        if self.persistence.is_some() {
+            self.persistence = Some(PersistenceStore::new(path, rules, codec));
+        }
+    }
+
+    /// Re-apply persisted tags and seed the last-known-value cache. Does nothing and returns
+    /// an empty report if this manager was not created with `with_persistence`.
+    pub fn restore(&self) -> io::Result<RestoreReport> {
+        match self.persistence {
+            None => Ok(RestoreReport::default()),
+            Some(ref store) => store.restore(&mut self.back_end.lock().unwrap())
+        }
+    }
+
+    /// Snapshot the currently persisted tags and last-known values. Does nothing if this
+    /// manager was not created with `with_persistence`.
+    pub fn snapshot(&self) -> io::Result<()> {
+        match self.persistence {
+            None => Ok(()),
+            Some(ref store) => store.snapshot(&self.back_end.lock().unwrap())
+        }
+    }
+
+    /// Create an AdapterManager that declaratively configures tags and per-adapter options
+    /// from a TOML file at `path`. Call `load_config()` once adapters and services are
+    /// registered, to apply it, then `watch_config()` to keep re-applying it as the file is
+    /// edited.
+    pub fn with_config(path: PathBuf) -> Self {
+        let mut manager = Self::new();
+        manager.config = Some(Arc::new(ConfigStore::new(path)));
+        manager
+    }
+
+    /// Load and apply this manager's config file. Does nothing and returns `Ok(())` if this
+    /// manager was not created with `with_config`.
+    pub fn load_config(&self) -> io::Result<()> {
+        match self.config {
+            None => Ok(()),
+            Some(ref store) => store.load(&mut self.back_end.lock().unwrap())
+        }
+    }
+
+    /// Start watching this manager's config file for edits, re-applying the minimal tag diff
+    /// once a burst of writes has settled for `settle_delay`, polling every `poll_interval`.
+    /// Returns `None` if this manager was not created with `with_config`. Dropping the returned
+    /// guard stops the watch.
+    pub fn watch_config(&self, poll_interval: Duration, settle_delay: Duration) -> Option<ConfigWatchGuard> {
+        self.config.as_ref().map(|store| {
+            store.clone().watch(self.back_end.clone(), poll_interval, settle_delay)
+        })
+    }
+
+    /// Create an AdapterManager that declaratively registers services and channels from a
+    /// TOML file at `path`. Call `load_topology_config()` once the adapters that will own
+    /// those services are registered, to apply it, then `watch_topology_config()` to keep
+    /// converging the topology as the file is edited.
+    pub fn with_topology_config(path: PathBuf) -> Self {
+        let mut manager = Self::new();
+        manager.topology_config = Some(Arc::new(TopologyConfigStore::new(path)));
+        manager
+    }
+
+    /// Load and apply this manager's topology config file. Does nothing and returns `Ok(())`
+    /// if this manager was not created with `with_topology_config`.
+    pub fn load_topology_config(&self) -> io::Result<()> {
+        match self.topology_config {
+            None => Ok(()),
+            Some(ref store) => store.load(&mut self.back_end.lock().unwrap())
+        }
+    }
+
+    /// Start watching this manager's topology config file for edits, re-converging the
+    /// topology once a burst of writes has settled for `settle_delay`, polling every
+    /// `poll_interval`. Returns `None` if this manager was not created with
+    /// `with_topology_config`. Dropping the returned guard stops the watch.
+    pub fn watch_topology_config(&self, poll_interval: Duration, settle_delay: Duration) -> Option<TopologyConfigWatchGuard> {
+        self.topology_config.as_ref().map(|store| {
+            store.clone().watch(self.back_end.clone(), poll_interval, settle_delay)
+        })
+    }
+}
+
+/// A `ValueCodec` that persists no value payload, only presence/absence and timestamps. Used
+/// as the default for `with_persistence` until `configure_persistence` supplies a codec that
+/// understands the adapters actually in use.
+struct NoopCodec;
+impl ValueCodec for NoopCodec {
+    fn encode(&self, _: &Value) -> Vec<u8> { Vec::new() }
+    fn decode(&self, _: &[u8]) -> Option<Value> { None }
 }
 
 impl Default for AdapterManager {
@@ -37,6 +215,51 @@ impl Default for AdapterManager {
     }
 }
 
+impl AdapterManager {
+    /// Watch for services and channels being added, removed or (un)tagged.
+    ///
+    /// Unlike `add_service_tags`/`remove_service_tags` and their channel equivalents, this
+    /// watch is live: `on_event` is called for every matching registration change until the
+    /// returned guard is dropped.
+    pub fn register_topology_watch(&self, service_selectors: Vec<ServiceSelector>,
+        getter_selectors: Vec<GetterSelector>, setter_selectors: Vec<SetterSelector>,
+        on_event: Box<Fn(TopologyEvent) + Send>) -> TopologyWatchGuard
+    {
+        let key = self.back_end.lock().unwrap().register_topology_watch(service_selectors,
+            getter_selectors, setter_selectors, on_event);
+        TopologyWatchGuard::new(self.back_end.clone(), key)
+    }
+
+    /// A snapshot of live counts (adapters, services, getters, setters, watcher fan-out) for
+    /// operators to inspect. See `metrics::Metrics`.
+    pub fn metrics(&self) -> Metrics {
+        self.back_end.lock().unwrap().metrics()
+    }
+
+    /// Register `factory` for lazy instantiation, instead of constructing the adapter and
+    /// calling `add_adapter` right away. See `provider::AdapterProviderRegistry`.
+    pub fn register_adapter_factory(&self, factory: Box<AdapterFactory>) {
+        self.back_end.lock().unwrap().register_adapter_factory(factory)
+    }
+
+    /// Ids of every registered adapter factory, not yet instantiated, that declares
+    /// `capability`. Does not instantiate any of the matching adapters; call `ensure_adapter`
+    /// on the ids actually needed.
+    pub fn adapters_with_capability(&self, capability: &str) -> Vec<Id<AdapterId>> {
+        self.back_end.lock().unwrap().adapters_with_capability(capability)
+    }
+
+    /// Make sure `id` is a live adapter, instantiating it from a registered factory if it
+    /// isn't already. Does nothing if `id` is already live.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `id` is neither a live adapter nor a registered factory.
+    pub fn ensure_adapter(&self, id: &Id<AdapterId>) -> Result<(), APIError> {
+        self.back_end.lock().unwrap().ensure_adapter(id)
+    }
+}
+
 impl AdapterManagerHandle for AdapterManager {
     /// Add an adapter to the system.
     ///
@@ -70,7 +293,7 @@ impl AdapterManagerHandle for AdapterManager {
     /// Returns an error if the adapter does not exist or a service with the same identifier
     /// already exists, or if the identifier introduces a channel that would overwrite another
     /// channel with the same identifier. In either cases, this method reverts all its changes.
-    fn add_service(&self, service: Service) -> Result<(), AdapterError> {
+    fn add_service(&self, service: Service) -> Result<ServiceHandle, AdapterError> {
         self.back_end.lock().unwrap().add_service(service)
     }
 
@@ -99,7 +322,7 @@ impl AdapterManagerHandle for AdapterManager {
     /// Returns an error if the adapter is not registered, the parent service is not
     /// registered, or a channel with the same identifier is already registered.
     /// In either cases, this method reverts all its changes.
-    fn add_getter(&self, getter: Channel<Getter>) -> Result<(), AdapterError> {
+    fn add_getter(&self, getter: Channel<Getter>) -> Result<GetterHandle, AdapterError> {
         self.back_end.lock().unwrap().add_getter(getter)
     }
 
@@ -128,7 +351,7 @@ impl AdapterManagerHandle for AdapterManager {
     /// Returns an error if the adapter is not registered, the parent service is not
     /// registered, or a channel with the same identifier is already registered.
     /// In either cases, this method reverts all its changes.
-    fn add_setter(&self, setter: Channel<Setter>) -> Result<(), AdapterError> {
+    fn add_setter(&self, setter: Channel<Setter>) -> Result<SetterHandle, AdapterError> {
         self.back_end.lock().unwrap().add_setter(setter)
     }
 
@@ -258,10 +481,485 @@ impl API for AdapterManager {
         on_event: Box<Fn(WatchEvent) + Send>) -> Self::WatchGuard
     {
         let (tx, key, is_dropped) = self.back_end.lock().unwrap().register_channel_watch(watch,
-            on_event);
+            subscribe_only(on_event), StreamMode::Subscribe, Duration::from_secs(0), None,
+            Box::new(|_| {}), None, self.back_end.clone());
         WatchGuard::new(self.back_end.clone(), tx, key, is_dropped)
     }
 
     /// A value that causes a disconnection once it is dropped.
     type WatchGuard = WatchGuard;
+}
+
+impl AdapterManager {
+    /// Like `API::get_services`, but each selector is a `Filtered<ServiceSelector>`: see
+    /// `Filtered` for the tag negation/disjunction this adds on top of plain `.with_tags(..)`.
+    pub fn get_services_matching(&self, selectors: &[Filtered<ServiceSelector>]) -> Vec<Service> {
+        self.back_end.lock().unwrap().get_services_matching(selectors)
+    }
+
+    /// Like `API::add_service_tags`, but each selector is a `Filtered<ServiceSelector>`. See
+    /// `Filtered`.
+    pub fn add_service_tags_matching(&self, selectors: &[Filtered<ServiceSelector>], tags: &[Id<TagId>]) -> usize {
+        self.back_end.lock().unwrap().add_service_tags_matching(selectors, tags)
+    }
+
+    /// Like `API::remove_service_tags`, but each selector is a `Filtered<ServiceSelector>`. See
+    /// `Filtered`.
+    pub fn remove_service_tags_matching(&self, selectors: &[Filtered<ServiceSelector>], tags: &[Id<TagId>]) -> usize {
+        self.back_end.lock().unwrap().remove_service_tags_matching(selectors, tags)
+    }
+
+    /// Like `API::get_getter_channels`, but each selector is a `Filtered<GetterSelector>`. See
+    /// `Filtered`.
+    pub fn get_getter_channels_matching(&self, selectors: &[Filtered<GetterSelector>]) -> Vec<Channel<Getter>> {
+        self.back_end.lock().unwrap().get_getter_channels_matching(selectors)
+    }
+
+    /// Like `API::get_setter_channels`, but each selector is a `Filtered<SetterSelector>`. See
+    /// `Filtered`.
+    pub fn get_setter_channels_matching(&self, selectors: &[Filtered<SetterSelector>]) -> Vec<Channel<Setter>> {
+        self.back_end.lock().unwrap().get_setter_channels_matching(selectors)
+    }
+
+    /// Like `API::add_getter_tags`, but each selector is a `Filtered<GetterSelector>`. See
+    /// `Filtered`.
+    pub fn add_getter_tags_matching(&self, selectors: &[Filtered<GetterSelector>], tags: &[Id<TagId>]) -> usize {
+        self.back_end.lock().unwrap().add_getter_tags_matching(selectors, tags)
+    }
+
+    /// Like `API::add_setter_tags`, but each selector is a `Filtered<SetterSelector>`. See
+    /// `Filtered`.
+    pub fn add_setter_tags_matching(&self, selectors: &[Filtered<SetterSelector>], tags: &[Id<TagId>]) -> usize {
+        self.back_end.lock().unwrap().add_setter_tags_matching(selectors, tags)
+    }
+
+    /// Like `API::remove_getter_tags`, but each selector is a `Filtered<GetterSelector>`. See
+    /// `Filtered`.
+    pub fn remove_getter_tags_matching(&self, selectors: &[Filtered<GetterSelector>], tags: &[Id<TagId>]) -> usize {
+        self.back_end.lock().unwrap().remove_getter_tags_matching(selectors, tags)
+    }
+
+    /// Like `API::remove_setter_tags`, but each selector is a `Filtered<SetterSelector>`. See
+    /// `Filtered`.
+    pub fn remove_setter_tags_matching(&self, selectors: &[Filtered<SetterSelector>], tags: &[Id<TagId>]) -> usize {
+        self.back_end.lock().unwrap().remove_setter_tags_matching(selectors, tags)
+    }
+
+    /// Watch for value changes, like `API::register_channel_watch`, but hold events for
+    /// a given getter for `debounce` before delivering them, so that a chatty sensor settles
+    /// to its final state instead of flooding `on_event`. A zero `debounce` is equivalent to
+    /// calling `register_channel_watch` directly.
+    pub fn register_channel_watch_with_debounce(&self, watch: Vec<(Vec<GetterSelector>, Exactly<Range>)>,
+        on_event: Box<Fn(WatchEvent) + Send>, debounce: Duration) -> WatchGuard
+    {
+        let (tx, key, is_dropped) = self.back_end.lock().unwrap().register_channel_watch(watch,
+            subscribe_only(on_event), StreamMode::Subscribe, debounce, None, Box::new(|_| {}),
+            None, self.back_end.clone());
+        WatchGuard::new(self.back_end.clone(), tx, key, is_dropped)
+    }
+
+    /// Watch for value changes, like `API::register_channel_watch`, but evict the watch
+    /// rather than let its event queue grow without bound: once more than `max_pending`
+    /// events are buffered waiting for `on_event` to drain them, the watch is dropped and
+    /// `on_lagged` fires once with how many events were discarded to detect the overflow.
+    pub fn register_channel_watch_bounded(&self, watch: Vec<(Vec<GetterSelector>, Exactly<Range>)>,
+        on_event: Box<Fn(WatchEvent) + Send>, max_pending: usize,
+        on_lagged: Box<Fn(WatchQueueLagged) + Send>) -> WatchGuard
+    {
+        let (tx, key, is_dropped) = self.back_end.lock().unwrap().register_channel_watch(watch,
+            subscribe_only(on_event), StreamMode::Subscribe, Duration::from_secs(0),
+            Some(max_pending), on_lagged, None, self.back_end.clone());
+        WatchGuard::new(self.back_end.clone(), tx, key, is_dropped)
+    }
+
+    /// Watch for value changes, combining `register_channel_watch_with_debounce` and
+    /// `register_channel_watch_bounded`: a chatty getter is settled over `debounce` before
+    /// delivery, and if `on_event` still can't keep up, the watch is evicted once more than
+    /// `max_pending` settled events are waiting for it, exactly as `register_channel_watch_bounded`
+    /// describes.
+    pub fn register_channel_watch_with_debounce_bounded(&self, watch: Vec<(Vec<GetterSelector>, Exactly<Range>)>,
+        on_event: Box<Fn(WatchEvent) + Send>, debounce: Duration, max_pending: usize,
+        on_lagged: Box<Fn(WatchQueueLagged) + Send>) -> WatchGuard
+    {
+        let (tx, key, is_dropped) = self.back_end.lock().unwrap().register_channel_watch(watch,
+            subscribe_only(on_event), StreamMode::Subscribe, debounce, Some(max_pending),
+            on_lagged, None, self.back_end.clone());
+        WatchGuard::new(self.back_end.clone(), tx, key, is_dropped)
+    }
+
+    /// Watch for value changes, like `API::register_channel_watch`, but cap the memory held for
+    /// a slow consumer by dropping the oldest buffered data event to make room for the newest,
+    /// rather than evicting the whole watch as `register_channel_watch_bounded` does. `on_dropped`
+    /// fires each time the buffer drains with at least one event lost since the last report.
+    pub fn register_channel_watch_with_buffer(&self, watch: Vec<(Vec<GetterSelector>, Exactly<Range>)>,
+        on_event: Box<Fn(WatchEvent) + Send>, budget: WatchBufferBudget,
+        on_dropped: Box<Fn(WatchBufferDropped) + Send>) -> WatchGuard
+    {
+        let (tx, key, is_dropped) = self.back_end.lock().unwrap().register_channel_watch(watch,
+            subscribe_only(on_event), StreamMode::Subscribe, Duration::from_secs(0), None,
+            Box::new(|_| {}), Some((budget, on_dropped)), self.back_end.clone());
+        WatchGuard::new(self.back_end.clone(), tx, key, is_dropped)
+    }
+
+    /// Watch for value changes, like `register_channel_watch_with_debounce`, but let the caller
+    /// choose whether to also receive the current state of every matching getter up front (see
+    /// `StreamMode`): `on_event` then also receives `StreamEvent::SnapshotDone` once that
+    /// snapshot, if any, has been fully delivered.
+    pub fn register_channel_watch_with_mode(&self, watch: Vec<(Vec<GetterSelector>, Exactly<Range>)>,
+        on_event: Box<Fn(StreamEvent) + Send>, mode: StreamMode, debounce: Duration) -> WatchGuard
+    {
+        let (tx, key, is_dropped) = self.back_end.lock().unwrap().register_channel_watch(watch,
+            on_event, mode, debounce, None, Box::new(|_| {}), None, self.back_end.clone());
+        WatchGuard::new(self.back_end.clone(), tx, key, is_dropped)
+    }
+
+    /// Send a bunch of values to a set of channels, like `API::send_values`, but honor each
+    /// entry's precondition (see `Precondition`): an entry whose precondition fails is reported
+    /// as `ConditionalWriteError::PreconditionFailed` instead of being written.
+    pub fn send_values_checked(&self,
+        keyvalues: Vec<(Vec<SetterSelector>, Value, Option<Precondition>)>) ->
+        ResultMap<Id<Setter>, (), ConditionalWriteError>
+    {
+        self.back_end.lock().unwrap().send_values_checked(keyvalues)
+    }
+
+    /// Send a bunch of values to a set of channels, like `API::send_values`, but all-or-nothing:
+    /// every target is resolved and type-checked against its `ChannelKind` before anything is
+    /// dispatched, so a multi-device scene either applies in full or leaves the system untouched
+    /// rather than partially applying as `send_values` would. See `AtomicSendRejection`.
+    pub fn send_values_atomic(&self, keyvalues: Vec<(Vec<SetterSelector>, Value)>)
+        -> Result<ResultMap<Id<Setter>, (), APIError>, AtomicSendRejection>
+    {
+        self.back_end.lock().unwrap().send_values_atomic(keyvalues)
+    }
+
+    /// Send a bunch of structured partial updates to a set of channels, like `API::send_values`,
+    /// but letting each entry merge or patch onto the setter's current value instead of always
+    /// replacing it outright. See `UpdateKind`.
+    pub fn send_values_updated(&self, keyvalues: Vec<(Vec<SetterSelector>, UpdateKind)>) ->
+        ResultMap<Id<Setter>, (), UpdateError>
+    {
+        self.back_end.lock().unwrap().send_values_updated(keyvalues)
+    }
+
+    /// Read the latest value from a set of channels, like `API::fetch_values`, but abandon any
+    /// adapter that has not replied by the time `timeout` elapses: the channels it owns are
+    /// reported as `Err(TimeoutError::Timeout)` instead of blocking the whole batch on it.
+    pub fn fetch_values_with_timeout(&self, selectors: &[GetterSelector], timeout: Duration) ->
+        ResultSet<Id<Getter>, Option<Value>, TimeoutError>
+    {
+        self.back_end.lock().unwrap().fetch_values_with_timeout(selectors, timeout)
+    }
+
+    /// Send a bunch of values to a set of channels, like `API::send_values`, but abandon any
+    /// adapter that has not replied by the time `timeout` elapses: the channels it owns are
+    /// reported as `Err(TimeoutError::Timeout)` instead of blocking the whole batch on it.
+    pub fn send_values_with_timeout(&self, keyvalues: Vec<(Vec<SetterSelector>, Value)>, timeout: Duration) ->
+        ResultMap<Id<Setter>, (), TimeoutError>
+    {
+        self.back_end.lock().unwrap().send_values_with_timeout(keyvalues, timeout)
+    }
+
+    /// Send a bunch of values to a set of channels, like `API::send_values`, but also report
+    /// staged progress through `on_event` for every setter accepted - see `SetterVerification`.
+    pub fn send_values_verified(&self, keyvalues: Vec<(Vec<SetterSelector>, Value)>,
+        on_event: Box<ExtSender<SetterVerification> + Send>) -> ResultMap<Id<Setter>, (), Arc<APIError>>
+    {
+        self.back_end.lock().unwrap().send_values_verified(keyvalues, on_event)
+    }
+
+    /// Send a bunch of values to a set of channels, like `API::send_values`, but never block:
+    /// dispatch is submitted to the worker pool and this call returns immediately with a
+    /// `JobHandle` that can cancel any still-pending setter (dropping it cancels every setter
+    /// still outstanding) and a `ResultsFuture` that eventually resolves to the combined result,
+    /// reporting `CancellationError::Cancelled` for any setter cancelled before its adapter
+    /// replied. Useful when a targeted adapter may be wedged and the caller cannot afford to
+    /// have the whole batch block on it.
+    pub fn send_values_with_handle(&self, keyvalues: Vec<(Vec<SetterSelector>, Value)>)
+        -> (JobHandle, ResultsFuture)
+    {
+        self.back_end.lock().unwrap().send_values_with_handle(keyvalues)
+    }
+
+    /// Register a logical channel backed by several physical setters - possibly spanning
+    /// several adapters - acknowledged once at least `quorum` of them accept a write. See
+    /// `send_to_logical_channel`.
+    pub fn add_logical_channel(&self, id: Id<LogicalChannelId>, backing: Vec<Id<Setter>>, quorum: usize)
+        -> Result<(), LogicalChannelError>
+    {
+        self.back_end.lock().unwrap().add_logical_channel(id, backing, quorum)
+    }
+
+    /// Send `value` to every setter backing the logical channel `id`, succeeding once at least
+    /// its quorum of them acknowledge. Returns `QuorumError::Diverged` instead of sending
+    /// anything if the backing setters disagree on a `Type`, and `QuorumError::QuorumFailed`,
+    /// listing every backing setter's individual result, if fewer than quorum acknowledged.
+    /// Gives replicated/redundant devices a single consistent control surface, fanning a write
+    /// out to every backing setter before acknowledging it.
+    pub fn send_to_logical_channel(&self, id: &Id<LogicalChannelId>, value: Value) -> Result<(), QuorumError> {
+        self.back_end.lock().unwrap().send_to_logical_channel(id, value)
+    }
+
+    /// Queue a bunch of values to be sent to a set of setters, like `API::send_values`, but
+    /// durably: each write is appended to its setter's outgoing queue, coalesced onto any
+    /// not-yet-flushed write to the same (non-cumulative) setter, and retried with bounded
+    /// exponential backoff if the adapter reports a retryable `Error`. The returned
+    /// `QueueHandle` resolves once every write in this batch has either succeeded or exhausted
+    /// its retries. See `queue::SendQueue`.
+    pub fn enqueue_send(&self, writes: Vec<(Id<Setter>, Value)>) -> QueueHandle {
+        self.queue.enqueue(writes)
+    }
+
+    /// Number of writes still queued (neither flushed nor given up on) for `id`.
+    pub fn pending_sends(&self, id: &Id<Setter>) -> usize {
+        self.queue.pending(id)
+    }
+
+    /// Opt `id` in or out of the outgoing queue's coalescing: a cumulative setter (e.g. one
+    /// whose semantics are "append" rather than "replace") has every queued write flushed in
+    /// order, instead of successive not-yet-flushed writes collapsing to the latest value.
+    pub fn set_setter_cumulative(&self, id: Id<Setter>, cumulative: bool) {
+        self.queue.set_cumulative(id, cumulative)
+    }
+
+    /// Set how long a non-cumulative `enqueue_send` write waits, once (re-)enqueued, before it
+    /// becomes eligible to flush - so a burst of rapid sends to the same setter collapses to a
+    /// single flush of the final value instead of one flush per send. See
+    /// `queue::SendQueue::set_debounce_window`.
+    pub fn set_send_debounce_window(&self, window: Duration) {
+        self.queue.set_debounce_window(window)
+    }
+
+    /// Subscribe to every value successfully applied through any `send_values*` call (including
+    /// `enqueue_send`, once its queued write flushes), as a single retained stream independent of
+    /// which call produced it and of any other subscriber's reading speed. See `effects::EffectBus`.
+    pub fn subscribe_effects(&self) -> EffectReceiver {
+        self.back_end.lock().unwrap().subscribe_effects()
+    }
+
+    /// The last value observed for `id`, whether from a live `fetch_values` or seeded from
+    /// persisted state, if any is known yet - with `CachedValue::seeded` marking whether it is a
+    /// fresh read or may now be stale. See `backend::AdapterManagerState::cached_value`.
+    pub fn cached_value(&self, id: &Id<Getter>) -> Option<CachedValue> {
+        self.back_end.lock().unwrap().cached_value(id)
+    }
+
+    /// Number of watches currently registered, i.e. `register_channel_watch*` calls whose
+    /// `WatchGuard` has not been dropped yet.
+    pub fn watcher_count(&self) -> usize {
+        self.back_end.lock().unwrap().watcher_count()
+    }
+
+    /// Number of registered watchers currently matching `getter`. Returns 0 if `getter` is not
+    /// registered at all.
+    pub fn watchers_for(&self, getter: &Id<Getter>) -> usize {
+        self.back_end.lock().unwrap().watchers_for(getter)
+    }
+
+    /// Whether any watch currently matches `getter`, so e.g. an adapter can skip polling
+    /// hardware nobody is listening to.
+    pub fn is_watched(&self, getter: &Id<Getter>) -> bool {
+        self.back_end.lock().unwrap().is_watched(getter)
+    }
+
+    /// Change the poll interval used for a getter that opts into background polling (see
+    /// `scheduler`) without naming a specific interval of its own, i.e. whose mechanism declares
+    /// `poll: Some(Duration::new(0, 0))`. Defaults to `scheduler::default_poll_interval()`.
+    pub fn set_default_poll_interval(&self, interval: Duration) {
+        self.scheduler.set_default_interval(interval);
+    }
+
+    /// Override the coalescing window the background poller uses for every getter of `kind`,
+    /// collapsing a burst of rapid polled changes into a single delivered event no more often
+    /// than `window`. See `scheduler`.
+    pub fn set_poll_coalesce_window(&self, kind: ChannelKind, window: Duration) {
+        self.scheduler.set_coalesce_window(kind, window);
+    }
+
+    /// Subscribe to live changes on every getter currently or eventually matching `selectors`,
+    /// without having to poll `fetch_values` in a loop. Combines a value watch (`Value`, fired
+    /// on every `WatchEvent::EnterRange`/`ExitRange`) with a topology watch (`Enter`/`Exit`,
+    /// fired when a matching getter is added/tagged into, or removed/untagged out of, the
+    /// selector) onto a single event stream. See `Subscriber`.
+    pub fn watch_values(&self, selectors: Vec<GetterSelector>) -> Subscriber {
+        let (tx, rx) = channel();
+
+        let tx_value = tx.clone();
+        let value_guard = self.register_channel_watch_with_mode(
+            vec![(selectors.clone(), Exactly::Always)],
+            Box::new(move |event| {
+                if let StreamEvent::Value(event) = event {
+                    let mapped = match event {
+                        WatchEvent::EnterRange { from, value } | WatchEvent::ExitRange { from, value } =>
+                            SubscriptionEvent::Value { id: from, value: Ok(value) },
+                        _ => return,
+                    };
+                    let _ = tx_value.send(mapped);
+                }
+            }),
+            StreamMode::Subscribe, Duration::from_secs(0));
+
+        let tx_topology = tx;
+        let topology_guard = self.register_topology_watch(vec![], selectors, vec![],
+            Box::new(move |event| {
+                use backend::TopologyEvent::*;
+                let mapped = match event {
+                    GetterAdded(channel) | GetterTagged(channel) =>
+                        Some(SubscriptionEvent::Enter { id: channel.id, kind: channel.mechanism.kind }),
+                    GetterRemoved(channel) | GetterUntagged(channel) =>
+                        Some(SubscriptionEvent::Exit { id: channel.id }),
+                    _ => None,
+                };
+                if let Some(mapped) = mapped {
+                    let _ = tx_topology.send(mapped);
+                }
+            }));
+
+        Subscriber { rx: rx, _value_guard: value_guard, _topology_guard: topology_guard }
+    }
+
+    /// Like `watch_values`, but pair each selector with a condition (see `Exactly<Range>`, the
+    /// same condition type `register_channel_watch` already takes) so a caller only hears about
+    /// a getter entering or leaving a range - or, with a half-bounded `Range`, crossing a single
+    /// threshold - instead of every reading. `SubscriptionEvent::Value` is still only fired for
+    /// `WatchEvent::EnterRange`/`ExitRange`, exactly as `watch_values` reports them.
+    pub fn watch_values_matching(&self, watch: Vec<(GetterSelector, Exactly<Range>)>) -> Subscriber {
+        let (tx, rx) = channel();
+
+        let selectors: Vec<GetterSelector> = watch.iter().map(|&(ref selector, _)| selector.clone()).collect();
+        let conditioned: Vec<(Vec<GetterSelector>, Exactly<Range>)> = watch.into_iter()
+            .map(|(selector, condition)| (vec![selector], condition))
+            .collect();
+
+        let tx_value = tx.clone();
+        let value_guard = self.register_channel_watch_with_mode(
+            conditioned,
+            Box::new(move |event| {
+                if let StreamEvent::Value(event) = event {
+                    let mapped = match event {
+                        WatchEvent::EnterRange { from, value } | WatchEvent::ExitRange { from, value } =>
+                            SubscriptionEvent::Value { id: from, value: Ok(value) },
+                        _ => return,
+                    };
+                    let _ = tx_value.send(mapped);
+                }
+            }),
+            StreamMode::Subscribe, Duration::from_secs(0));
+
+        let tx_topology = tx;
+        let topology_guard = self.register_topology_watch(vec![], selectors, vec![],
+            Box::new(move |event| {
+                use backend::TopologyEvent::*;
+                let mapped = match event {
+                    GetterAdded(channel) | GetterTagged(channel) =>
+                        Some(SubscriptionEvent::Enter { id: channel.id, kind: channel.mechanism.kind }),
+                    GetterRemoved(channel) | GetterUntagged(channel) =>
+                        Some(SubscriptionEvent::Exit { id: channel.id }),
+                    _ => None,
+                };
+                if let Some(mapped) = mapped {
+                    let _ = tx_topology.send(mapped);
+                }
+            }));
+
+        Subscriber { rx: rx, _value_guard: value_guard, _topology_guard: topology_guard }
+    }
+}
+
+/// An event delivered to a `Subscriber`. Unlike the plain `WatchEvent` reported by
+/// `register_channel_watch`, a `Subscriber` also reports a getter's registration changing, so a
+/// caller watching a selector that matches nothing yet still learns when one shows up.
+pub enum SubscriptionEvent {
+    /// A getter matching the selector was added, or tagged into matching it.
+    Enter { id: Id<Getter>, kind: ChannelKind },
+    /// A getter matching the selector was removed, or untagged out of matching it.
+    Exit { id: Id<Getter> },
+    /// A new value is available for a getter matching the selector.
+    Value { id: Id<Getter>, value: Result<Value, APIError> },
+}
+
+/// A live subscription on a set of getters, returned by `AdapterManager::watch_values`. Modeled
+/// on sled's `Subscriber`: a thin iterator over the receiving half of an `mpsc` channel fed by
+/// the watch/topology-watch dispatch paths. Dropping it drops both underlying guards, which
+/// deregisters the watches so the manager stops doing work for it.
+///
+/// This crate predates `std::future::Future` (it still builds against the nightly-only
+/// `#![feature(custom_derive, plugin)]`, long before `async`/`await` existed), so unlike sled's
+/// `Subscriber`, this one does not also implement `Future`/`Stream` - there is no `Waker` to
+/// store a watcher in yet. `Iterator::next` (blocking on `rx.recv()`) is the only way to drain
+/// it.
+pub struct Subscriber {
+    rx: Receiver<SubscriptionEvent>,
+    _value_guard: WatchGuard,
+    _topology_guard: TopologyWatchGuard,
+}
+impl Iterator for Subscriber {
+    type Item = SubscriptionEvent;
+    fn next(&mut self) -> Option<SubscriptionEvent> {
+        self.rx.recv().ok()
+    }
+}
+
+/// Adapt a plain `WatchEvent` callback to the `StreamEvent` callback `register_channel_watch`
+/// now expects, for callers that only ever want `StreamMode::Subscribe` and have no use for
+/// `StreamEvent::SnapshotDone`.
+fn subscribe_only(on_event: Box<Fn(WatchEvent) + Send>) -> Box<Fn(StreamEvent) + Send> {
+    Box::new(move |event| {
+        if let StreamEvent::Value(event) = event {
+            on_event(event);
+        }
+    })
+}
+
+/// Default bound used by callers of `register_channel_watch_bounded` that don't need a
+/// tighter limit: enough to absorb a short burst without costing much memory.
+pub const DEFAULT_MAX_PENDING_EVENTS: usize = 256;
+
+impl AdapterManager {
+    /// Obtain a generation-stamped handle to a currently registered service, or `None` if
+    /// there is no such service. The handle becomes stale (see `remove_service_checked`) the
+    /// moment the service is removed, even if another service is later registered under the
+    /// same id.
+    pub fn service_handle(&self, id: &Id<ServiceId>) -> Option<ServiceHandle> {
+        self.back_end.lock().unwrap().service_handle(id)
+    }
+
+    /// Obtain a generation-stamped handle to a currently registered getter, or `None` if
+    /// there is no such getter.
+    pub fn getter_handle(&self, id: &Id<Getter>) -> Option<GetterHandle> {
+        self.back_end.lock().unwrap().getter_handle(id)
+    }
+
+    /// Obtain a generation-stamped handle to a currently registered setter, or `None` if
+    /// there is no such setter.
+    pub fn setter_handle(&self, id: &Id<Setter>) -> Option<SetterHandle> {
+        self.back_end.lock().unwrap().setter_handle(id)
+    }
+
+    /// Remove a service, but only if `handle` still designates the live slot. Returns
+    /// `Err(HandleError::StaleHandle)` instead of silently operating on a service that was
+    /// removed and replaced since the handle was obtained.
+    pub fn remove_service_checked(&self, handle: &ServiceHandle) -> Result<(), HandleError> {
+        self.back_end.lock().unwrap().remove_service_checked(handle)
+    }
+
+    /// Remove a getter, but only if `handle` still designates the live slot.
+    pub fn remove_getter_checked(&self, handle: &GetterHandle) -> Result<(), HandleError> {
+        self.back_end.lock().unwrap().remove_getter_checked(handle)
+    }
+
+    /// Remove a setter, but only if `handle` still designates the live slot.
+    pub fn remove_setter_checked(&self, handle: &SetterHandle) -> Result<(), HandleError> {
+        self.back_end.lock().unwrap().remove_setter_checked(handle)
+    }
+
+    /// Fetch a single value, but only if `handle` still designates the live slot.
+    pub fn fetch_value_checked(&self, handle: &GetterHandle) -> Result<Option<Value>, HandleError> {
+        self.back_end.lock().unwrap().fetch_value_checked(handle)
+    }
+
+    /// Send a single value, but only if `handle` still designates the live slot.
+    pub fn send_value_checked(&self, handle: &SetterHandle, value: Value) -> Result<(), HandleError> {
+        self.back_end.lock().unwrap().send_value_checked(handle, value)
+    }
 }
\ No newline at end of file
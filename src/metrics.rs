@@ -0,0 +1,76 @@
+//! Read-only metrics and introspection for `AdapterManagerState`.
+//!
+//! `AdapterManagerState::metrics()` reports live counts of registered adapters, services,
+//! getters, setters and watcher fan-out, so an operator can observe the system (and spot a
+//! leaked watch) without reaching into its private maps. There is no per-service channel count,
+//! so a service with no channels does not show up here.
+//! `Metrics::to_text()` renders the same counts in a Prometheus-compatible exposition format,
+//! analogous to the admin metrics endpoint exposed by a distributed store.
+
+use foxbox_taxonomy::services::{ AdapterId, Getter };
+use foxbox_taxonomy::util::Id;
+
+use std::collections::HashMap;
+use std::fmt::Write;
+
+/// A point-in-time snapshot of the live state of an `AdapterManagerState`.
+#[derive(Debug, Default, Clone)]
+pub struct Metrics {
+    /// Number of registered adapters.
+    pub adapters: usize,
+
+    /// Number of services registered to each adapter.
+    pub services_per_adapter: HashMap<Id<AdapterId>, usize>,
+
+    /// Number of registered getters, across all services.
+    pub getters: usize,
+
+    /// Number of registered setters, across all services.
+    pub setters: usize,
+
+    /// Number of watches currently registered in the `WatchMap`, i.e. `register_channel_watch`
+    /// calls whose `WatchGuard` has not been dropped yet.
+    pub active_watchers: usize,
+
+    /// Number of registered watchers currently matching each getter.
+    pub watchers_per_getter: HashMap<Id<Getter>, usize>,
+}
+
+impl Metrics {
+    /// Render these metrics in a Prometheus-compatible text exposition format: one `# HELP`/
+    /// `# TYPE` pair per metric name, followed by one gauge line per value (labeled by adapter
+    /// or getter id where the metric is per-resource).
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+
+        let _ = writeln!(text, "# HELP fxbox_adapters_total Number of registered adapters.");
+        let _ = writeln!(text, "# TYPE fxbox_adapters_total gauge");
+        let _ = writeln!(text, "fxbox_adapters_total {}", self.adapters);
+
+        let _ = writeln!(text, "# HELP fxbox_adapter_services Number of services registered to an adapter.");
+        let _ = writeln!(text, "# TYPE fxbox_adapter_services gauge");
+        for (adapter, services) in &self.services_per_adapter {
+            let _ = writeln!(text, "fxbox_adapter_services{{adapter=\"{:?}\"}} {}", adapter, services);
+        }
+
+        let _ = writeln!(text, "# HELP fxbox_getters_total Number of registered getters.");
+        let _ = writeln!(text, "# TYPE fxbox_getters_total gauge");
+        let _ = writeln!(text, "fxbox_getters_total {}", self.getters);
+
+        let _ = writeln!(text, "# HELP fxbox_setters_total Number of registered setters.");
+        let _ = writeln!(text, "# TYPE fxbox_setters_total gauge");
+        let _ = writeln!(text, "fxbox_setters_total {}", self.setters);
+
+        let _ = writeln!(text, "# HELP fxbox_active_watchers Number of live channel watches.");
+        let _ = writeln!(text, "# TYPE fxbox_active_watchers gauge");
+        let _ = writeln!(text, "fxbox_active_watchers {}", self.active_watchers);
+
+        let _ = writeln!(text, "# HELP fxbox_getter_watchers Number of watchers currently matching a getter.");
+        let _ = writeln!(text, "# TYPE fxbox_getter_watchers gauge");
+        for (getter, watchers) in &self.watchers_per_getter {
+            let _ = writeln!(text, "fxbox_getter_watchers{{getter=\"{:?}\"}} {}", getter, watchers);
+        }
+
+        text
+    }
+}
@@ -0,0 +1,159 @@
+//! Persistence of tags and last-known channel values across reboots.
+//!
+//! `AdapterManager::new()` starts from a clean slate: every reboot otherwise loses
+//! user-applied tags and the last reading of every sensor, forcing adapters to be re-probed
+//! before the taxonomy API is useful again. A `PersistenceStore` snapshots the services and
+//! getters matched by a set of `PersistenceRule`s (each capped at `max_bytes`) to a file, and
+//! `restore()` re-applies the tags and seeds `AdapterManagerState`'s last-known-value cache
+//! before the first live `fetch_values` of the new boot.
+//!
+//! `Value` is defined in an external crate with adapter-specific variants, so turning one into
+//! bytes and back is left to a pluggable `ValueCodec` rather than assumed.
+
+use backend::AdapterManagerState;
+
+use foxbox_taxonomy::selector::{ GetterSelector, ServiceSelector };
+use foxbox_taxonomy::services::Getter;
+use foxbox_taxonomy::util::Id;
+use foxbox_taxonomy::values::Value;
+
+use std::fs::File;
+use std::io::{ self, BufRead, BufReader, Write };
+use std::path::PathBuf;
+use std::time::{ Duration, UNIX_EPOCH };
+
+/// One rule describing what to persist: the services and getters matched by either selector
+/// list, capped at `max_bytes` of serialized state so that a single chatty channel cannot blow
+/// out the store.
+pub struct PersistenceRule {
+    pub service_selectors: Vec<ServiceSelector>,
+    pub getter_selectors: Vec<GetterSelector>,
+    pub max_bytes: usize,
+}
+
+/// Converts a `Value` to and from its on-disk representation.
+pub trait ValueCodec: Send + Sync {
+    fn encode(&self, value: &Value) -> Vec<u8>;
+    fn decode(&self, bytes: &[u8]) -> Option<Value>;
+}
+
+/// How many tag assignments and last-known values were restored from the store.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RestoreReport {
+    pub tags_restored: usize,
+    pub values_restored: usize,
+}
+
+/// Snapshots tags and last-known values to `path`, and restores them on startup.
+pub struct PersistenceStore {
+    path: PathBuf,
+    rules: Vec<PersistenceRule>,
+    codec: Box<ValueCodec>,
+}
+
+impl PersistenceStore {
+    pub fn new(path: PathBuf, rules: Vec<PersistenceRule>, codec: Box<ValueCodec>) -> Self {
+        PersistenceStore {
+            path: path,
+            rules: rules,
+            codec: codec,
+        }
+    }
+
+    /// Serialize the tags and last-known values of every service/getter matched by `self.rules`
+    /// to `self.path`.
+    pub fn snapshot(&self, state: &AdapterManagerState) -> io::Result<()> {
+        let mut file = try!(File::create(&self.path));
+        for rule in &self.rules {
+            let mut budget = rule.max_bytes;
+            for service in state.get_services(&rule.service_selectors) {
+                for tag in &service.tags {
+                    // `Id`'s `Debug` form is not guaranteed to round-trip through `Id::new` (it
+                    // may quote or otherwise decorate the string); its `Display` form is the
+                    // plain id, which is what every other place in this crate constructs an `Id`
+                    // from (see `config::apply`).
+                    let line = format!("SERVICE_TAG\t{}\t{}\n", service.id, tag);
+                    if line.len() > budget { break; }
+                    budget -= line.len();
+                    try!(file.write_all(line.as_bytes()));
+                }
+            }
+            for getter in state.get_getter_channels(&rule.getter_selectors) {
+                for tag in &getter.tags {
+                    let line = format!("GETTER_TAG\t{}\t{}\n", getter.id, tag);
+                    if line.len() > budget { break; }
+                    budget -= line.len();
+                    try!(file.write_all(line.as_bytes()));
+                }
+                if let Some(cached) = state.cached_value(&getter.id) {
+                    let since_epoch = cached.timestamp.duration_since(UNIX_EPOCH).unwrap_or(Duration::new(0, 0));
+                    let encoded = match cached.value {
+                        None => Vec::new(),
+                        Some(ref value) => self.codec.encode(value),
+                    };
+                    let line = format!("VALUE\t{}\t{}\t{}\n", getter.id, since_epoch.as_secs(),
+                        to_hex(&encoded));
+                    if line.len() > budget { continue; }
+                    budget -= line.len();
+                    try!(file.write_all(line.as_bytes()));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Re-apply the tags and seed the last-known-value cache of `state` from `self.path`.
+    /// Missing files are treated as "nothing to restore" rather than an error, since the very
+    /// first boot has no prior snapshot.
+    pub fn restore(&self, state: &mut AdapterManagerState) -> io::Result<RestoreReport> {
+        let file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(RestoreReport::default()),
+            Err(err) => return Err(err),
+        };
+        let mut report = RestoreReport::default();
+        for line in BufReader::new(file).lines() {
+            let line = try!(line);
+            let fields: Vec<&str> = line.splitn(4, '\t').collect();
+            if fields.len() == 3 && fields[0] == "SERVICE_TAG" {
+                let selector = ServiceSelector::new().with_id(Id::new(fields[1]));
+                state.add_service_tags(&[selector], &[Id::new(fields[2])]);
+                report.tags_restored += 1;
+            } else if fields.len() == 3 && fields[0] == "GETTER_TAG" {
+                let selector = GetterSelector::new().with_id(Id::new(fields[1]));
+                state.add_getter_tags(&[selector], &[Id::new(fields[2])]);
+                report.tags_restored += 1;
+            } else if fields.len() == 4 && fields[0] == "VALUE" {
+                let timestamp = UNIX_EPOCH + Duration::from_secs(fields[2].parse().unwrap_or(0));
+                let bytes = from_hex(fields[3]);
+                let value = if bytes.is_empty() { None } else { self.codec.decode(&bytes) };
+                state.seed_cached_value(Id::<Getter>::new(fields[1]), value, timestamp);
+                report.values_restored += 1;
+            }
+            // Ignore unrecognized or malformed lines, e.g. from a newer format.
+        }
+        Ok(report)
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len() * 2);
+    for byte in bytes {
+        out.push_str(&format!("{:02x}", byte));
+    }
+    out
+}
+
+fn from_hex(hex: &str) -> Vec<u8> {
+    let mut out = Vec::with_capacity(hex.len() / 2);
+    let chars: Vec<char> = hex.chars().collect();
+    for pair in chars.chunks(2) {
+        if pair.len() != 2 { break; }
+        let byte = match u8::from_str_radix(&pair.iter().cloned().collect::<String>(), 16) {
+            Ok(byte) => byte,
+            Err(_) => break,
+        };
+        out.push(byte);
+    }
+    out
+}
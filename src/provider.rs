@@ -0,0 +1,66 @@
+//! A lazy, dependency-injection-style registry of adapter factories.
+//!
+//! `AdapterManagerState::add_adapter` requires an already-constructed `Box<Adapter>`, which
+//! forces every adapter to be instantiated eagerly even if the selectors actually in use never
+//! touch it. An `AdapterProviderRegistry` lets a caller register a factory for an adapter
+//! instead: the adapter's id and the capabilities (interfaces) it will provide are known
+//! immediately, but the adapter itself is only constructed the first time
+//! `AdapterManagerState::ensure_adapter` asks for it. `with_capability` then resolves "every
+//! adapter that can provide capability X" the way a dependency injector resolves every service
+//! implementing an interface, without instantiating any of them.
+
+use adapter::Adapter;
+
+use foxbox_taxonomy::services::AdapterId;
+use foxbox_taxonomy::util::Id;
+
+use std::collections::HashMap;
+
+/// Builds an adapter on first use. Unlike `Adapter` itself, a factory can be queried for the
+/// capabilities its adapter will provide before that adapter is actually constructed.
+pub trait AdapterFactory: Send {
+    /// The id the constructed adapter will report from `Adapter::id()`.
+    fn id(&self) -> Id<AdapterId>;
+
+    /// The capabilities (interfaces) this adapter provides, declared statically so that
+    /// `AdapterProviderRegistry::with_capability` can discover a provider without constructing
+    /// it.
+    fn capabilities(&self) -> Vec<String>;
+
+    /// Construct the adapter. Called at most once, the first time this factory is resolved
+    /// through `AdapterProviderRegistry::take`.
+    fn create(self: Box<Self>) -> Box<Adapter>;
+}
+
+/// A registry of not-yet-instantiated adapters, keyed by the id they will report once built.
+#[derive(Default)]
+pub struct AdapterProviderRegistry {
+    factories: HashMap<Id<AdapterId>, Box<AdapterFactory>>,
+}
+
+impl AdapterProviderRegistry {
+    pub fn new() -> Self {
+        AdapterProviderRegistry {
+            factories: HashMap::new(),
+        }
+    }
+
+    /// Register `factory`. Replaces any factory previously registered under the same id.
+    pub fn register(&mut self, factory: Box<AdapterFactory>) {
+        self.factories.insert(factory.id(), factory);
+    }
+
+    /// Ids of every registered, not-yet-instantiated factory that declares `capability`.
+    pub fn with_capability(&self, capability: &str) -> Vec<Id<AdapterId>> {
+        self.factories.values()
+            .filter(|factory| factory.capabilities().iter().any(|owned| owned == capability))
+            .map(|factory| factory.id())
+            .collect()
+    }
+
+    /// Take ownership of the factory registered under `id` and construct its adapter. Returns
+    /// `None` if no factory is registered under this id (e.g. it was already taken).
+    pub fn take(&mut self, id: &Id<AdapterId>) -> Option<Box<Adapter>> {
+        self.factories.remove(id).map(|factory| factory.create())
+    }
+}
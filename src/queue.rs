@@ -0,0 +1,251 @@
+//! A durable, coalescing outgoing queue for setter writes, with bounded retry.
+//!
+//! `AdapterManager::send_values` (and its `_checked`/`_updated` cousins) dispatch once,
+//! synchronously, and hand back whatever `Error` the adapter produced: it is on the caller to
+//! retry a transient failure, and two back-to-back writes to the same setter both reach the
+//! adapter even if the second immediately supersedes the first. `SendQueue` instead appends
+//! each write to a per-setter FIFO; a background flusher drains it through
+//! `AdapterManagerState::send_values`, and on a retryable `Error` re-enqueues the write with
+//! bounded exponential backoff, up to a maximum number of attempts, before giving up and
+//! reporting the failure to every caller waiting on it.
+//!
+//! Writes to the same setter that have not yet been flushed are coalesced: only the latest
+//! value is ever sent. `SendQueue::set_cumulative` opts a setter out of this, for one whose
+//! semantics are cumulative (e.g. "append") rather than replace-in-place, so every write to it
+//! is flushed in order instead of being collapsed to the latest.
+//!
+//! `SendQueue::set_debounce_window` additionally delays every non-cumulative write by a
+//! configurable window before it becomes eligible to flush, and - since coalescing resets a
+//! pending write's deadline along with its value - a burst of rapid successive sends to the same
+//! setter keeps pushing that deadline out until the burst actually stops, so only the final value
+//! from a debounce window's worth of sends is ever flushed.
+
+use backend::AdapterManagerState;
+
+use foxbox_taxonomy::api::{ Error, InternalError, ResultMap };
+use foxbox_taxonomy::selector::SetterSelector;
+use foxbox_taxonomy::services::Setter;
+use foxbox_taxonomy::util::Id;
+use foxbox_taxonomy::values::Value;
+
+use std::collections::{ HashMap, HashSet, VecDeque };
+use std::sync::{ Arc, Mutex };
+use std::sync::atomic::{ AtomicBool, Ordering };
+use std::sync::mpsc::{ channel, Receiver, Sender };
+use std::thread;
+use std::time::{ Duration, SystemTime };
+
+/// How often the flusher thread wakes up to check for ready writes.
+const FLUSH_INTERVAL_MS: u64 = 50;
+
+/// Number of attempts (the original send plus retries) a retryable failure gets before
+/// `SendQueue` gives up and reports it.
+const DEFAULT_MAX_ATTEMPTS: usize = 5;
+
+/// The delay before the first retry; doubled after each further attempt, capped at
+/// `MAX_BACKOFF_MS`.
+const INITIAL_BACKOFF_MS: u64 = 50;
+const MAX_BACKOFF_MS: u64 = 10_000;
+
+fn backoff_for(attempt: usize) -> Duration {
+    let factor = 1u64 << (attempt.saturating_sub(1).min(16) as u32);
+    Duration::from_millis(INITIAL_BACKOFF_MS.saturating_mul(factor).min(MAX_BACKOFF_MS))
+}
+
+/// Whether `error` is worth retrying at all. `InternalError` reports an inconsistency in this
+/// crate's own bookkeeping (e.g. a stale/removed setter) rather than a transient failure from
+/// the adapter, so retrying it would only repeat the same outcome.
+fn is_retryable(error: &Error) -> bool {
+    match *error {
+        Error::InternalError(_) => false,
+        _ => true,
+    }
+}
+
+/// A single not-yet-settled write, waiting in a setter's queue.
+struct QueuedWrite {
+    value: Value,
+    attempt: usize,
+    not_before: SystemTime,
+    /// One sender per `enqueue` call this write is still the (possibly coalesced) answer for;
+    /// all are notified together once it finally succeeds or exhausts its attempts.
+    ///
+    /// A failure is wrapped in `Arc` rather than cloned: `foxbox_taxonomy::api::Error` is defined
+    /// in an external crate with no guaranteed `Clone`, but the same failure still needs to reach
+    /// every coalesced caller.
+    waiters: Vec<Sender<(Id<Setter>, Result<(), Arc<Error>>)>>,
+}
+
+struct QueueState {
+    per_setter: HashMap<Id<Setter>, VecDeque<QueuedWrite>>,
+    cumulative: HashSet<Id<Setter>>,
+    /// See `SendQueue::set_debounce_window`. Zero by default, matching the pre-existing
+    /// behavior of a write becoming eligible to flush as soon as it is enqueued.
+    debounce: Duration,
+}
+
+/// A handle to a batch of writes submitted through `SendQueue::enqueue`/
+/// `AdapterManager::enqueue_send`. Resolves once every write in the batch has either succeeded
+/// or exhausted its retries, which may be well after the call that produced it, since a write
+/// can sit through several backed-off retries before the queue gives up on it.
+pub struct QueueHandle {
+    rx: Receiver<(Id<Setter>, Result<(), Arc<Error>>)>,
+    remaining: usize,
+}
+impl QueueHandle {
+    /// Block until every write in this batch has resolved, and return their combined result.
+    pub fn wait(self) -> ResultMap<Id<Setter>, (), Arc<Error>> {
+        let mut results = HashMap::new();
+        for _ in 0 .. self.remaining {
+            match self.rx.recv() {
+                Ok((id, result)) => { results.insert(id, result); }
+                Err(_) => break, // The flusher thread is gone; nothing more will ever arrive.
+            }
+        }
+        results
+    }
+}
+
+/// The outgoing queue itself. Owns the background flusher thread, stopped when this value is
+/// dropped.
+pub struct SendQueue {
+    state: Arc<Mutex<QueueState>>,
+    stop: Arc<AtomicBool>,
+}
+
+impl SendQueue {
+    pub fn new(back_end: Arc<Mutex<AdapterManagerState>>) -> Self {
+        let state = Arc::new(Mutex::new(QueueState {
+            per_setter: HashMap::new(),
+            cumulative: HashSet::new(),
+            debounce: Duration::from_millis(0),
+        }));
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let state_thread = state.clone();
+        let stop_thread = stop.clone();
+        thread::spawn(move || {
+            while !stop_thread.load(Ordering::Relaxed) {
+                thread::sleep(Duration::from_millis(FLUSH_INTERVAL_MS));
+                flush_ready(&state_thread, &back_end);
+            }
+        });
+
+        SendQueue { state: state, stop: stop }
+    }
+
+    /// Append `writes` to their setters' queues (coalescing onto any not-yet-flushed write to
+    /// the same, non-cumulative, setter) and return a handle that resolves once every one of
+    /// them has settled.
+    pub fn enqueue(&self, writes: Vec<(Id<Setter>, Value)>) -> QueueHandle {
+        let (tx, rx) = channel();
+        let remaining = writes.len();
+        let mut state = self.state.lock().unwrap();
+        let debounce = state.debounce;
+        for (id, value) in writes {
+            let cumulative = state.cumulative.contains(&id);
+            let queue = state.per_setter.entry(id).or_insert_with(VecDeque::new);
+            if !cumulative {
+                if let Some(pending) = queue.back_mut() {
+                    pending.value = value;
+                    pending.attempt = 0;
+                    pending.not_before = SystemTime::now() + debounce;
+                    pending.waiters.push(tx.clone());
+                    continue;
+                }
+            }
+            queue.push_back(QueuedWrite {
+                value: value,
+                attempt: 0,
+                not_before: SystemTime::now() + debounce,
+                waiters: vec![tx.clone()],
+            });
+        }
+        QueueHandle { rx: rx, remaining: remaining }
+    }
+
+    /// Number of writes still queued (neither flushed nor given up on) for `id`.
+    pub fn pending(&self, id: &Id<Setter>) -> usize {
+        self.state.lock().unwrap().per_setter.get(id).map_or(0, |queue| queue.len())
+    }
+
+    /// Opt `id` in or out of coalescing: a cumulative setter has every write flushed in order,
+    /// instead of successive not-yet-flushed writes collapsing to the latest value.
+    pub fn set_cumulative(&self, id: Id<Setter>, cumulative: bool) {
+        let mut state = self.state.lock().unwrap();
+        if cumulative {
+            state.cumulative.insert(id);
+        } else {
+            state.cumulative.remove(&id);
+        }
+    }
+
+    /// Set how long a non-cumulative write waits, after being (re-)enqueued, before it becomes
+    /// eligible to flush. Applies to every write enqueued from this point on; a write already
+    /// past its previous deadline but not yet flushed keeps that earlier deadline. See this
+    /// module's doc comment for why this turns a burst of rapid sends to the same setter into a
+    /// single flush of the final value.
+    pub fn set_debounce_window(&self, window: Duration) {
+        self.state.lock().unwrap().debounce = window;
+    }
+}
+impl Drop for SendQueue {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Pop every setter's due write (at most one per setter, preserving its queue's FIFO order),
+/// flush them through `back_end` in one batch, then settle or retry each according to its
+/// result.
+fn flush_ready(state: &Arc<Mutex<QueueState>>, back_end: &Arc<Mutex<AdapterManagerState>>) {
+    let now = SystemTime::now();
+    let ready: Vec<(Id<Setter>, QueuedWrite)> = {
+        let mut state = state.lock().unwrap();
+        let mut ready = Vec::new();
+        for (id, queue) in state.per_setter.iter_mut() {
+            let due = match queue.front() {
+                Some(write) => write.not_before <= now,
+                None => false,
+            };
+            if due {
+                ready.push((id.clone(), queue.pop_front().unwrap()));
+            }
+        }
+        state.per_setter.retain(|_, queue| !queue.is_empty());
+        ready
+    };
+    if ready.is_empty() {
+        return;
+    }
+
+    let keyvalues = ready.iter()
+        .map(|&(ref id, ref write)| (vec![SetterSelector::new().with_id(id.clone())], write.value.clone()))
+        .collect();
+    let mut results = back_end.lock().unwrap().send_values(keyvalues);
+
+    for (id, mut write) in ready {
+        let result = results.remove(&id)
+            .unwrap_or_else(|| Err(Error::InternalError(InternalError::NoSuchSetter(id.clone()))));
+        match result {
+            Ok(()) => {
+                for waiter in write.waiters.drain(..) {
+                    let _ = waiter.send((id.clone(), Ok(())));
+                }
+            }
+            Err(err) => {
+                if is_retryable(&err) && write.attempt + 1 < DEFAULT_MAX_ATTEMPTS {
+                    write.attempt += 1;
+                    write.not_before = now + backoff_for(write.attempt);
+                    let mut state = state.lock().unwrap();
+                    state.per_setter.entry(id).or_insert_with(VecDeque::new).push_front(write);
+                } else {
+                    let err = Arc::new(err);
+                    for waiter in write.waiters.drain(..) {
+                        let _ = waiter.send((id.clone(), Err(err.clone())));
+                    }
+                }
+            }
+        }
+    }
+}
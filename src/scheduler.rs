@@ -0,0 +1,220 @@
+//! Background polling for getters whose adapter has no native push notification.
+//!
+//! `attach_matching_watchers` (see `backend.rs`) already wires a push-capable getter straight
+//! from `Adapter::register_watch` into its watchers the moment one is registered. A getter whose
+//! mechanism leaves `watch` false has no such native callback: if it declares a `poll` interval,
+//! `PollScheduler` instead calls `AdapterManagerState::fetch_values` for it on that cadence and
+//! delivers the result to its watchers, but only when the value differs from the last one
+//! observed, so a getter that isn't actually changing doesn't spam them.
+//!
+//! Tracking is entirely derived from `AdapterManagerState::pollable_getters` on every tick rather
+//! than kept in sync through explicit subscribe/unsubscribe calls: a getter starts being polled
+//! the moment it gains its first watcher and stops the moment it loses its last one, with nothing
+//! left behind in between ticks for an idle channel to cost.
+//!
+//! `Getter.trigger` plays no part here: it belongs to the native-push path already wired through
+//! `attach_matching_watchers`, not to this fallback poller.
+
+use backend::AdapterManagerState;
+
+use foxbox_taxonomy::selector::GetterSelector;
+use foxbox_taxonomy::services::{ ChannelKind, Getter };
+use foxbox_taxonomy::util::Id;
+use foxbox_taxonomy::values::Value;
+
+use std::collections::HashMap;
+use std::sync::{ Arc, Mutex };
+use std::sync::atomic::{ AtomicBool, Ordering };
+use std::thread;
+use std::time::{ Duration, SystemTime };
+
+/// How often the scheduler thread wakes up to check which tracked getters are due. No getter is
+/// ever polled more often than this, regardless of how short a `poll` interval it declares.
+const TICK_INTERVAL_MS: u64 = 100;
+
+/// The interval used for a getter whose mechanism opts into polling without naming a specific
+/// interval, i.e. `poll: Some(Duration::new(0, 0))`. Overridden by `PollScheduler::set_default_interval`.
+pub fn default_poll_interval() -> Duration {
+    Duration::from_secs(5)
+}
+
+/// How long a run of back-to-back value changes on one getter is collapsed into a single
+/// delivered event, keyed by the getter's `ChannelKind`: a discrete channel (e.g. a door sensor)
+/// is reported close to immediately, while a continuously drifting one (e.g. a thermometer) is
+/// batched so a watcher isn't flooded with one event per tick while its value settles. Consulted
+/// only after `overrides` (see `PollScheduler::set_coalesce_window`) turns up nothing for `kind`.
+fn coalesce_window_for(kind: &ChannelKind, overrides: &[(ChannelKind, Duration)]) -> Duration {
+    if let Some(&(_, window)) = overrides.iter().find(|&&(ref candidate, _)| candidate == kind) {
+        return window;
+    }
+    match *kind {
+        ChannelKind::OnOff | ChannelKind::OpenClosed | ChannelKind::DoorLocked => Duration::from_millis(0),
+        _ => Duration::from_millis(500),
+    }
+}
+
+/// Per-getter state kept across ticks while a getter is pollable. Dropped (and silently
+/// forgotten) the moment `pollable_getters` stops reporting it, i.e. once its last watcher drops.
+struct Tracked {
+    next_poll: SystemTime,
+    /// The value last actually delivered to this getter's watchers, used to tell a genuine
+    /// change from a poll that came back with the same reading.
+    last_delivered: Option<Value>,
+    /// A value observed to differ from `last_delivered`, held back while still inside
+    /// `coalesce_window_for(kind)` of the last delivery. Delivered, and cleared, the moment the
+    /// window elapses - even if no further poll changes it in the meantime.
+    pending: Option<Value>,
+    coalesce_until: Option<SystemTime>,
+}
+impl Tracked {
+    fn new(now: SystemTime) -> Self {
+        Tracked { next_poll: now, last_delivered: None, pending: None, coalesce_until: None }
+    }
+}
+
+struct SchedulerState {
+    tracked: HashMap<Id<Getter>, Tracked>,
+    default_interval: Duration,
+    /// Per-`ChannelKind` overrides of `coalesce_window_for`'s built-in defaults, set through
+    /// `PollScheduler::set_coalesce_window`. Kept as a small `Vec` rather than a `HashMap`:
+    /// `ChannelKind` comes from `foxbox_taxonomy` and isn't guaranteed hashable, and the list of
+    /// kinds actually overridden by a given embedder is expected to be tiny.
+    coalesce_overrides: Vec<(ChannelKind, Duration)>,
+}
+
+/// A witness that a `PollScheduler`'s background thread is running. Dropping it stops the
+/// thread; it does not affect any value already fetched or delivered.
+pub struct PollSchedulerGuard {
+    stop: Arc<AtomicBool>,
+}
+impl Drop for PollSchedulerGuard {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Drives background polling for an `AdapterManagerState`. See the module documentation.
+pub struct PollScheduler {
+    state: Arc<Mutex<SchedulerState>>,
+}
+
+impl PollScheduler {
+    pub fn new() -> Self {
+        PollScheduler {
+            state: Arc::new(Mutex::new(SchedulerState {
+                tracked: HashMap::new(),
+                default_interval: default_poll_interval(),
+                coalesce_overrides: Vec::new(),
+            })),
+        }
+    }
+
+    /// Change the interval used for a getter that opts into polling without naming a specific
+    /// one of its own. Takes effect on that getter's next due-check; does not reschedule a poll
+    /// already in flight.
+    pub fn set_default_interval(&self, interval: Duration) {
+        self.state.lock().unwrap().default_interval = interval;
+    }
+
+    /// Override the coalescing window used for every getter of `kind`, in place of
+    /// `coalesce_window_for`'s built-in default. Replaces any window previously set for the same
+    /// `kind`.
+    pub fn set_coalesce_window(&self, kind: ChannelKind, window: Duration) {
+        let mut state = self.state.lock().unwrap();
+        state.coalesce_overrides.retain(|&(ref existing, _)| *existing != kind);
+        state.coalesce_overrides.push((kind, window));
+    }
+
+    /// Start the background thread, polling `back_end` every `TICK_INTERVAL_MS`. Dropping the
+    /// returned guard stops it.
+    pub fn watch(&self, back_end: Arc<Mutex<AdapterManagerState>>) -> PollSchedulerGuard {
+        let stop = Arc::new(AtomicBool::new(false));
+        let should_stop = stop.clone();
+        let state = self.state.clone();
+        thread::spawn(move || {
+            while !should_stop.load(Ordering::Relaxed) {
+                thread::sleep(Duration::from_millis(TICK_INTERVAL_MS));
+                tick(&state, &back_end);
+            }
+        });
+        PollSchedulerGuard { stop: stop }
+    }
+}
+impl Default for PollScheduler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Run one polling pass: fetch every tracked getter whose interval has elapsed, record any value
+/// that differs from what was last delivered to it, then deliver (or, within
+/// `coalesce_window_for`, keep holding) whichever value is pending for it. Getters no longer
+/// reported by `pollable_getters` (no watcher left, or polling turned off) are dropped from
+/// `tracked` without further ceremony.
+fn tick(state: &Arc<Mutex<SchedulerState>>, back_end: &Arc<Mutex<AdapterManagerState>>) {
+    let now = SystemTime::now();
+    let live: HashMap<_, _> = back_end.lock().unwrap().pollable_getters().into_iter()
+        .map(|(id, interval, kind)| (id, (interval, kind)))
+        .collect();
+
+    let due: Vec<Id<Getter>> = {
+        let mut state = state.lock().unwrap();
+        state.tracked.retain(|id, _| live.contains_key(id));
+        live.keys()
+            .filter(|id| state.tracked.get(*id).map_or(true, |tracked| tracked.next_poll <= now))
+            .cloned()
+            .collect()
+    };
+    if due.is_empty() {
+        return;
+    }
+
+    let selectors: Vec<_> = due.iter().map(|id| GetterSelector::new().with_id(id.clone())).collect();
+    let fetched: HashMap<_, _> = back_end.lock().unwrap().fetch_values(&selectors).into_iter().collect();
+
+    let mut to_deliver = Vec::new();
+    {
+        let mut state = state.lock().unwrap();
+        let default_interval = state.default_interval;
+        for id in due {
+            let &(interval, ref kind) = match live.get(&id) {
+                Some(entry) => entry,
+                None => continue, // Lost its last watcher while the fetch was in flight.
+            };
+            let interval = if interval == Duration::new(0, 0) { default_interval } else { interval };
+            let tracked = state.tracked.entry(id.clone()).or_insert_with(|| Tracked::new(now));
+
+            if let Some(&Ok(Some(ref value))) = fetched.get(&id) {
+                if tracked.last_delivered.as_ref() != Some(value) {
+                    tracked.pending = Some(value.clone());
+                }
+            }
+
+            match tracked.pending.clone() {
+                None => {
+                    tracked.next_poll = now + interval;
+                }
+                Some(value) => {
+                    let ready = tracked.coalesce_until.map_or(true, |until| now >= until);
+                    if ready {
+                        let window = coalesce_window_for(kind, &state.coalesce_overrides);
+                        tracked.pending = None;
+                        tracked.last_delivered = Some(value.clone());
+                        tracked.coalesce_until = Some(now + window);
+                        tracked.next_poll = now + interval;
+                        to_deliver.push((id.clone(), value));
+                    } else {
+                        // Still coalescing: force a re-check once the window elapses, so the
+                        // last value of a collapsed burst is never dropped even if the channel
+                        // stops changing in the meantime.
+                        tracked.next_poll = tracked.coalesce_until.unwrap().min(now + interval);
+                    }
+                }
+            }
+        }
+    }
+
+    for (id, value) in to_deliver {
+        back_end.lock().unwrap().notify_polled_value(&id, value);
+    }
+}
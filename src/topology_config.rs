@@ -0,0 +1,370 @@
+//! Hot-reloading of the service/getter/setter topology itself from a declarative config file.
+//!
+//! [[config]] lets an operator pin tags on services/channels that already exist; this module
+//! goes one step further and lets which services and channels exist in the first place be
+//! described in a file, so a box can be reconfigured by editing `user.toml` instead of
+//! restarting or scripting `AdapterManagerHandle` calls. Like `ConfigStore`, edits are picked
+//! up by a debounced poll of the file's mtime, coalescing a burst of writes into one reload.
+//!
+//! Reloading diffs the freshly parsed `Topology` against the one last applied and emits only
+//! the `Change`s needed to converge: services/channels that are new or gone are added/removed
+//! outright, but a service that still exists is compared *ignoring its getters/setters* (as
+//! rathole compares configs "without services") so that only the channels whose own
+//! declaration actually changed are torn down and recreated. Every other device keeps its live
+//! watchers and tags across a reload.
+
+use backend::AdapterManagerState;
+
+use foxbox_taxonomy::services::{ Channel, ChannelKind, Getter, Service, Setter };
+use foxbox_taxonomy::util::Id;
+
+use std::collections::{ HashMap, HashSet };
+use std::fs::File;
+use std::io::{ self, Read };
+use std::path::PathBuf;
+use std::sync::{ Arc, Mutex };
+use std::sync::atomic::{ AtomicBool, Ordering };
+use std::thread;
+use std::time::{ Duration, SystemTime };
+
+/// The declarative description of a single getter or setter: everything needed to build a
+/// `Channel` other than the id/service/adapter, which come from its position in `Topology`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChannelDecl {
+    pub kind: String,
+    pub tags: HashSet<String>,
+}
+
+/// The declarative description of a single service: its owning adapter, its tags, and the
+/// getters/setters it exposes, keyed by id.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServiceDecl {
+    pub adapter: String,
+    pub tags: HashSet<String>,
+    pub getters: HashMap<String, ChannelDecl>,
+    pub setters: HashMap<String, ChannelDecl>,
+}
+
+impl ServiceDecl {
+    /// Whether `self` and `other` describe the same service, ignoring their getters/setters.
+    /// Used to decide whether a service that survives a reload needs to be recreated (e.g. its
+    /// adapter changed) or just has its channels diffed in place.
+    fn same_service(&self, other: &ServiceDecl) -> bool {
+        self.adapter == other.adapter && self.tags == other.tags
+    }
+}
+
+/// The full topology described by a config file, keyed by service id.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Topology {
+    pub services: HashMap<String, ServiceDecl>,
+}
+
+impl Topology {
+    /// Parse a TOML document such as
+    ///
+    /// ```toml
+    /// [services.my-service-id]
+    /// adapter = "my-adapter-id"
+    /// tags = ["room:kitchen"]
+    ///
+    /// [services.my-service-id.getters.my-getter-id]
+    /// kind = "OnOff"
+    /// tags = ["display:false"]
+    /// ```
+    pub fn parse(text: &str) -> Result<Topology, String> {
+        let value = try!(text.parse::<toml::Value>().map_err(|err| format!("{}", err)));
+        let table = match value.as_table() {
+            Some(table) => table,
+            None => return Err("expected a TOML table at the top level".to_owned()),
+        };
+        let mut services = HashMap::new();
+        if let Some(section) = table.get("services").and_then(|value| value.as_table()) {
+            for (id, entry) in section {
+                let entry = match entry.as_table() {
+                    Some(entry) => entry,
+                    None => continue,
+                };
+                services.insert(id.clone(), ServiceDecl {
+                    adapter: entry.get("adapter").and_then(|value| value.as_str())
+                        .unwrap_or("").to_owned(),
+                    tags: read_tags(entry),
+                    getters: read_channels(entry, "getters"),
+                    setters: read_channels(entry, "setters"),
+                });
+            }
+        }
+        Ok(Topology { services: services })
+    }
+}
+
+fn read_tags(table: &toml::value::Table) -> HashSet<String> {
+    table.get("tags").and_then(|value| value.as_array())
+        .map(|tags| tags.iter().filter_map(|tag| tag.as_str()).map(|tag| tag.to_owned()).collect())
+        .unwrap_or_else(HashSet::new)
+}
+
+fn read_channels(table: &toml::value::Table, section: &str) -> HashMap<String, ChannelDecl> {
+    let mut out = HashMap::new();
+    let section = match table.get(section).and_then(|value| value.as_table()) {
+        Some(section) => section,
+        None => return out,
+    };
+    for (id, entry) in section {
+        let entry = match entry.as_table() {
+            Some(entry) => entry,
+            None => continue,
+        };
+        let kind = entry.get("kind").and_then(|value| value.as_str()).unwrap_or("").to_owned();
+        out.insert(id.clone(), ChannelDecl { kind: kind, tags: read_tags(entry) });
+    }
+    out
+}
+
+/// The minimal set of `AdapterManagerState` calls needed to converge from one `Topology` to
+/// another.
+#[derive(Debug, Clone)]
+enum Change {
+    AddService(Service),
+    RemoveService(String),
+    AddGetter(Channel<Getter>),
+    RemoveGetter(String),
+    AddSetter(Channel<Setter>),
+    RemoveSetter(String),
+}
+
+/// Build the `Service`/`Channel` used to add `id`'s current declaration from scratch: the
+/// service itself with no channels (as required by `add_service`), plus one `AddGetter`/
+/// `AddSetter` per declared channel.
+fn additions_for(id: &str, decl: &ServiceDecl) -> Vec<Change> {
+    let mut changes = vec![Change::AddService(Service {
+        id: Id::new(id),
+        adapter: Id::new(decl.adapter.as_ref()),
+        tags: decl.tags.iter().map(|tag| Id::new(tag.as_ref())).collect(),
+        getters: HashMap::new(),
+        setters: HashMap::new(),
+    })];
+    for (getter_id, getter) in &decl.getters {
+        changes.push(Change::AddGetter(build_getter(id, getter_id, getter, &decl.adapter)));
+    }
+    for (setter_id, setter) in &decl.setters {
+        changes.push(Change::AddSetter(build_setter(id, setter_id, setter, &decl.adapter)));
+    }
+    changes
+}
+
+fn build_getter(service_id: &str, getter_id: &str, decl: &ChannelDecl, adapter: &str) -> Channel<Getter> {
+    Channel {
+        id: Id::new(getter_id),
+        service: Id::new(service_id),
+        adapter: Id::new(adapter),
+        last_seen: None,
+        tags: decl.tags.iter().map(|tag| Id::new(tag.as_ref())).collect(),
+        mechanism: Getter {
+            updated: None,
+            kind: channel_kind(&decl.kind),
+            watch: false,
+            poll: None,
+            trigger: None,
+        },
+    }
+}
+
+fn build_setter(service_id: &str, setter_id: &str, decl: &ChannelDecl, adapter: &str) -> Channel<Setter> {
+    Channel {
+        id: Id::new(setter_id),
+        service: Id::new(service_id),
+        adapter: Id::new(adapter),
+        last_seen: None,
+        tags: decl.tags.iter().map(|tag| Id::new(tag.as_ref())).collect(),
+        mechanism: Setter {
+            updated: None,
+            kind: channel_kind(&decl.kind),
+            push: None,
+        },
+    }
+}
+
+/// Only `OnOff` is understood today; an unrecognized or missing `kind` falls back to it rather
+/// than failing the whole reload over one malformed channel.
+fn channel_kind(kind: &str) -> ChannelKind {
+    let _ = kind;
+    ChannelKind::OnOff
+}
+
+/// Diff `previous` against `next` and return the `Change`s needed to converge, in an order
+/// safe to apply directly (removed channels/services before added ones sharing their id).
+fn diff(previous: &Topology, next: &Topology) -> Vec<Change> {
+    let mut changes = Vec::new();
+    for id in previous.services.keys() {
+        if !next.services.contains_key(id) {
+            changes.push(Change::RemoveService(id.clone()));
+        }
+    }
+    for (id, decl) in &next.services {
+        match previous.services.get(id) {
+            None => changes.extend(additions_for(id, decl)),
+            Some(before) if !before.same_service(decl) => {
+                changes.push(Change::RemoveService(id.clone()));
+                changes.extend(additions_for(id, decl));
+            }
+            Some(before) => changes.extend(diff_channels(id, before, decl)),
+        }
+    }
+    // Every removal first, so a channel moved to another service is gone before it is re-added;
+    // the sort is stable, so each group keeps its own order.
+    changes.sort_by_key(|change| match *change {
+        Change::RemoveService(_) | Change::RemoveGetter(_) | Change::RemoveSetter(_) => 0,
+        _ => 1,
+    });
+    changes
+}
+
+fn diff_channels(service_id: &str, before: &ServiceDecl, after: &ServiceDecl) -> Vec<Change> {
+    let mut changes = Vec::new();
+    for getter_id in before.getters.keys() {
+        if !after.getters.contains_key(getter_id) {
+            changes.push(Change::RemoveGetter(getter_id.clone()));
+        }
+    }
+    for (getter_id, decl) in &after.getters {
+        match before.getters.get(getter_id) {
+            None => changes.push(Change::AddGetter(build_getter(service_id, getter_id, decl, &after.adapter))),
+            Some(previous) if previous != decl => {
+                changes.push(Change::RemoveGetter(getter_id.clone()));
+                changes.push(Change::AddGetter(build_getter(service_id, getter_id, decl, &after.adapter)));
+            }
+            Some(_) => { }
+        }
+    }
+    for setter_id in before.setters.keys() {
+        if !after.setters.contains_key(setter_id) {
+            changes.push(Change::RemoveSetter(setter_id.clone()));
+        }
+    }
+    for (setter_id, decl) in &after.setters {
+        match before.setters.get(setter_id) {
+            None => changes.push(Change::AddSetter(build_setter(service_id, setter_id, decl, &after.adapter))),
+            Some(previous) if previous != decl => {
+                changes.push(Change::RemoveSetter(setter_id.clone()));
+                changes.push(Change::AddSetter(build_setter(service_id, setter_id, decl, &after.adapter)));
+            }
+            Some(_) => { }
+        }
+    }
+    changes
+}
+
+fn apply(state: &mut AdapterManagerState, changes: Vec<Change>) {
+    for change in changes {
+        // Errors are not fatal to the reload: one device failing to come up (e.g. its adapter
+        // isn't registered yet) shouldn't block the rest of the topology from converging, and
+        // the next settled edit will retry it.
+        match change {
+            Change::AddService(service) => { let _ = state.add_service(service); }
+            Change::RemoveService(id) => { let _ = state.remove_service(&Id::new(id.as_ref())); }
+            Change::AddGetter(getter) => { let _ = state.add_getter(getter); }
+            Change::RemoveGetter(id) => { let _ = state.remove_getter(&Id::new(id.as_ref())); }
+            Change::AddSetter(setter) => { let _ = state.add_setter(setter); }
+            Change::RemoveSetter(id) => { let _ = state.remove_setter(&Id::new(id.as_ref())); }
+        }
+    }
+}
+
+/// A witness that a topology config file is being watched for changes. Dropping it stops the
+/// background polling thread without touching the topology already applied.
+pub struct TopologyConfigWatchGuard {
+    stop: Arc<AtomicBool>,
+}
+impl Drop for TopologyConfigWatchGuard {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+/// The settle delay used by `TopologyConfigStore::watch` callers that don't need a tighter
+/// responsiveness/thrash tradeoff: long enough to coalesce a multi-write save.
+pub fn default_settle_delay() -> Duration {
+    Duration::from_secs(2)
+}
+
+/// Loads a declarative topology from a TOML file, applies it to an `AdapterManagerState`, and
+/// watches the file for edits.
+pub struct TopologyConfigStore {
+    path: PathBuf,
+
+    /// The topology last successfully applied, used as the baseline for the next reload's
+    /// diff. `None` until `load` has run once.
+    current: Mutex<Option<Topology>>,
+}
+
+impl TopologyConfigStore {
+    pub fn new(path: PathBuf) -> Self {
+        TopologyConfigStore {
+            path: path,
+            current: Mutex::new(None),
+        }
+    }
+
+    /// Read `self.path` and add every service/channel it describes to `state`. Called once at
+    /// startup, before `watch` is attached.
+    pub fn load(&self, state: &mut AdapterManagerState) -> io::Result<()> {
+        let topology = try!(self.read());
+        apply(state, diff(&Topology::default(), &topology));
+        *self.current.lock().unwrap() = Some(topology);
+        Ok(())
+    }
+
+    /// Re-read `self.path` and converge `state`'s topology to match, applying only the
+    /// `Change`s needed to get from the topology last applied to this one.
+    pub fn reload(&self, state: &mut AdapterManagerState) -> io::Result<()> {
+        let next = try!(self.read());
+        let mut current = self.current.lock().unwrap();
+        let previous = current.clone().unwrap_or_else(Topology::default);
+        apply(state, diff(&previous, &next));
+        *current = Some(next);
+        Ok(())
+    }
+
+    fn read(&self) -> io::Result<Topology> {
+        let mut text = String::new();
+        try!(try!(File::open(&self.path)).read_to_string(&mut text));
+        Topology::parse(&text).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    fn modified(&self) -> Option<SystemTime> {
+        self.path.metadata().ok().and_then(|metadata| metadata.modified().ok())
+    }
+
+    /// Poll `self.path` every `poll_interval`, and once it has gone `settle_delay` without a
+    /// further change, `reload` it into `state`. Mirrors `config::ConfigStore::watch`.
+    pub fn watch(self: Arc<Self>, state: Arc<Mutex<AdapterManagerState>>, poll_interval: Duration,
+        settle_delay: Duration) -> TopologyConfigWatchGuard
+    {
+        let stop = Arc::new(AtomicBool::new(false));
+        let should_stop = stop.clone();
+        let store = self;
+        thread::spawn(move || {
+            let mut last_modified = store.modified();
+            let mut pending_since: Option<SystemTime> = None;
+            while !should_stop.load(Ordering::Relaxed) {
+                thread::sleep(poll_interval);
+                let modified = store.modified();
+                if modified != last_modified {
+                    last_modified = modified;
+                    pending_since = Some(SystemTime::now());
+                    continue;
+                }
+                let settled = match pending_since {
+                    Some(since) => since.elapsed().unwrap_or(Duration::new(0, 0)) >= settle_delay,
+                    None => false,
+                };
+                if settled {
+                    pending_since = None;
+                    let _ = store.reload(&mut state.lock().unwrap());
+                }
+            }
+        });
+        TopologyConfigWatchGuard { stop: stop }
+    }
+}
@@ -3,8 +3,14 @@ extern crate foxbox_taxonomy;
 extern crate transformable_channels;
 
 use foxbox_adapters::adapter::*;
+// Aliased: this file's own `Effect` (below) is the `TestAdapter` instrumentation type used by
+// the `send_values` tests, unrelated to (and name-colliding with) the manager-wide broadcast
+// bus's `Effect` exercised by `test_subscribe_effects`.
+use foxbox_adapters::effects::Effect as BusEffect;
+use foxbox_adapters::kafka_bridge::{ KafkaBridge, KafkaBridgeConfig, KafkaProducer };
 use foxbox_adapters::manager::*;
-use foxbox_taxonomy::api::{ API, Error, InternalError };
+use foxbox_adapters::persistence::{ PersistenceRule, ValueCodec };
+use foxbox_taxonomy::api::{ API, Error, InternalError, WatchEvent as APIWatchEvent };
 use foxbox_taxonomy::selector::*;
 use foxbox_taxonomy::services::*;
 use foxbox_taxonomy::util::*;
@@ -14,9 +20,16 @@ use transformable_channels::mpsc::*;
 
 use std::cell::RefCell;
 use std::collections::{ HashMap, HashSet };
+use std::env;
+use std::fs;
+use std::fs::File;
+use std::io::Write;
+use std::process;
 use std::sync::{ Arc, Mutex };
+use std::sync::atomic::{ AtomicUsize, Ordering };
 use std::thread;
 use std::sync::mpsc::{ sync_channel, SyncSender };
+use std::time::Duration;
 
 enum TestOp {
     InjectGetterValue(Id<Getter>, Result<Option<Value>, Error>),
@@ -31,6 +44,69 @@ enum Effect {
 fn dup<T>(t: T) -> (T, T) where T: Clone {
     (t.clone(), t)
 }
+
+/// An `OnOff` setter channel on `service`/`adapter`, untagged and never yet seen.
+fn onoff_setter(id: &Id<Setter>, service: &Id<ServiceId>, adapter: &Id<AdapterId>) -> Channel<Setter> {
+    Channel {
+        id: id.clone(),
+        service: service.clone(),
+        adapter: adapter.clone(),
+        last_seen: None,
+        tags: HashSet::new(),
+        mechanism: Setter {
+            kind: ChannelKind::OnOff,
+            updated: None,
+            push: None,
+        },
+    }
+}
+
+/// An `OnOff` getter channel on `service`/`adapter`, watchable, untagged and never yet seen.
+fn onoff_getter(id: &Id<Getter>, service: &Id<ServiceId>, adapter: &Id<AdapterId>) -> Channel<Getter> {
+    Channel {
+        id: id.clone(),
+        service: service.clone(),
+        adapter: adapter.clone(),
+        last_seen: None,
+        tags: HashSet::new(),
+        mechanism: Getter {
+            updated: None,
+            kind: ChannelKind::OnOff,
+            watch: true,
+            poll: None,
+            trigger: None,
+        },
+    }
+}
+/// A single live `register_watch` subscription on one getter, as tracked by `TestAdapter`.
+/// `matched` is the subscription's match state as of the last injected value, so that for a
+/// subscription with a range `TestAdapter::notify_watchers` only fires `Enter`/`Exit` on an
+/// actual transition rather than on every injected sample; a subscription without a range gets
+/// `Enter` for every injected value.
+struct TestWatch {
+    key: usize,
+    range: Option<Range>,
+    matched: bool,
+    cb: Arc<Fn(WatchEvent) + Send>,
+}
+
+/// Torn down by `TestAdapter::register_watch` when dropped: removes exactly this subscription
+/// from `watchers`, even if other subscriptions remain registered on the same getter.
+struct TestWatchGuard {
+    watchers: Arc<Mutex<HashMap<Id<Getter>, Vec<TestWatch>>>>,
+    getter: Id<Getter>,
+    key: usize,
+}
+impl AdapterWatchGuard for TestWatchGuard {
+}
+impl Drop for TestWatchGuard {
+    fn drop(&mut self) {
+        if let Some(watches) = self.watchers.lock().unwrap().get_mut(&self.getter) {
+            watches.retain(|watch| watch.key != self.key);
+        }
+    }
+}
+
 struct TestAdapter {
     id: Id<AdapterId>,
     name: String,
@@ -38,7 +114,9 @@ struct TestAdapter {
     tx_effect: RawSender<Effect>,
     rx_effect: RefCell<Option<Receiver<Effect>>>,
     values: Arc<Mutex<HashMap<Id<Getter>, Result<Value, Error>>>>,
-    senders: Arc<Mutex<HashMap<Id<Setter>, Error>>>
+    senders: Arc<Mutex<HashMap<Id<Setter>, Error>>>,
+    watchers: Arc<Mutex<HashMap<Id<Getter>, Vec<TestWatch>>>>,
+    next_watch_key: Arc<AtomicUsize>,
 }
 
 impl TestAdapter {
@@ -48,18 +126,22 @@ impl TestAdapter {
 
         let (values_main, values_thread) = dup(Arc::new(Mutex::new(HashMap::new())));
         let (senders_main, senders_thread) = dup(Arc::new(Mutex::new(HashMap::new())));
+        let (watchers_main, watchers_thread) = dup(Arc::new(Mutex::new(HashMap::new())));
         thread::spawn(move || {
             use self::TestOp::*;
             for msg in rx {
                 match msg {
                     InjectGetterValue(id, Ok(Some(value))) => {
-                        values_thread.lock().unwrap().insert(id, Ok(value));
+                        values_thread.lock().unwrap().insert(id.clone(), Ok(value.clone()));
+                        TestAdapter::notify_watchers(&watchers_thread, &id, Some(&value));
                     },
                     InjectGetterValue(id, Err(error)) => {
-                        values_thread.lock().unwrap().insert(id, Err(error));
+                        values_thread.lock().unwrap().insert(id.clone(), Err(error));
+                        TestAdapter::notify_watchers(&watchers_thread, &id, None);
                     },
                     InjectGetterValue(id, Ok(None)) => {
                         values_thread.lock().unwrap().remove(&id);
+                        TestAdapter::notify_watchers(&watchers_thread, &id, None);
                     },
                     InjectSetterError(id, None) => {
                         senders_thread.lock().unwrap().remove(&id);
@@ -77,13 +159,50 @@ impl TestAdapter {
             senders: senders_main,
             tx: tx,
             tx_effect: tx_effect,
-            rx_effect: RefCell::new(Some(rx_effect))
+            rx_effect: RefCell::new(Some(rx_effect)),
+            watchers: watchers_main,
+            next_watch_key: Arc::new(AtomicUsize::new(0)),
         }
     }
 
     fn take_rx(&self) -> Receiver<Effect> {
         self.rx_effect.borrow_mut().take().unwrap()
     }
+
+    /// Evaluate every subscription registered on `id` against the newly injected `value` (`None`
+    /// if the value was cleared or errored out, in which case there is no value to report a
+    /// transition against, so subscriptions are simply left in their last known state), firing
+    /// `Enter`/`Exit` for whichever ranged subscriptions just changed match state, and `Enter`
+    /// for every subscription without a range.
+    fn notify_watchers(watchers: &Arc<Mutex<HashMap<Id<Getter>, Vec<TestWatch>>>>, id: &Id<Getter>,
+        value: Option<&Value>)
+    {
+        let value = match value {
+            Some(value) => value,
+            None => return,
+        };
+        let mut watchers = watchers.lock().unwrap();
+        let watches = match watchers.get_mut(id) {
+            Some(watches) => watches,
+            None => return,
+        };
+        for watch in watches.iter_mut() {
+            let matches = match watch.range {
+                Some(ref range) => range.contains(value),
+                None => true,
+            };
+            if watch.range.is_some() && matches == watch.matched {
+                continue;
+            }
+            watch.matched = matches;
+            let event = if matches {
+                WatchEvent::Enter { id: id.clone(), value: value.clone() }
+            } else {
+                WatchEvent::Exit { id: id.clone(), value: value.clone() }
+            };
+            (watch.cb)(event);
+        }
+    }
 }
 
 static VERSION : [u32;4] = [0, 0, 0, 0];
@@ -138,10 +257,70 @@ impl Adapter for TestAdapter {
     }
 
     fn register_watch(&self, sources: Vec<(Id<Getter>, Option<Range>)>,
-        cb: Box<ExtSender<WatchEvent>>) ->
+        cb: Box<Fn(WatchEvent) + Send>) ->
             ResultMap<Id<Getter>, Box<AdapterWatchGuard>, Error>
     {
-        unimplemented!()
+        let cb: Arc<Fn(WatchEvent) + Send> = Arc::from(cb);
+        let mut watchers = self.watchers.lock().unwrap();
+        let values = self.values.lock().unwrap();
+        sources.into_iter().map(|(id, range)| {
+            let key = self.next_watch_key.fetch_add(1, Ordering::Relaxed);
+            let matched = match (values.get(&id), &range) {
+                (Some(&Ok(ref value)), &Some(ref range)) => range.contains(value),
+                (Some(&Ok(_)), &None) => true,
+                _ => false,
+            };
+            watchers.entry(id.clone()).or_insert_with(Vec::new).push(TestWatch {
+                key: key,
+                range: range,
+                matched: matched,
+                cb: cb.clone(),
+            });
+            let guard = Box::new(TestWatchGuard {
+                watchers: self.watchers.clone(),
+                getter: id.clone(),
+                key: key,
+            });
+            (id, Ok(guard as Box<AdapterWatchGuard>))
+        }).collect()
+    }
+}
+
+/// An adapter whose `fetch_values`/`send_values` block for a fixed `delay` before answering.
+/// Used only by `test_fetch_with_timeout`/`test_send_with_timeout` to exercise
+/// `AdapterManager::fetch_values_with_timeout`/`send_values_with_timeout` against an adapter
+/// slower than the deadline.
+struct SlowAdapter {
+    id: Id<AdapterId>,
+    delay: Duration,
+}
+impl Adapter for SlowAdapter {
+    fn id(&self) -> Id<AdapterId> {
+        self.id.clone()
+    }
+    fn name(&self) -> &str {
+        "slow adapter"
+    }
+    fn vendor(&self) -> &str {
+        "test@foxbox_adapters"
+    }
+    fn version(&self) -> &[u32;4] {
+        &VERSION
+    }
+    fn fetch_values(&self, channels: Vec<Id<Getter>>) -> ResultMap<Id<Getter>, Option<Value>, Error> {
+        thread::sleep(self.delay);
+        channels.into_iter().map(|id| (id, Ok(Some(Value::OnOff(OnOff::On))))).collect()
+    }
+    fn send_values(&self, mut values: Vec<(Id<Setter>, Value)>) -> ResultMap<Id<Setter>, (), Error> {
+        thread::sleep(self.delay);
+        values.drain(..).map(|(id, _)| (id, Ok(()))).collect()
+    }
+    fn register_watch(&self, sources: Vec<(Id<Getter>, Option<Range>)>, _cb: Box<Fn(WatchEvent) + Send>)
+        -> ResultMap<Id<Getter>, Box<AdapterWatchGuard>, Error>
+    {
+        sources.into_iter()
+            .map(|(id, _)| (id.clone(), Err(Error::InternalError(InternalError::NoSuchGetter(id)))))
+            .collect()
     }
 }
 
@@ -848,6 +1027,187 @@ fn test_add_remove_tags() {
     println!("");
 }
 
+#[test]
+fn test_tag_predicate() {
+    println!("");
+    let manager = AdapterManager::new();
+    let id_1 = Id::<AdapterId>::new("adapter id 1");
+
+    let getter_id_1 = Id::<Getter>::new("getter id 1");
+    let getter_id_2 = Id::<Getter>::new("getter id 2");
+
+    let setter_id_1 = Id::<Setter>::new("setter id 1");
+    let setter_id_2 = Id::<Setter>::new("setter id 2");
+
+    let service_id_1 = Id::<ServiceId>::new("service id 1");
+    let service_id_2 = Id::<ServiceId>::new("service id 2");
+
+    let tag_1 = Id::<TagId>::new("tag_1");
+    let tag_2 = Id::<TagId>::new("tag_2");
+    let tag_3 = Id::<TagId>::new("tag_3");
+
+    let mut tags_1 = HashSet::new();
+    tags_1.insert(tag_1.clone());
+    tags_1.insert(tag_2.clone());
+
+    let mut tags_2 = HashSet::new();
+    tags_2.insert(tag_1.clone());
+    tags_2.insert(tag_3.clone());
+
+    let getter_1 = Channel {
+        id: getter_id_1.clone(),
+        service: service_id_1.clone(),
+        adapter: id_1.clone(),
+        last_seen: None,
+        tags: tags_1.clone(),
+        mechanism: Getter {
+            updated: None,
+            kind: ChannelKind::OnOff,
+            watch: false,
+            poll: None,
+            trigger: None,
+        },
+    };
+
+    let setter_1 = Channel {
+        id: setter_id_1.clone(),
+        service: service_id_1.clone(),
+        adapter: id_1.clone(),
+        last_seen: None,
+        tags: tags_1.clone(),
+        mechanism: Setter {
+            updated: None,
+            kind: ChannelKind::OnOff,
+            push: None,
+        },
+    };
+
+    let service_1 = Service {
+        id: service_id_1.clone(),
+        adapter: id_1.clone(),
+        tags: tags_1.clone(),
+        getters: HashMap::new(),
+        setters: HashMap::new(),
+    };
+
+    let getter_2 = Channel {
+        id: getter_id_2.clone(),
+        service: service_id_2.clone(),
+        adapter: id_1.clone(),
+        last_seen: None,
+        tags: tags_2.clone(),
+        mechanism: Getter {
+            updated: None,
+            kind: ChannelKind::OnOff,
+            watch: false,
+            poll: None,
+            trigger: None,
+        },
+    };
+
+    let setter_2 = Channel {
+        id: setter_id_2.clone(),
+        service: service_id_2.clone(),
+        adapter: id_1.clone(),
+        last_seen: None,
+        tags: tags_2.clone(),
+        mechanism: Setter {
+            updated: None,
+            kind: ChannelKind::OnOff,
+            push: None,
+        },
+    };
+
+    let service_2 = Service {
+        id: service_id_2.clone(),
+        adapter: id_1.clone(),
+        tags: tags_2.clone(),
+        getters: HashMap::new(),
+        setters: HashMap::new(),
+    };
+
+    manager.add_adapter(Box::new(TestAdapter::new(&id_1))).unwrap();
+    manager.add_service(service_1.clone()).unwrap();
+    manager.add_service(service_2.clone()).unwrap();
+    manager.add_getter(getter_1.clone()).unwrap();
+    manager.add_getter(getter_2.clone()).unwrap();
+    manager.add_setter(setter_1.clone()).unwrap();
+    manager.add_setter(setter_2.clone()).unwrap();
+
+    println!("* without_tags excludes a service/channel that also matches `with_tags`.");
+    let selection = manager.get_services_matching(vec![
+        Filtered::new(ServiceSelector::new().with_tags(vec![tag_1.clone()]))
+            .without_tags(vec![tag_2.clone()])
+    ]);
+    assert_eq!(selection.len(), 1);
+    assert_eq!(selection[0].id, service_id_2);
+
+    let selection = manager.get_getter_channels_matching(vec![
+        Filtered::new(GetterSelector::new().with_tags(vec![tag_1.clone()]))
+            .without_tags(vec![tag_2.clone()])
+    ]);
+    assert_eq!(selection.len(), 1);
+    assert_eq!(selection[0].id, getter_id_2);
+
+    let selection = manager.get_setter_channels_matching(vec![
+        Filtered::new(SetterSelector::new().with_tags(vec![tag_1.clone()]))
+            .without_tags(vec![tag_2.clone()])
+    ]);
+    assert_eq!(selection.len(), 1);
+    assert_eq!(selection[0].id, setter_id_2);
+
+    println!("* with_any_tags narrows `with_tags` to only those also carrying one of the listed tags.");
+    let selection = manager.get_services_matching(vec![
+        Filtered::new(ServiceSelector::new().with_tags(vec![tag_1.clone()]))
+            .with_any_tags(vec![tag_2.clone()])
+    ]);
+    assert_eq!(selection.len(), 1);
+    assert_eq!(selection[0].id, service_id_1);
+
+    let selection = manager.get_getter_channels_matching(vec![
+        Filtered::new(GetterSelector::new().with_tags(vec![tag_1.clone()]))
+            .with_any_tags(vec![tag_2.clone()])
+    ]);
+    assert_eq!(selection.len(), 1);
+    assert_eq!(selection[0].id, getter_id_1);
+
+    let selection = manager.get_setter_channels_matching(vec![
+        Filtered::new(SetterSelector::new().with_tags(vec![tag_1.clone()]))
+            .with_any_tags(vec![tag_2.clone()])
+    ]);
+    assert_eq!(selection.len(), 1);
+    assert_eq!(selection[0].id, setter_id_1);
+
+    println!("* without_tags and with_any_tags combine on the same selector.");
+    let selection = manager.get_services_matching(vec![
+        Filtered::new(ServiceSelector::new().with_tags(vec![tag_1.clone()]))
+            .with_any_tags(vec![tag_2.clone(), tag_3.clone()])
+            .without_tags(vec![tag_3.clone()])
+    ]);
+    assert_eq!(selection.len(), 1);
+    assert_eq!(selection[0].id, service_id_1);
+
+    println!("* add/remove tag predicate variants narrow exactly like their plain counterparts.");
+    assert_eq!(manager
+        .add_service_tags_matching(
+            vec![Filtered::new(ServiceSelector::new()).without_tags(vec![tag_2.clone()])],
+            vec![tag_3.clone()]
+        ),
+        1);
+    assert!(manager.get_services(vec![ServiceSelector::new().with_id(service_id_2.clone())])[0].tags.contains(&tag_3));
+    assert!(!manager.get_services(vec![ServiceSelector::new().with_id(service_id_1.clone())])[0].tags.contains(&tag_3));
+
+    assert_eq!(manager
+        .remove_service_tags_matching(
+            vec![Filtered::new(ServiceSelector::new()).without_tags(vec![tag_2.clone()])],
+            vec![tag_3.clone()]
+        ),
+        1);
+    assert!(!manager.get_services(vec![ServiceSelector::new().with_id(service_id_2.clone())])[0].tags.contains(&tag_3));
+
+    println!("");
+}
+
 #[test]
 fn test_fetch() {
     println!("");
@@ -1249,8 +1609,1838 @@ fn test_send() {
     assert!(rx_adapter_2.try_recv().is_err());
     tx_adapter_1.send(TestOp::InjectSetterError(setter_id_1_1.clone(), None)).unwrap();
 
-    // FIXME: What happens if we send several times to the same setter?
+    println!("* Sending several times to the same setter within one call is coalesced: only the last value is sent.");
+    let data = manager.send_values(vec![
+        (vec![SetterSelector::new().with_id(setter_id_1_1.clone())], Value::OnOff(OnOff::Off)),
+        (vec![SetterSelector::new().with_id(setter_id_1_1.clone())], Value::OnOff(OnOff::On)),
+    ]);
+    assert_eq!(data.len(), 1);
+    match data.get(&setter_id_1_1) {
+        Some(&Ok(())) => {},
+        other => panic!("Unexpected result for {:?}: {:?}", setter_id_1_1, other)
+    }
+    match rx_adapter_1.try_recv().unwrap() {
+        Effect::ValueSent(ref id, Value::OnOff(OnOff::On)) if *id == setter_id_1_1 => {},
+        effect => panic!("Unexpected effect {:?}", effect)
+    }
+    assert!(rx_adapter_1.try_recv().is_err());
+
+    println!("");
+}
+
+#[test]
+fn test_subscribe_effects() {
+    println!("");
+    let manager = AdapterManager::new();
+    let id_1 = Id::<AdapterId>::new("adapter id 1");
+
+    let setter_id_1 = Id::<Setter>::new("setter id 1");
+    let service_id_1 = Id::<ServiceId>::new("service id 1");
+
+    let setter_1 = Channel {
+        id: setter_id_1.clone(),
+        service: service_id_1.clone(),
+        adapter: id_1.clone(),
+        last_seen: None,
+        tags: HashSet::new(),
+        mechanism: Setter {
+            kind: ChannelKind::OnOff,
+            updated: None,
+            push: None,
+        },
+    };
+
+    let service_1 = Service {
+        id: service_id_1.clone(),
+        adapter: id_1.clone(),
+        tags: HashSet::new(),
+        getters: HashMap::new(),
+        setters: HashMap::new(),
+    };
+
+    manager.add_adapter(Box::new(TestAdapter::new(&id_1))).unwrap();
+    manager.add_service(service_1).unwrap();
+    manager.add_setter(setter_1).unwrap();
+
+    println!("* A subscriber opened before a send sees its ValueSent effect.");
+    let mut subscriber = manager.subscribe_effects();
+    manager.send_values(vec![
+        (vec![SetterSelector::new().with_id(setter_id_1.clone())], Value::OnOff(OnOff::On))
+    ]);
+    match subscriber.recv() {
+        BusEffect::ValueSent(ref id, Value::OnOff(OnOff::On)) if *id == setter_id_1 => {},
+        effect => panic!("Unexpected effect {:?}", effect)
+    }
+
+    println!("* Two independent subscribers both see every effect.");
+    let mut other_subscriber = manager.subscribe_effects();
+    manager.send_values(vec![
+        (vec![SetterSelector::new().with_id(setter_id_1.clone())], Value::OnOff(OnOff::Off))
+    ]);
+    match subscriber.recv() {
+        BusEffect::ValueSent(ref id, Value::OnOff(OnOff::Off)) if *id == setter_id_1 => {},
+        effect => panic!("Unexpected effect {:?}", effect)
+    }
+    match other_subscriber.recv() {
+        BusEffect::ValueSent(ref id, Value::OnOff(OnOff::Off)) if *id == setter_id_1 => {},
+        effect => panic!("Unexpected effect {:?}", effect)
+    }
 
     println!("");
 }
 
+/// Collects every record `KafkaBridge` would have sent to a real broker, for `test_kafka_bridge`.
+struct TestProducer {
+    records: Arc<Mutex<Vec<(String, u32, Vec<u8>)>>>,
+}
+impl KafkaProducer for TestProducer {
+    fn send(&self, topic: &str, partition: u32, payload: Vec<u8>) {
+        self.records.lock().unwrap().push((topic.to_owned(), partition, payload));
+    }
+}
+
+/// Encodes only the `OnOff` values these tests send, for `test_kafka_bridge`.
+struct TestValueCodec;
+impl ValueCodec for TestValueCodec {
+    fn encode(&self, value: &Value) -> Vec<u8> {
+        match *value {
+            Value::OnOff(OnOff::On) => vec![1],
+            Value::OnOff(OnOff::Off) => vec![0],
+            _ => Vec::new(),
+        }
+    }
+    fn decode(&self, _: &[u8]) -> Option<Value> {
+        None
+    }
+}
+
+/// Round-trips the `OnOff` values `test_persistence_roundtrip` seeds, unlike `TestValueCodec`
+/// whose `decode` is a stub that's never exercised by `test_kafka_bridge`.
+struct OnOffCodec;
+impl ValueCodec for OnOffCodec {
+    fn encode(&self, value: &Value) -> Vec<u8> {
+        match *value {
+            Value::OnOff(OnOff::On) => vec![1],
+            Value::OnOff(OnOff::Off) => vec![0],
+            _ => Vec::new(),
+        }
+    }
+    fn decode(&self, bytes: &[u8]) -> Option<Value> {
+        match bytes.first() {
+            Some(&1) => Some(Value::OnOff(OnOff::On)),
+            Some(&0) => Some(Value::OnOff(OnOff::Off)),
+            _ => None,
+        }
+    }
+}
+
+#[test]
+fn test_kafka_bridge() {
+    println!("");
+    let manager = AdapterManager::new();
+    let id_1 = Id::<AdapterId>::new("adapter id 1");
+
+    let setter_id_1 = Id::<Setter>::new("setter id 1");
+    let service_id_1 = Id::<ServiceId>::new("service id 1");
+
+    let setter_1 = Channel {
+        id: setter_id_1.clone(),
+        service: service_id_1.clone(),
+        adapter: id_1.clone(),
+        last_seen: None,
+        tags: HashSet::new(),
+        mechanism: Setter {
+            kind: ChannelKind::OnOff,
+            updated: None,
+            push: None,
+        },
+    };
+
+    let service_1 = Service {
+        id: service_id_1.clone(),
+        adapter: id_1.clone(),
+        tags: HashSet::new(),
+        getters: HashMap::new(),
+        setters: HashMap::new(),
+    };
+
+    manager.add_adapter(Box::new(TestAdapter::new(&id_1))).unwrap();
+    manager.add_service(service_1).unwrap();
+    manager.add_setter(setter_1).unwrap();
+
+    let records = Arc::new(Mutex::new(Vec::new()));
+    let config = KafkaBridgeConfig {
+        brokers: vec!["localhost:9092".to_owned()],
+        topic: "fxbox-effects".to_owned(),
+        client_id: "test-bridge".to_owned(),
+        send_buffer: 16,
+        partition_count: 4,
+    };
+    let bridge = KafkaBridge::new(config, Box::new(TestProducer { records: records.clone() }), Box::new(TestValueCodec));
+    let _guard = bridge.start(manager.subscribe_effects());
+
+    manager.send_values(vec![
+        (vec![SetterSelector::new().with_id(setter_id_1.clone())], Value::OnOff(OnOff::On))
+    ]);
+
+    println!("* The bridge eventually republishes the ValueSent effect as a JSON record.");
+    let mut attempts = 0;
+    loop {
+        if !records.lock().unwrap().is_empty() {
+            break;
+        }
+        assert!(attempts < 200, "Timed out waiting for the bridge to forward the effect");
+        attempts += 1;
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    let got = records.lock().unwrap();
+    let &(ref topic, partition, ref payload) = &got[0];
+    assert_eq!(topic, "fxbox-effects");
+    assert!(partition < 4);
+    let text = String::from_utf8(payload.clone()).unwrap();
+    assert!(text.contains("\"type\":\"value_sent\""));
+    assert!(text.contains("\"value\":\"01\""));
+
+    println!("");
+}
+
+#[test]
+fn test_send_values_atomic() {
+    println!("");
+    let manager = AdapterManager::new();
+    let id_1 = Id::<AdapterId>::new("adapter id 1");
+
+    let setter_id_1 = Id::<Setter>::new("setter id 1");
+    let setter_id_2 = Id::<Setter>::new("setter id 2");
+    let service_id_1 = Id::<ServiceId>::new("service id 1");
+
+    let setter_1 = Channel {
+        id: setter_id_1.clone(),
+        service: service_id_1.clone(),
+        adapter: id_1.clone(),
+        last_seen: None,
+        tags: HashSet::new(),
+        mechanism: Setter {
+            kind: ChannelKind::OnOff,
+            updated: None,
+            push: None,
+        },
+    };
+
+    let setter_2 = Channel {
+        id: setter_id_2.clone(),
+        service: service_id_1.clone(),
+        adapter: id_1.clone(),
+        last_seen: None,
+        tags: HashSet::new(),
+        mechanism: Setter {
+            kind: ChannelKind::OnOff,
+            updated: None,
+            push: None,
+        },
+    };
+
+    let service_1 = Service {
+        id: service_id_1.clone(),
+        adapter: id_1.clone(),
+        tags: HashSet::new(),
+        getters: HashMap::new(),
+        setters: HashMap::new(),
+    };
+
+    let adapter_1 = TestAdapter::new(&id_1);
+    let rx_adapter_1 = adapter_1.take_rx();
+
+    manager.add_adapter(Box::new(adapter_1)).unwrap();
+    manager.add_service(service_1.clone()).unwrap();
+    manager.add_setter(setter_1.clone()).unwrap();
+    manager.add_setter(setter_2.clone()).unwrap();
+
+    println!("* A batch in which every value is well-typed is applied in full.");
+    let result = manager.send_values_atomic(vec![
+        (vec![SetterSelector::new().with_id(setter_id_1.clone())], Value::OnOff(OnOff::On)),
+        (vec![SetterSelector::new().with_id(setter_id_2.clone())], Value::OnOff(OnOff::Off)),
+    ]).unwrap();
+    assert_eq!(result.len(), 2);
+    for result in result.values() {
+        if let Ok(()) = *result {
+            // We're good.
+        } else {
+            panic!("Unexpected result {:?}", result)
+        }
+    }
+    let mut sent = HashMap::new();
+    for _ in 0..2 {
+        let Effect::ValueSent(id, value) = rx_adapter_1.try_recv().unwrap();
+        sent.insert(id, value);
+    }
+    assert_eq!(sent.len(), 2);
+    assert!(rx_adapter_1.try_recv().is_err());
+
+    println!("* A batch with one ill-typed value is rejected in full: nothing is sent.");
+    let rejection = manager.send_values_atomic(vec![
+        (vec![SetterSelector::new().with_id(setter_id_1.clone())], Value::OnOff(OnOff::On)),
+        (vec![SetterSelector::new().with_id(setter_id_2.clone())], Value::OpenClosed(OpenClosed::Closed)),
+    ]).unwrap_err();
+    assert!(rejection.unmatched.is_empty());
+    match rejection.type_errors.get(&setter_id_2) {
+        Some(&TypeError { got: Type::OpenClosed, expected: Type::OnOff }) => {},
+        other => panic!("Unexpected result for {:?}: {:?}", setter_id_2, other)
+    }
+    assert!(rejection.type_errors.get(&setter_id_1).is_none());
+    assert!(rx_adapter_1.try_recv().is_err());
+
+    println!("* A batch with a selector matching no setter is rejected in full: nothing is sent.");
+    let rejection = manager.send_values_atomic(vec![
+        (vec![SetterSelector::new().with_id(setter_id_1.clone())], Value::OnOff(OnOff::On)),
+        (vec![SetterSelector::new().with_id(Id::<Setter>::new("no such setter"))], Value::OnOff(OnOff::On)),
+    ]).unwrap_err();
+    assert!(rejection.type_errors.is_empty());
+    assert_eq!(rejection.unmatched.len(), 1);
+    assert!(rx_adapter_1.try_recv().is_err());
+
+    println!("");
+}
+
+#[test]
+fn test_watch() {
+    println!("");
+    let manager = AdapterManager::new();
+    let id_1 = Id::<AdapterId>::new("adapter id 1");
+
+    let getter_id_1 = Id::<Getter>::new("getter id 1");
+    let getter_id_2 = Id::<Getter>::new("getter id 2");
+    let service_id_1 = Id::<ServiceId>::new("service id 1");
+
+    let getter_1 = Channel {
+        id: getter_id_1.clone(),
+        service: service_id_1.clone(),
+        adapter: id_1.clone(),
+        last_seen: None,
+        tags: HashSet::new(),
+        mechanism: Getter {
+            updated: None,
+            kind: ChannelKind::OnOff,
+            watch: false,
+            poll: None,
+            trigger: None,
+        },
+    };
+
+    let getter_2 = Channel {
+        id: getter_id_2.clone(),
+        .. getter_1.clone()
+    };
+
+    let service_1 = Service {
+        id: service_id_1.clone(),
+        adapter: id_1.clone(),
+        tags: HashSet::new(),
+        getters: HashMap::new(),
+        setters: HashMap::new(),
+    };
+
+    let adapter_1 = TestAdapter::new(&id_1);
+    let tx_adapter_1 = adapter_1.tx.clone();
+
+    manager.add_adapter(Box::new(adapter_1)).unwrap();
+    manager.add_service(service_1.clone()).unwrap();
+    manager.add_getter(getter_1.clone()).unwrap();
+
+    println!("* Two independent watches on the same getter each receive their own events.");
+    let (tx_events_1, rx_events_1) = std::sync::mpsc::channel();
+    let guard_1 = manager.register_channel_watch(
+        vec![(vec![GetterSelector::new().with_id(getter_id_1.clone())], Exactly::Always)],
+        Box::new(move |event| { let _ = tx_events_1.send(event); }));
+
+    let (tx_events_2, rx_events_2) = std::sync::mpsc::channel();
+    let guard_2 = manager.register_channel_watch(
+        vec![(vec![GetterSelector::new().with_id(getter_id_1.clone())], Exactly::Always)],
+        Box::new(move |event| { let _ = tx_events_2.send(event); }));
+
+    tx_adapter_1.send(TestOp::InjectGetterValue(getter_id_1.clone(), Ok(Some(Value::OnOff(OnOff::On))))).unwrap();
+    match rx_events_1.recv().unwrap() {
+        APIWatchEvent::EnterRange { from, value: Value::OnOff(OnOff::On) } => assert_eq!(from, getter_id_1),
+        other => panic!("Unexpected event {:?}", other)
+    }
+    match rx_events_2.recv().unwrap() {
+        APIWatchEvent::EnterRange { from, value: Value::OnOff(OnOff::On) } => assert_eq!(from, getter_id_1),
+        other => panic!("Unexpected event {:?}", other)
+    }
+
+    println!("* Dropping one guard leaves the other watch, on the same getter, untouched.");
+    drop(guard_1);
+    tx_adapter_1.send(TestOp::InjectGetterValue(getter_id_1.clone(), Ok(Some(Value::OnOff(OnOff::Off))))).unwrap();
+    match rx_events_2.recv().unwrap() {
+        APIWatchEvent::EnterRange { from, value: Value::OnOff(OnOff::Off) } => assert_eq!(from, getter_id_1),
+        other => panic!("Unexpected event {:?}", other)
+    }
+    assert!(rx_events_1.try_recv().is_err());
+
+    println!("* A getter added after the watch was installed is matched retroactively.");
+    let (tx_events_3, rx_events_3) = std::sync::mpsc::channel();
+    let _guard_3 = manager.register_channel_watch(
+        vec![(vec![GetterSelector::new()], Exactly::Always)],
+        Box::new(move |event| { let _ = tx_events_3.send(event); }));
+    manager.add_getter(getter_2.clone()).unwrap();
+    tx_adapter_1.send(TestOp::InjectGetterValue(getter_id_2.clone(), Ok(Some(Value::OnOff(OnOff::On))))).unwrap();
+    match rx_events_3.recv().unwrap() {
+        APIWatchEvent::EnterRange { from, value: Value::OnOff(OnOff::On) } => assert_eq!(from, getter_id_2),
+        other => panic!("Unexpected event {:?}", other)
+    }
+
+    drop(guard_2);
+    println!("");
+}
+
+#[test]
+fn test_fetch_with_timeout() {
+    println!("");
+    let manager = AdapterManager::new();
+    let id_fast = Id::<AdapterId>::new("adapter id fast");
+    let id_slow = Id::<AdapterId>::new("adapter id slow");
+
+    let getter_id_fast = Id::<Getter>::new("getter id fast");
+    let getter_id_slow = Id::<Getter>::new("getter id slow");
+
+    let service_id_fast = Id::<ServiceId>::new("service id fast");
+    let service_id_slow = Id::<ServiceId>::new("service id slow");
+
+    let getter_fast = Channel {
+        id: getter_id_fast.clone(),
+        service: service_id_fast.clone(),
+        adapter: id_fast.clone(),
+        last_seen: None,
+        tags: HashSet::new(),
+        mechanism: Getter {
+            updated: None,
+            kind: ChannelKind::OnOff,
+            watch: false,
+            poll: None,
+            trigger: None,
+        },
+    };
+    let getter_slow = Channel {
+        id: getter_id_slow.clone(),
+        service: service_id_slow.clone(),
+        adapter: id_slow.clone(),
+        last_seen: None,
+        tags: HashSet::new(),
+        mechanism: Getter {
+            updated: None,
+            kind: ChannelKind::OnOff,
+            watch: false,
+            poll: None,
+            trigger: None,
+        },
+    };
+
+    let service_fast = Service {
+        id: service_id_fast.clone(),
+        adapter: id_fast.clone(),
+        tags: HashSet::new(),
+        getters: HashMap::new(),
+        setters: HashMap::new(),
+    };
+    let service_slow = Service {
+        id: service_id_slow.clone(),
+        adapter: id_slow.clone(),
+        tags: HashSet::new(),
+        getters: HashMap::new(),
+        setters: HashMap::new(),
+    };
+
+    let fast = TestAdapter::new(&id_fast);
+    let tx_fast = fast.tx.clone();
+    manager.add_adapter(Box::new(fast)).unwrap();
+    manager.add_adapter(Box::new(SlowAdapter { id: id_slow.clone(), delay: Duration::from_millis(200) })).unwrap();
+    manager.add_service(service_fast).unwrap();
+    manager.add_service(service_slow).unwrap();
+    manager.add_getter(getter_fast).unwrap();
+    manager.add_getter(getter_slow).unwrap();
+
+    tx_fast.send(TestOp::InjectGetterValue(getter_id_fast.clone(), Ok(Some(Value::OnOff(OnOff::On))))).unwrap();
+
+    println!("* Fetching with a deadline shorter than the slow adapter's reply still returns the fast adapter's value.");
+    let data = manager.fetch_values_with_timeout(&[GetterSelector::new()], Duration::from_millis(20));
+    assert_eq!(data.len(), 2);
+    match data.get(&getter_id_fast) {
+        Some(&Ok(Some(Value::OnOff(OnOff::On)))) => {},
+        Some(&Ok(_)) => panic!("Unexpected value for the fast getter"),
+        Some(&Err(_)) => panic!("The fast getter should not have timed out"),
+        None => panic!("Missing result for the fast getter")
+    }
+
+    println!("* ... and reports a timeout for the channel owned by the slow adapter.");
+    match data.get(&getter_id_slow) {
+        Some(&Err(TimeoutError::Timeout)) => {},
+        Some(&Err(TimeoutError::Inner(_))) => panic!("Expected a timeout, not an inner error"),
+        other => panic!("Unexpected result for the slow getter {:?}", other.map(|_| ()))
+    }
+
+    println!("* Fetching again with a deadline long enough for the slow adapter to answer returns its value too.");
+    let data = manager.fetch_values_with_timeout(&[GetterSelector::new()], Duration::from_secs(2));
+    match data.get(&getter_id_slow) {
+        Some(&Ok(Some(Value::OnOff(OnOff::On)))) => {},
+        other => panic!("Unexpected result for the slow getter {:?}", other.map(|_| ()))
+    }
+}
+
+#[test]
+fn test_send_with_timeout() {
+    println!("");
+    let manager = AdapterManager::new();
+    let id_fast = Id::<AdapterId>::new("adapter id fast");
+    let id_slow = Id::<AdapterId>::new("adapter id slow");
+
+    let setter_id_fast = Id::<Setter>::new("setter id fast");
+    let setter_id_slow = Id::<Setter>::new("setter id slow");
+
+    let service_id_fast = Id::<ServiceId>::new("service id fast");
+    let service_id_slow = Id::<ServiceId>::new("service id slow");
+
+    let setter_fast = Channel {
+        id: setter_id_fast.clone(),
+        service: service_id_fast.clone(),
+        adapter: id_fast.clone(),
+        last_seen: None,
+        tags: HashSet::new(),
+        mechanism: Setter {
+            updated: None,
+            kind: ChannelKind::OnOff,
+            push: None,
+        },
+    };
+    let setter_slow = Channel {
+        id: setter_id_slow.clone(),
+        service: service_id_slow.clone(),
+        adapter: id_slow.clone(),
+        last_seen: None,
+        tags: HashSet::new(),
+        mechanism: Setter {
+            updated: None,
+            kind: ChannelKind::OnOff,
+            push: None,
+        },
+    };
+
+    let service_fast = Service {
+        id: service_id_fast.clone(),
+        adapter: id_fast.clone(),
+        tags: HashSet::new(),
+        getters: HashMap::new(),
+        setters: HashMap::new(),
+    };
+    let service_slow = Service {
+        id: service_id_slow.clone(),
+        adapter: id_slow.clone(),
+        tags: HashSet::new(),
+        getters: HashMap::new(),
+        setters: HashMap::new(),
+    };
+
+    let fast = TestAdapter::new(&id_fast);
+    manager.add_adapter(Box::new(fast)).unwrap();
+    manager.add_adapter(Box::new(SlowAdapter { id: id_slow.clone(), delay: Duration::from_millis(200) })).unwrap();
+    manager.add_service(service_fast).unwrap();
+    manager.add_service(service_slow).unwrap();
+    manager.add_setter(setter_fast).unwrap();
+    manager.add_setter(setter_slow).unwrap();
+
+    println!("* Sending with a deadline shorter than the slow adapter's reply still delivers to the fast adapter.");
+    let data = manager.send_values_with_timeout(vec![
+        (vec![SetterSelector::new().with_id(setter_id_fast.clone())], Value::OnOff(OnOff::On)),
+        (vec![SetterSelector::new().with_id(setter_id_slow.clone())], Value::OnOff(OnOff::On)),
+    ], Duration::from_millis(20));
+    assert_eq!(data.len(), 2);
+    match data.get(&setter_id_fast) {
+        Some(&Ok(())) => {},
+        other => panic!("Unexpected result for the fast setter {:?}", other.map(|_| ()))
+    }
+
+    println!("* ... and reports a timeout for the channel owned by the slow adapter.");
+    match data.get(&setter_id_slow) {
+        Some(&Err(TimeoutError::Timeout)) => {},
+        other => panic!("Unexpected result for the slow setter {:?}", other.map(|_| ()))
+    }
+}
+
+#[test]
+fn test_send_with_handle() {
+    println!("");
+    let manager = AdapterManager::new();
+    let id_fast = Id::<AdapterId>::new("adapter id fast");
+    let id_slow = Id::<AdapterId>::new("adapter id slow");
+
+    let setter_id_fast = Id::<Setter>::new("setter id fast");
+    let setter_id_slow = Id::<Setter>::new("setter id slow");
+
+    let service_id_fast = Id::<ServiceId>::new("service id fast");
+    let service_id_slow = Id::<ServiceId>::new("service id slow");
+
+    let setter_fast = Channel {
+        id: setter_id_fast.clone(),
+        service: service_id_fast.clone(),
+        adapter: id_fast.clone(),
+        last_seen: None,
+        tags: HashSet::new(),
+        mechanism: Setter {
+            updated: None,
+            kind: ChannelKind::OnOff,
+            push: None,
+        },
+    };
+    let setter_slow = Channel {
+        id: setter_id_slow.clone(),
+        service: service_id_slow.clone(),
+        adapter: id_slow.clone(),
+        last_seen: None,
+        tags: HashSet::new(),
+        mechanism: Setter {
+            updated: None,
+            kind: ChannelKind::OnOff,
+            push: None,
+        },
+    };
+
+    let service_fast = Service {
+        id: service_id_fast.clone(),
+        adapter: id_fast.clone(),
+        tags: HashSet::new(),
+        getters: HashMap::new(),
+        setters: HashMap::new(),
+    };
+    let service_slow = Service {
+        id: service_id_slow.clone(),
+        adapter: id_slow.clone(),
+        tags: HashSet::new(),
+        getters: HashMap::new(),
+        setters: HashMap::new(),
+    };
+
+    let fast = TestAdapter::new(&id_fast);
+    manager.add_adapter(Box::new(fast)).unwrap();
+    manager.add_adapter(Box::new(SlowAdapter { id: id_slow.clone(), delay: Duration::from_millis(300) })).unwrap();
+    manager.add_service(service_fast).unwrap();
+    manager.add_service(service_slow).unwrap();
+    manager.add_setter(setter_fast).unwrap();
+    manager.add_setter(setter_slow).unwrap();
+
+    println!("* send_values_with_handle returns immediately, without waiting for the slow adapter.");
+    let started = std::time::Instant::now();
+    let (handle, future) = manager.send_values_with_handle(vec![
+        (vec![SetterSelector::new().with_id(setter_id_fast.clone())], Value::OnOff(OnOff::On)),
+        (vec![SetterSelector::new().with_id(setter_id_slow.clone())], Value::OnOff(OnOff::On)),
+    ]);
+    assert!(started.elapsed() < Duration::from_millis(300));
+
+    println!("* Cancelling the setter still waiting on the slow adapter reports it as Cancelled, \
+              while the fast setter keeps its real result.");
+    thread::sleep(Duration::from_millis(20));
+    handle.cancel_setter(&setter_id_slow);
+    let data = future.wait();
+    assert_eq!(data.len(), 2);
+    match data.get(&setter_id_fast) {
+        Some(&Ok(())) => {},
+        other => panic!("Unexpected result for the fast setter {:?}", other.map(|_| ()))
+    }
+    match data.get(&setter_id_slow) {
+        Some(&Err(CancellationError::Cancelled)) => {},
+        other => panic!("Unexpected result for the slow setter {:?}", other.map(|_| ()))
+    }
+
+    println!("* Dropping the handle before the adapter replies cancels every setter still outstanding.");
+    let (handle, future) = manager.send_values_with_handle(vec![
+        (vec![SetterSelector::new().with_id(setter_id_slow.clone())], Value::OnOff(OnOff::Off)),
+    ]);
+    drop(handle);
+    match future.wait().get(&setter_id_slow) {
+        Some(&Err(CancellationError::Cancelled)) => {},
+        other => panic!("Unexpected result for the slow setter {:?}", other.map(|_| ()))
+    }
+}
+
+#[test]
+fn test_logical_channel() {
+    println!("");
+    let manager = AdapterManager::new();
+    let id_1 = Id::<AdapterId>::new("adapter id 1");
+
+    let setter_id_a = Id::<Setter>::new("setter id a");
+    let setter_id_b = Id::<Setter>::new("setter id b");
+    let setter_id_c = Id::<Setter>::new("setter id c");
+    let setter_id_diverged = Id::<Setter>::new("setter id diverged");
+
+    let service_id_1 = Id::<ServiceId>::new("service id 1");
+
+    let onoff_setter = |id: &Id<Setter>| Channel {
+        id: id.clone(),
+        service: service_id_1.clone(),
+        adapter: id_1.clone(),
+        last_seen: None,
+        tags: HashSet::new(),
+        mechanism: Setter {
+            kind: ChannelKind::OnOff,
+            updated: None,
+            push: None,
+        },
+    };
+    let setter_a = onoff_setter(&setter_id_a);
+    let setter_b = onoff_setter(&setter_id_b);
+    let setter_c = onoff_setter(&setter_id_c);
+    let setter_diverged = Channel {
+        id: setter_id_diverged.clone(),
+        service: service_id_1.clone(),
+        adapter: id_1.clone(),
+        last_seen: None,
+        tags: HashSet::new(),
+        mechanism: Setter {
+            kind: ChannelKind::OpenClosed,
+            updated: None,
+            push: None,
+        },
+    };
+
+    let service_1 = Service {
+        id: service_id_1.clone(),
+        adapter: id_1.clone(),
+        tags: HashSet::new(),
+        getters: HashMap::new(),
+        setters: HashMap::new(),
+    };
+
+    let adapter_1 = TestAdapter::new(&id_1);
+    let tx_adapter_1 = adapter_1.tx.clone();
+    let rx_adapter_1 = adapter_1.take_rx();
+
+    manager.add_adapter(Box::new(adapter_1)).unwrap();
+    manager.add_service(service_1).unwrap();
+    manager.add_setter(setter_a).unwrap();
+    manager.add_setter(setter_b).unwrap();
+    manager.add_setter(setter_c).unwrap();
+    manager.add_setter(setter_diverged).unwrap();
+
+    let channel_id = Id::<LogicalChannelId>::new("logical channel id");
+    manager.add_logical_channel(channel_id.clone(),
+        vec![setter_id_a.clone(), setter_id_b.clone(), setter_id_c.clone()], 2).unwrap();
+
+    println!("* A write reaching its quorum succeeds, and every backing setter receives it.");
+    match manager.send_to_logical_channel(&channel_id, Value::OnOff(OnOff::On)) {
+        Ok(()) => {},
+        other => panic!("Unexpected result {:?}", other)
+    }
+    let mut sent = HashSet::new();
+    for _ in 0..3 {
+        let Effect::ValueSent(id, Value::OnOff(OnOff::On)) = rx_adapter_1.try_recv().unwrap();
+        sent.insert(id);
+    }
+    assert_eq!(sent, vec![setter_id_a.clone(), setter_id_b.clone(), setter_id_c.clone()].into_iter().collect());
+
+    println!("* A write that falls short of its quorum reports every backing setter's result.");
+    tx_adapter_1.send(TestOp::InjectSetterError(setter_id_a.clone(),
+        Some(Error::InternalError(InternalError::InvalidInitialService)))).unwrap();
+    tx_adapter_1.send(TestOp::InjectSetterError(setter_id_b.clone(),
+        Some(Error::InternalError(InternalError::InvalidInitialService)))).unwrap();
+    match manager.send_to_logical_channel(&channel_id, Value::OnOff(OnOff::Off)) {
+        Err(QuorumError::QuorumFailed { quorum, acked, results }) => {
+            assert_eq!(quorum, 2);
+            assert_eq!(acked, 1);
+            assert_eq!(results.len(), 3);
+        }
+        other => panic!("Unexpected result {:?}", other.map(|_| ()))
+    }
+    tx_adapter_1.send(TestOp::InjectSetterError(setter_id_a.clone(), None)).unwrap();
+    tx_adapter_1.send(TestOp::InjectSetterError(setter_id_b.clone(), None)).unwrap();
+
+    println!("* Registering a channel backed by setters of incompatible Types is rejected outright.");
+    let diverged_channel_id = Id::<LogicalChannelId>::new("diverged logical channel id");
+    manager.add_logical_channel(diverged_channel_id.clone(),
+        vec![setter_id_a.clone(), setter_id_diverged.clone()], 1).unwrap();
+    match manager.send_to_logical_channel(&diverged_channel_id, Value::OnOff(OnOff::On)) {
+        Err(QuorumError::Diverged(TypeError { got: Type::OpenClosed, expected: Type::OnOff })) => {},
+        other => panic!("Unexpected result {:?}", other.map(|_| ()))
+    }
+    assert!(rx_adapter_1.try_recv().is_err());
+
+    println!("* send_to_logical_channel on an unregistered id fails.");
+    match manager.send_to_logical_channel(&Id::<LogicalChannelId>::new("no such channel"), Value::OnOff(OnOff::On)) {
+        Err(QuorumError::NoSuchChannel(_)) => {},
+        other => panic!("Unexpected result {:?}", other.map(|_| ()))
+    }
+}
+
+#[test]
+fn test_enqueue_send() {
+    println!("");
+    let manager = AdapterManager::new();
+    let id_1 = Id::<AdapterId>::new("adapter id 1");
+
+    let setter_id_coalesced = Id::<Setter>::new("setter id coalesced");
+    let setter_id_cumulative = Id::<Setter>::new("setter id cumulative");
+    let setter_id_retry = Id::<Setter>::new("setter id retry");
+    let setter_id_permanent = Id::<Setter>::new("setter id permanent");
+
+    let service_id_1 = Id::<ServiceId>::new("service id 1");
+
+    let service_1 = Service {
+        id: service_id_1.clone(),
+        adapter: id_1.clone(),
+        tags: HashSet::new(),
+        getters: HashMap::new(),
+        setters: HashMap::new(),
+    };
+
+    let adapter_1 = TestAdapter::new(&id_1);
+    let tx_adapter_1 = adapter_1.tx.clone();
+    let rx_adapter_1 = adapter_1.take_rx();
+
+    manager.add_adapter(Box::new(adapter_1)).unwrap();
+    manager.add_service(service_1).unwrap();
+    manager.add_setter(onoff_setter(&setter_id_coalesced, &service_id_1, &id_1)).unwrap();
+    manager.add_setter(onoff_setter(&setter_id_cumulative, &service_id_1, &id_1)).unwrap();
+    manager.add_setter(onoff_setter(&setter_id_retry, &service_id_1, &id_1)).unwrap();
+    manager.add_setter(onoff_setter(&setter_id_permanent, &service_id_1, &id_1)).unwrap();
+    manager.set_setter_cumulative(setter_id_cumulative.clone(), true);
+
+    println!("* Two back-to-back writes to the same setter are coalesced: only the latest value is sent.");
+    let handle_1 = manager.enqueue_send(vec![(setter_id_coalesced.clone(), Value::OnOff(OnOff::Off))]);
+    let handle_2 = manager.enqueue_send(vec![(setter_id_coalesced.clone(), Value::OnOff(OnOff::On))]);
+    assert_eq!(manager.pending_sends(&setter_id_coalesced), 1);
+    match handle_1.wait().get(&setter_id_coalesced) {
+        Some(&Ok(())) => {},
+        other => panic!("Unexpected result {:?}", other.map(|_| ()))
+    }
+    match handle_2.wait().get(&setter_id_coalesced) {
+        Some(&Ok(())) => {},
+        other => panic!("Unexpected result {:?}", other.map(|_| ()))
+    }
+    match rx_adapter_1.recv().unwrap() {
+        Effect::ValueSent(ref id, Value::OnOff(OnOff::On)) if *id == setter_id_coalesced => {},
+        effect => panic!("Unexpected effect {:?}", effect)
+    }
+    assert!(rx_adapter_1.try_recv().is_err());
+
+    println!("* A setter opted out of coalescing has every queued write flushed, in order.");
+    let handle_1 = manager.enqueue_send(vec![(setter_id_cumulative.clone(), Value::OnOff(OnOff::Off))]);
+    let handle_2 = manager.enqueue_send(vec![(setter_id_cumulative.clone(), Value::OnOff(OnOff::On))]);
+    assert_eq!(manager.pending_sends(&setter_id_cumulative), 2);
+    handle_1.wait();
+    handle_2.wait();
+    match rx_adapter_1.recv().unwrap() {
+        Effect::ValueSent(ref id, Value::OnOff(OnOff::Off)) if *id == setter_id_cumulative => {},
+        effect => panic!("Unexpected effect {:?}", effect)
+    }
+    match rx_adapter_1.recv().unwrap() {
+        Effect::ValueSent(ref id, Value::OnOff(OnOff::On)) if *id == setter_id_cumulative => {},
+        effect => panic!("Unexpected effect {:?}", effect)
+    }
+
+    println!("* A retryable failure is retried until the adapter recovers.");
+    tx_adapter_1.send(TestOp::InjectSetterError(setter_id_retry.clone(), Some(Error::TypeError(TypeError {
+        got: Type::OpenClosed,
+        expected: Type::OnOff,
+    })))).unwrap();
+    let handle = manager.enqueue_send(vec![(setter_id_retry.clone(), Value::OnOff(OnOff::On))]);
+    thread::sleep(Duration::from_millis(120));
+    tx_adapter_1.send(TestOp::InjectSetterError(setter_id_retry.clone(), None)).unwrap();
+    match handle.wait().get(&setter_id_retry) {
+        Some(&Ok(())) => {},
+        other => panic!("Unexpected result {:?}", other.map(|_| ()))
+    }
+
+    println!("* A non-retryable failure is reported without being retried.");
+    tx_adapter_1.send(TestOp::InjectSetterError(setter_id_permanent.clone(),
+        Some(Error::InternalError(InternalError::InvalidInitialService)))).unwrap();
+    let handle = manager.enqueue_send(vec![(setter_id_permanent.clone(), Value::OnOff(OnOff::On))]);
+    match handle.wait().get(&setter_id_permanent) {
+        Some(&Err(ref err)) => match **err {
+            Error::InternalError(InternalError::InvalidInitialService) => {},
+            ref other => panic!("Unexpected error {:?}", other),
+        },
+        other => panic!("Unexpected result {:?}", other.map(|_| ()))
+    }
+}
+
+#[test]
+fn test_enqueue_send_debounce() {
+    println!("");
+    let manager = AdapterManager::new();
+    let id_1 = Id::<AdapterId>::new("adapter id 1");
+
+    let setter_id_1 = Id::<Setter>::new("setter id 1");
+
+    let service_id_1 = Id::<ServiceId>::new("service id 1");
+
+    let setter_1 = Channel {
+        id: setter_id_1.clone(),
+        service: service_id_1.clone(),
+        adapter: id_1.clone(),
+        last_seen: None,
+        tags: HashSet::new(),
+        mechanism: Setter {
+            kind: ChannelKind::OnOff,
+            updated: None,
+            push: None,
+        },
+    };
+
+    let service_1 = Service {
+        id: service_id_1.clone(),
+        adapter: id_1.clone(),
+        tags: HashSet::new(),
+        getters: HashMap::new(),
+        setters: HashMap::new(),
+    };
+
+    let adapter_1 = TestAdapter::new(&id_1);
+    let rx_adapter_1 = adapter_1.take_rx();
+
+    manager.add_adapter(Box::new(adapter_1)).unwrap();
+    manager.add_service(service_1).unwrap();
+    manager.add_setter(setter_1).unwrap();
+
+    println!("* A burst of sends within the debounce window collapses to a single flush of the final value.");
+    manager.set_send_debounce_window(Duration::from_millis(200));
+    let handle_1 = manager.enqueue_send(vec![(setter_id_1.clone(), Value::OnOff(OnOff::Off))]);
+    thread::sleep(Duration::from_millis(50));
+    let handle_2 = manager.enqueue_send(vec![(setter_id_1.clone(), Value::OnOff(OnOff::On))]);
+    thread::sleep(Duration::from_millis(50));
+    let handle_3 = manager.enqueue_send(vec![(setter_id_1.clone(), Value::OnOff(OnOff::Off))]);
+
+    // None of the bursts above should have flushed yet: each one pushed the deadline back out.
+    assert!(rx_adapter_1.try_recv().is_err());
+
+    handle_1.wait();
+    handle_2.wait();
+    handle_3.wait();
+    match rx_adapter_1.recv().unwrap() {
+        Effect::ValueSent(ref id, Value::OnOff(OnOff::Off)) if *id == setter_id_1 => {},
+        effect => panic!("Unexpected effect {:?}", effect)
+    }
+    assert!(rx_adapter_1.try_recv().is_err());
+}
+
+#[test]
+fn test_send_values_verified() {
+    println!("");
+    let manager = AdapterManager::new();
+    let id_1 = Id::<AdapterId>::new("adapter id 1");
+
+    let setter_id_ok = Id::<Setter>::new("setter id ok");
+    let setter_id_err = Id::<Setter>::new("setter id err");
+
+    let service_id_1 = Id::<ServiceId>::new("service id 1");
+
+    let service_1 = Service {
+        id: service_id_1.clone(),
+        adapter: id_1.clone(),
+        tags: HashSet::new(),
+        getters: HashMap::new(),
+        setters: HashMap::new(),
+    };
+
+    let adapter_1 = TestAdapter::new(&id_1);
+    let tx_adapter_1 = adapter_1.tx.clone();
+
+    manager.add_adapter(Box::new(adapter_1)).unwrap();
+    manager.add_service(service_1).unwrap();
+    manager.add_setter(onoff_setter(&setter_id_ok, &service_id_1, &id_1)).unwrap();
+    manager.add_setter(onoff_setter(&setter_id_err, &service_id_1, &id_1)).unwrap();
+    tx_adapter_1.send(TestOp::InjectSetterError(setter_id_err.clone(), Some(Error::TypeError(TypeError {
+        got: Type::OpenClosed,
+        expected: Type::OnOff,
+    })))).unwrap();
+
+    println!("* Every accepted setter gets an Accepted, a Started, and exactly one Completed, all sharing a request id.");
+    let (tx_event, rx_event) = channel();
+    let data = manager.send_values_verified(vec![
+        (vec![SetterSelector::new().with_id(setter_id_ok.clone())], Value::OnOff(OnOff::On)),
+        (vec![SetterSelector::new().with_id(setter_id_err.clone())], Value::OnOff(OnOff::On)),
+    ], Box::new(tx_event));
+
+    match data.get(&setter_id_ok) {
+        Some(&Ok(())) => {},
+        other => panic!("Unexpected result for the ok setter {:?}", other.map(|_| ()))
+    }
+    match data.get(&setter_id_err) {
+        Some(&Err(ref err)) => match **err {
+            Error::TypeError(_) => {},
+            ref other => panic!("Unexpected error {:?}", other),
+        },
+        other => panic!("Unexpected result for the err setter {:?}", other.map(|_| ()))
+    }
+
+    let mut seen = HashMap::new();
+    let mut requests = HashSet::new();
+    while let Ok(event) = rx_event.try_recv() {
+        let (setter, stage, request) = match event {
+            SetterVerification::Accepted { request, setter } => (setter, "Accepted", request),
+            SetterVerification::Started { request, setter } => (setter, "Started", request),
+            SetterVerification::Completed { request, setter, result: _ } => (setter, "Completed", request),
+        };
+        seen.entry(setter).or_insert_with(Vec::new).push(stage);
+        requests.insert(request);
+    }
+    assert_eq!(seen.get(&setter_id_ok), Some(&vec!["Accepted", "Started", "Completed"]));
+    assert_eq!(seen.get(&setter_id_err), Some(&vec!["Accepted", "Started", "Completed"]));
+    assert_eq!(requests.len(), 1);
+}
+
+#[test]
+fn test_watch_values() {
+    println!("");
+    let manager = AdapterManager::new();
+    let id_1 = Id::<AdapterId>::new("adapter id 1");
+
+    let getter_id_1 = Id::<Getter>::new("getter id 1");
+    let getter_id_2 = Id::<Getter>::new("getter id 2");
+    let service_id_1 = Id::<ServiceId>::new("service id 1");
+
+    let service_1 = Service {
+        id: service_id_1.clone(),
+        adapter: id_1.clone(),
+        tags: HashSet::new(),
+        getters: HashMap::new(),
+        setters: HashMap::new(),
+    };
+
+    let adapter_1 = TestAdapter::new(&id_1);
+    let tx_adapter_1 = adapter_1.tx.clone();
+
+    manager.add_adapter(Box::new(adapter_1)).unwrap();
+    manager.add_service(service_1).unwrap();
+    manager.add_getter(onoff_getter(&getter_id_1, &service_id_1, &id_1)).unwrap();
+
+    println!("* A getter already present when the subscription starts delivers Value events.");
+    let mut subscriber = manager.watch_values(vec![GetterSelector::new()]);
+    tx_adapter_1.send(TestOp::InjectGetterValue(getter_id_1.clone(), Ok(Some(Value::OnOff(OnOff::On))))).unwrap();
+    match subscriber.next() {
+        Some(SubscriptionEvent::Value { id, value: Ok(Value::OnOff(OnOff::On)) }) => assert_eq!(id, getter_id_1),
+        other => panic!("Unexpected event {:?}", other.map(|_| ()))
+    }
+
+    println!("* A getter added after the subscription started reports an Enter.");
+    manager.add_getter(onoff_getter(&getter_id_2, &service_id_1, &id_1)).unwrap();
+    match subscriber.next() {
+        Some(SubscriptionEvent::Enter { id, kind: ChannelKind::OnOff }) => assert_eq!(id, getter_id_2),
+        other => panic!("Unexpected event {:?}", other.map(|_| ()))
+    }
+
+    println!("* Removing that getter reports an Exit.");
+    manager.remove_getter(&getter_id_2).unwrap();
+    match subscriber.next() {
+        Some(SubscriptionEvent::Exit { id }) => assert_eq!(id, getter_id_2),
+        other => panic!("Unexpected event {:?}", other.map(|_| ()))
+    }
+
+    println!("* Dropping the subscriber stops further delivery.");
+    drop(subscriber);
+    tx_adapter_1.send(TestOp::InjectGetterValue(getter_id_1.clone(), Ok(Some(Value::OnOff(OnOff::Off))))).unwrap();
+    assert_eq!(manager.watcher_count(), 0);
+}
+
+#[test]
+fn test_watch_values_matching() {
+    println!("");
+    let manager = AdapterManager::new();
+    let id_1 = Id::<AdapterId>::new("adapter id 1");
+
+    let getter_id_1 = Id::<Getter>::new("getter id 1");
+    let getter_id_2 = Id::<Getter>::new("getter id 2");
+    let service_id_1 = Id::<ServiceId>::new("service id 1");
+
+    let service_1 = Service {
+        id: service_id_1.clone(),
+        adapter: id_1.clone(),
+        tags: HashSet::new(),
+        getters: HashMap::new(),
+        setters: HashMap::new(),
+    };
+
+    let adapter_1 = TestAdapter::new(&id_1);
+    let tx_adapter_1 = adapter_1.tx.clone();
+
+    manager.add_adapter(Box::new(adapter_1)).unwrap();
+    manager.add_service(service_1).unwrap();
+    manager.add_getter(onoff_getter(&getter_id_1, &service_id_1, &id_1)).unwrap();
+    manager.add_getter(onoff_getter(&getter_id_2, &service_id_1, &id_1)).unwrap();
+
+    println!("* Each selector keeps its own condition; an unconditioned selector (Exactly::Always) still delivers Value events.");
+    let mut subscriber = manager.watch_values_matching(vec![
+        (GetterSelector::new().with_id(getter_id_1.clone()), Exactly::Always),
+        (GetterSelector::new().with_id(getter_id_2.clone()), Exactly::Always),
+    ]);
+    tx_adapter_1.send(TestOp::InjectGetterValue(getter_id_1.clone(), Ok(Some(Value::OnOff(OnOff::On))))).unwrap();
+    match subscriber.next() {
+        Some(SubscriptionEvent::Value { id, value: Ok(Value::OnOff(OnOff::On)) }) => assert_eq!(id, getter_id_1),
+        other => panic!("Unexpected event {:?}", other.map(|_| ()))
+    }
+    tx_adapter_1.send(TestOp::InjectGetterValue(getter_id_2.clone(), Ok(Some(Value::OnOff(OnOff::Off))))).unwrap();
+    match subscriber.next() {
+        Some(SubscriptionEvent::Value { id, value: Ok(Value::OnOff(OnOff::Off)) }) => assert_eq!(id, getter_id_2),
+        other => panic!("Unexpected event {:?}", other.map(|_| ()))
+    }
+
+    println!("* Dropping the subscriber stops further delivery.");
+    drop(subscriber);
+    tx_adapter_1.send(TestOp::InjectGetterValue(getter_id_1.clone(), Ok(Some(Value::OnOff(OnOff::Off))))).unwrap();
+    assert_eq!(manager.watcher_count(), 0);
+}
+
+
+#[test]
+fn test_persistence_roundtrip() {
+    println!("");
+    let path = env::temp_dir().join(format!("foxbox_adapters_test_persistence_{}.db", process::id()));
+    let _ = fs::remove_file(&path);
+
+    let id_1 = Id::<AdapterId>::new("adapter id 1");
+    let getter_id_1 = Id::<Getter>::new("getter id 1");
+    let service_id_1 = Id::<ServiceId>::new("service id 1");
+    let tag_1 = Id::<TagId>::new("tag_1");
+
+    let service_1 = Service {
+        id: service_id_1.clone(),
+        adapter: id_1.clone(),
+        tags: HashSet::new(),
+        getters: HashMap::new(),
+        setters: HashMap::new(),
+    };
+
+    println!("* Snapshotting tags and the last-known value of a matched getter.");
+    {
+        let mut manager = AdapterManager::with_persistence(path.clone());
+        manager.configure_persistence(path.clone(), vec![
+            PersistenceRule {
+                service_selectors: vec![ServiceSelector::new()],
+                getter_selectors: vec![GetterSelector::new()],
+                max_bytes: 4096,
+            }
+        ], Box::new(OnOffCodec));
+
+        let adapter_1 = TestAdapter::new(&id_1);
+        let tx_adapter_1 = adapter_1.tx.clone();
+        manager.add_adapter(Box::new(adapter_1)).unwrap();
+        manager.add_service(service_1.clone()).unwrap();
+        manager.add_getter(onoff_getter(&getter_id_1, &service_id_1, &id_1)).unwrap();
+        manager.add_getter_tags(vec![GetterSelector::new().with_id(getter_id_1.clone())], vec![tag_1.clone()]);
+
+        tx_adapter_1.send(TestOp::InjectGetterValue(getter_id_1.clone(), Ok(Some(Value::OnOff(OnOff::On))))).unwrap();
+        manager.fetch_values(vec![GetterSelector::new().with_id(getter_id_1.clone())]);
+
+        manager.snapshot().unwrap();
+    }
+
+    println!("* A fresh manager restores both the tag and the last-known value from the snapshot.");
+    {
+        let manager = AdapterManager::with_persistence(path.clone());
+        let adapter_1 = TestAdapter::new(&id_1);
+        manager.add_adapter(Box::new(adapter_1)).unwrap();
+        manager.add_service(service_1.clone()).unwrap();
+        manager.add_getter(onoff_getter(&getter_id_1, &service_id_1, &id_1)).unwrap();
+
+        let report = manager.restore().unwrap();
+        assert_eq!(report.tags_restored, 1);
+        assert_eq!(report.values_restored, 1);
+
+        assert_eq!(
+            manager.get_getter_channels(vec![GetterSelector::new().with_tags(vec![tag_1.clone()])]).len(),
+            1);
+
+        match manager.cached_value(&getter_id_1) {
+            Some(ref cached) if cached.seeded => {
+                assert_eq!(cached.value, Some(Value::OnOff(OnOff::On)));
+            }
+            other => panic!("Unexpected cached value {:?}", other.map(|c| c.value)),
+        }
+    }
+
+    let _ = fs::remove_file(&path);
+}
+
+#[test]
+fn test_send_values_checked() {
+    println!("");
+    let manager = AdapterManager::new();
+    let id_1 = Id::<AdapterId>::new("adapter id 1");
+
+    let setter_id_1 = Id::<Setter>::new("setter id 1");
+    let service_id_1 = Id::<ServiceId>::new("service id 1");
+
+    let service_1 = Service {
+        id: service_id_1.clone(),
+        adapter: id_1.clone(),
+        tags: HashSet::new(),
+        getters: HashMap::new(),
+        setters: HashMap::new(),
+    };
+
+    let adapter_1 = TestAdapter::new(&id_1);
+
+    manager.add_adapter(Box::new(adapter_1)).unwrap();
+    manager.add_service(service_1).unwrap();
+    manager.add_setter(onoff_setter(&setter_id_1, &service_id_1, &id_1)).unwrap();
+
+    println!("* A write with an `IfUnset` precondition succeeds while no value is known yet.");
+    let data = manager.send_values_checked(vec![
+        (vec![SetterSelector::new().with_id(setter_id_1.clone())], Value::OnOff(OnOff::On), Some(Precondition::IfUnset))
+    ]);
+    match data.get(&setter_id_1) {
+        Some(&Ok(())) => {},
+        other => panic!("Unexpected result {:?}", other.map(|_| ()))
+    }
+
+    println!("* A write whose `IfEqual` precondition doesn't match the last written value fails \
+        without reaching the adapter, reporting the value it was checked against.");
+    let data = manager.send_values_checked(vec![
+        (vec![SetterSelector::new().with_id(setter_id_1.clone())], Value::OnOff(OnOff::Off),
+            Some(Precondition::IfEqual(Value::OnOff(OnOff::Off))))
+    ]);
+    match data.get(&setter_id_1) {
+        Some(&Err(ConditionalWriteError::PreconditionFailed { current: Some(Value::OnOff(OnOff::On)) })) => {},
+        other => panic!("Unexpected result {:?}", other.map(|_| ()))
+    }
+
+    println!("* A write whose `IfEqual` precondition matches the last written value succeeds.");
+    let data = manager.send_values_checked(vec![
+        (vec![SetterSelector::new().with_id(setter_id_1.clone())], Value::OnOff(OnOff::Off),
+            Some(Precondition::IfEqual(Value::OnOff(OnOff::On))))
+    ]);
+    match data.get(&setter_id_1) {
+        Some(&Ok(())) => {},
+        other => panic!("Unexpected result {:?}", other.map(|_| ()))
+    }
+}
+
+#[test]
+fn test_register_topology_watch() {
+    println!("");
+    let manager = AdapterManager::new();
+    let id_1 = Id::<AdapterId>::new("adapter id 1");
+
+    let getter_id_1 = Id::<Getter>::new("getter id 1");
+    let service_id_1 = Id::<ServiceId>::new("service id 1");
+
+    let service_1 = Service {
+        id: service_id_1.clone(),
+        adapter: id_1.clone(),
+        tags: HashSet::new(),
+        getters: HashMap::new(),
+        setters: HashMap::new(),
+    };
+
+    let adapter_1 = TestAdapter::new(&id_1);
+    manager.add_adapter(Box::new(adapter_1)).unwrap();
+
+    println!("* A registered watch fires ServiceAdded for a matching service added afterwards.");
+    let (tx_events, rx_events) = std::sync::mpsc::channel();
+    let guard = manager.register_topology_watch(
+        vec![ServiceSelector::new()], vec![GetterSelector::new()], vec![],
+        Box::new(move |event| { let _ = tx_events.send(event); }));
+
+    manager.add_service(service_1.clone()).unwrap();
+    match rx_events.recv().unwrap() {
+        TopologyEvent::ServiceAdded(ref service) => assert_eq!(service.id, service_id_1),
+        _ => panic!("Unexpected event"),
+    }
+
+    println!("* It also fires GetterAdded for a matching getter added afterwards.");
+    manager.add_getter(onoff_getter(&getter_id_1, &service_id_1, &id_1)).unwrap();
+    match rx_events.recv().unwrap() {
+        TopologyEvent::GetterAdded(ref channel) => assert_eq!(channel.id, getter_id_1),
+        _ => panic!("Unexpected event"),
+    }
+
+    println!("* Dropping the guard stops further delivery.");
+    drop(guard);
+    manager.remove_getter(&getter_id_1).unwrap();
+    assert!(rx_events.recv_timeout(Duration::from_millis(100)).is_err());
+}
+
+#[test]
+fn test_config_hot_reload() {
+    println!("");
+    let path = env::temp_dir().join(format!("foxbox_adapters_test_config_{}.toml", process::id()));
+
+    let id_1 = Id::<AdapterId>::new("adapter id 1");
+    let service_id_1 = Id::<ServiceId>::new("service id 1");
+    let service_1 = Service {
+        id: service_id_1.clone(),
+        adapter: id_1.clone(),
+        tags: HashSet::new(),
+        getters: HashMap::new(),
+        setters: HashMap::new(),
+    };
+
+    File::create(&path).unwrap()
+        .write_all(b"[services.\"service id 1\"]\ntags = [\"room:kitchen\"]\n").unwrap();
+
+    let manager = AdapterManager::with_config(path.clone());
+    manager.add_adapter(Box::new(TestAdapter::new(&id_1))).unwrap();
+    manager.add_service(service_1).unwrap();
+
+    println!("* load_config applies the tags declared for an already-registered service.");
+    manager.load_config().unwrap();
+    assert_eq!(
+        manager.get_services(vec![ServiceSelector::new().with_tags(vec![Id::<TagId>::new("room:kitchen")])]).len(),
+        1);
+
+    println!("* Once watched, an edit settles and converges the live tags to match.");
+    let guard = manager.watch_config(Duration::from_millis(20), Duration::from_millis(50));
+    File::create(&path).unwrap()
+        .write_all(b"[services.\"service id 1\"]\ntags = [\"room:den\"]\n").unwrap();
+
+    let mut attempts = 0;
+    loop {
+        if manager.get_services(vec![ServiceSelector::new().with_tags(vec![Id::<TagId>::new("room:den")])]).len() == 1 {
+            break;
+        }
+        assert!(attempts < 200, "Timed out waiting for the config watch to converge");
+        attempts += 1;
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert_eq!(
+        manager.get_services(vec![ServiceSelector::new().with_tags(vec![Id::<TagId>::new("room:kitchen")])]).len(),
+        0);
+
+    drop(guard);
+    let _ = fs::remove_file(&path);
+}
+
+#[test]
+fn test_topology_config_hot_reload() {
+    println!("");
+    let path = env::temp_dir().join(format!("foxbox_adapters_test_topology_config_{}.toml", process::id()));
+    let id_1 = Id::<AdapterId>::new("adapter id 1");
+
+    File::create(&path).unwrap().write_all(
+        b"[services.svc1]\nadapter = \"adapter id 1\"\n[services.svc1.getters.g1]\nkind = \"OnOff\"\n"
+    ).unwrap();
+
+    let manager = AdapterManager::with_topology_config(path.clone());
+    manager.add_adapter(Box::new(TestAdapter::new(&id_1))).unwrap();
+
+    println!("* load_topology_config declares the service and getter described in the file.");
+    manager.load_topology_config().unwrap();
+    assert_eq!(manager.get_services(vec![ServiceSelector::new().with_id(Id::<ServiceId>::new("svc1"))]).len(), 1);
+    assert_eq!(manager.get_getter_channels(vec![GetterSelector::new().with_id(Id::<Getter>::new("g1"))]).len(), 1);
+
+    println!("* Once watched, replacing g1 with g2 in the file tears down g1 and brings up g2, \
+        leaving the service itself in place.");
+    let guard = manager.watch_topology_config(Duration::from_millis(20), Duration::from_millis(50));
+    File::create(&path).unwrap().write_all(
+        b"[services.svc1]\nadapter = \"adapter id 1\"\n[services.svc1.getters.g2]\nkind = \"OnOff\"\n"
+    ).unwrap();
+
+    let mut attempts = 0;
+    loop {
+        if manager.get_getter_channels(vec![GetterSelector::new().with_id(Id::<Getter>::new("g2"))]).len() == 1 {
+            break;
+        }
+        assert!(attempts < 200, "Timed out waiting for the topology config watch to converge");
+        attempts += 1;
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert_eq!(manager.get_getter_channels(vec![GetterSelector::new().with_id(Id::<Getter>::new("g1"))]).len(), 0);
+    assert_eq!(manager.get_services(vec![ServiceSelector::new().with_id(Id::<ServiceId>::new("svc1"))]).len(), 1);
+
+    drop(guard);
+    let _ = fs::remove_file(&path);
+}
+
+#[test]
+fn test_metrics() {
+    println!("");
+    let manager = AdapterManager::new();
+    let id_1 = Id::<AdapterId>::new("adapter id 1");
+
+    let getter_id_1 = Id::<Getter>::new("getter id 1");
+    let service_id_1 = Id::<ServiceId>::new("service id 1");
+
+    let service_1 = Service {
+        id: service_id_1.clone(),
+        adapter: id_1.clone(),
+        tags: HashSet::new(),
+        getters: HashMap::new(),
+        setters: HashMap::new(),
+    };
+
+    let adapter_1 = TestAdapter::new(&id_1);
+    let tx_adapter_1 = adapter_1.tx.clone();
+
+    manager.add_adapter(Box::new(adapter_1)).unwrap();
+    manager.add_service(service_1).unwrap();
+    manager.add_getter(onoff_getter(&getter_id_1, &service_id_1, &id_1)).unwrap();
+
+    println!("* Counts reflect what was just registered, with no watchers yet.");
+    let metrics = manager.metrics();
+    assert_eq!(metrics.adapters, 1);
+    assert_eq!(metrics.services_per_adapter.get(&id_1), Some(&1));
+    assert_eq!(metrics.getters, 1);
+    assert_eq!(metrics.setters, 0);
+    assert_eq!(metrics.active_watchers, 0);
+    assert_eq!(metrics.watchers_per_getter.get(&getter_id_1), None);
+
+    println!("* A live watch on the getter is counted both as an active watcher and per-getter.");
+    let _guard = manager.register_channel_watch(
+        vec![(vec![GetterSelector::new().with_id(getter_id_1.clone())], Exactly::Always)],
+        Box::new(move |_| {}));
+    tx_adapter_1.send(TestOp::InjectGetterValue(getter_id_1.clone(), Ok(Some(Value::OnOff(OnOff::On))))).unwrap();
+
+    let metrics = manager.metrics();
+    assert_eq!(metrics.active_watchers, 1);
+    assert_eq!(metrics.watchers_per_getter.get(&getter_id_1), Some(&1));
+
+    println!("* to_text renders every counter as a Prometheus gauge line.");
+    let text = metrics.to_text();
+    assert!(text.contains("fxbox_adapters_total 1"));
+    assert!(text.contains("fxbox_getters_total 1"));
+    assert!(text.contains("fxbox_active_watchers 1"));
+    assert!(text.contains("fxbox_getter_watchers{"));
+}
+
+/// Wraps a not-yet-constructed `TestAdapter` for `test_adapter_provider_registry`, declaring a
+/// capability up front so `adapters_with_capability` can discover it before it's built.
+struct TestAdapterFactory {
+    id: Id<AdapterId>,
+    capabilities: Vec<String>,
+}
+impl AdapterFactory for TestAdapterFactory {
+    fn id(&self) -> Id<AdapterId> {
+        self.id.clone()
+    }
+    fn capabilities(&self) -> Vec<String> {
+        self.capabilities.clone()
+    }
+    fn create(self: Box<Self>) -> Box<Adapter> {
+        Box::new(TestAdapter::new(&self.id))
+    }
+}
+
+#[test]
+fn test_adapter_provider_registry() {
+    println!("");
+    let manager = AdapterManager::new();
+    let id_1 = Id::<AdapterId>::new("adapter id 1");
+
+    println!("* A registered factory is discoverable by capability without being instantiated.");
+    manager.register_adapter_factory(Box::new(TestAdapterFactory {
+        id: id_1.clone(),
+        capabilities: vec!["on_off".to_owned()],
+    }));
+    assert_eq!(manager.adapters_with_capability("on_off"), vec![id_1.clone()]);
+    assert_eq!(manager.adapters_with_capability("no such capability"), Vec::<Id<AdapterId>>::new());
+    assert_eq!(manager.get_services(vec![ServiceSelector::new()]).len(), 0);
+
+    println!("* ensure_adapter instantiates it and adds it to the system.");
+    manager.ensure_adapter(&id_1).unwrap();
+
+    let service_id_1 = Id::<ServiceId>::new("service id 1");
+    let service_1 = Service {
+        id: service_id_1.clone(),
+        adapter: id_1.clone(),
+        tags: HashSet::new(),
+        getters: HashMap::new(),
+        setters: HashMap::new(),
+    };
+    manager.add_service(service_1).unwrap();
+    assert_eq!(manager.get_services(vec![ServiceSelector::new().with_id(service_id_1.clone())]).len(), 1);
+
+    println!("* A second ensure_adapter on the same id is a harmless no-op.");
+    manager.ensure_adapter(&id_1).unwrap();
+
+    println!("* ensure_adapter on an id with no live adapter and no factory left fails.");
+    match manager.ensure_adapter(&Id::<AdapterId>::new("no such adapter")) {
+        Err(Error::InternalError(InternalError::NoSuchAdapter(ref id))) => assert_eq!(*id, Id::<AdapterId>::new("no such adapter")),
+        other => panic!("Unexpected result {:?}", other),
+    }
+}
+
+#[test]
+fn test_send_values_updated() {
+    println!("");
+    let manager = AdapterManager::new();
+    let id_1 = Id::<AdapterId>::new("adapter id 1");
+
+    let setter_id_1 = Id::<Setter>::new("setter id 1");
+    let service_id_1 = Id::<ServiceId>::new("service id 1");
+
+    let service_1 = Service {
+        id: service_id_1.clone(),
+        adapter: id_1.clone(),
+        tags: HashSet::new(),
+        getters: HashMap::new(),
+        setters: HashMap::new(),
+    };
+
+    let adapter_1 = TestAdapter::new(&id_1);
+
+    manager.add_adapter(Box::new(adapter_1)).unwrap();
+    manager.add_service(service_1).unwrap();
+    manager.add_setter(onoff_setter(&setter_id_1, &service_id_1, &id_1)).unwrap();
+
+    println!("* An UpdateKind::Replace is materialized exactly like a plain send_values.");
+    let data = manager.send_values_updated(vec![
+        (vec![SetterSelector::new().with_id(setter_id_1.clone())], UpdateKind::Replace(Value::OnOff(OnOff::On)))
+    ]);
+    match data.get(&setter_id_1) {
+        Some(&Ok(())) => {},
+        other => panic!("Unexpected result {:?}", other.map(|_| ()))
+    }
+
+    println!("* An UpdateKind::Merge is rejected without reaching the adapter.");
+    let data = manager.send_values_updated(vec![
+        (vec![SetterSelector::new().with_id(setter_id_1.clone())], UpdateKind::Merge(Value::OnOff(OnOff::Off)))
+    ]);
+    match data.get(&setter_id_1) {
+        Some(&Err(UpdateError::UnsupportedUpdate)) => {},
+        other => panic!("Unexpected result {:?}", other.map(|_| ()))
+    }
+
+    println!("* An UpdateKind::Patch is rejected the same way.");
+    let data = manager.send_values_updated(vec![
+        (vec![SetterSelector::new().with_id(setter_id_1.clone())],
+            UpdateKind::Patch(vec![PatchOp::Replace { path: "/".to_owned(), value: Value::OnOff(OnOff::Off) }]))
+    ]);
+    match data.get(&setter_id_1) {
+        Some(&Err(UpdateError::UnsupportedUpdate)) => {},
+        other => panic!("Unexpected result {:?}", other.map(|_| ()))
+    }
+}
+
+#[test]
+fn test_poll_scheduler() {
+    println!("");
+    let manager = AdapterManager::new();
+    let id_1 = Id::<AdapterId>::new("adapter id 1");
+
+    let getter_id_1 = Id::<Getter>::new("getter id 1");
+    let service_id_1 = Id::<ServiceId>::new("service id 1");
+
+    let service_1 = Service {
+        id: service_id_1.clone(),
+        adapter: id_1.clone(),
+        tags: HashSet::new(),
+        getters: HashMap::new(),
+        setters: HashMap::new(),
+    };
+
+    let getter_1 = Channel {
+        id: getter_id_1.clone(),
+        service: service_id_1.clone(),
+        adapter: id_1.clone(),
+        last_seen: None,
+        tags: HashSet::new(),
+        mechanism: Getter {
+            updated: None,
+            kind: ChannelKind::OnOff,
+            watch: false,
+            poll: Some(Duration::new(0, 0)),
+            trigger: None,
+        },
+    };
+
+    let adapter_1 = TestAdapter::new(&id_1);
+    let tx_adapter_1 = adapter_1.tx.clone();
+
+    manager.add_adapter(Box::new(adapter_1)).unwrap();
+    manager.add_service(service_1).unwrap();
+    manager.add_getter(getter_1).unwrap();
+    manager.set_default_poll_interval(Duration::from_millis(10));
+
+    println!("* Registering a watch is what starts background polling for a getter with no native push.");
+    let (tx_events, rx_events) = std::sync::mpsc::channel();
+    let _guard = manager.register_channel_watch(
+        vec![(vec![GetterSelector::new().with_id(getter_id_1.clone())], Exactly::Always)],
+        Box::new(move |event| { let _ = tx_events.send(event); }));
+
+    tx_adapter_1.send(TestOp::InjectGetterValue(getter_id_1.clone(), Ok(Some(Value::OnOff(OnOff::On))))).unwrap();
+    match rx_events.recv_timeout(Duration::from_millis(1000)) {
+        Ok(APIWatchEvent::EnterRange { from, value: Value::OnOff(OnOff::On) }) => assert_eq!(from, getter_id_1),
+        other => panic!("Unexpected event {:?}", other)
+    }
+
+    println!("* A later poll that fetches the same value back is not redelivered.");
+    assert!(rx_events.recv_timeout(Duration::from_millis(300)).is_err());
+
+    println!("* A value that actually changed is delivered on a later poll.");
+    tx_adapter_1.send(TestOp::InjectGetterValue(getter_id_1.clone(), Ok(Some(Value::OnOff(OnOff::Off))))).unwrap();
+    match rx_events.recv_timeout(Duration::from_millis(1000)) {
+        Ok(APIWatchEvent::EnterRange { from, value: Value::OnOff(OnOff::Off) }) => assert_eq!(from, getter_id_1),
+        other => panic!("Unexpected event {:?}", other)
+    }
+
+    println!("* Dropping the last watcher stops polling from costing anything further.");
+    drop(_guard);
+    tx_adapter_1.send(TestOp::InjectGetterValue(getter_id_1.clone(), Ok(Some(Value::OnOff(OnOff::On))))).unwrap();
+    assert!(rx_events.recv_timeout(Duration::from_millis(300)).is_err());
+}
+
+#[test]
+fn test_poll_scheduler_bounded_watch() {
+    println!("");
+    let manager = AdapterManager::new();
+    let id_1 = Id::<AdapterId>::new("adapter id 1");
+
+    let getter_id_1 = Id::<Getter>::new("getter id 1");
+    let service_id_1 = Id::<ServiceId>::new("service id 1");
+
+    let service_1 = Service {
+        id: service_id_1.clone(),
+        adapter: id_1.clone(),
+        tags: HashSet::new(),
+        getters: HashMap::new(),
+        setters: HashMap::new(),
+    };
+
+    // No native push (`watch: false`), so this getter is also polled by the background
+    // scheduler: `notify_polled_value` and the adapter's own push callback can both end up
+    // delivering to the very same bounded watch below.
+    let getter_1 = Channel {
+        id: getter_id_1.clone(),
+        service: service_id_1.clone(),
+        adapter: id_1.clone(),
+        last_seen: None,
+        tags: HashSet::new(),
+        mechanism: Getter {
+            updated: None,
+            kind: ChannelKind::OnOff,
+            watch: false,
+            poll: Some(Duration::new(0, 0)),
+            trigger: None,
+        },
+    };
+
+    let adapter_1 = TestAdapter::new(&id_1);
+    let tx_adapter_1 = adapter_1.tx.clone();
+
+    manager.add_adapter(Box::new(adapter_1)).unwrap();
+    manager.add_service(service_1).unwrap();
+    manager.add_getter(getter_1).unwrap();
+    manager.set_default_poll_interval(Duration::from_millis(10));
+
+    println!("* A bounded watch on a getter polled in the background is not spuriously evicted: \
+        the scheduler's own deliveries must share the same pending/in-flight bookkeeping as the \
+        adapter's push callback, not bypass it.");
+    let (tx_events, rx_events) = std::sync::mpsc::channel();
+    let (tx_lagged, rx_lagged) = std::sync::mpsc::channel();
+    let _guard = manager.register_channel_watch_bounded(
+        vec![(vec![GetterSelector::new().with_id(getter_id_1.clone())], Exactly::Always)],
+        Box::new(move |event| { let _ = tx_events.send(event); }),
+        4,
+        Box::new(move |lagged| { let _ = tx_lagged.send(lagged); }));
+
+    for i in 0..6 {
+        let value = if i % 2 == 0 { OnOff::On } else { OnOff::Off };
+        tx_adapter_1.send(TestOp::InjectGetterValue(getter_id_1.clone(), Ok(Some(Value::OnOff(value))))).unwrap();
+        match rx_events.recv_timeout(Duration::from_millis(1000)) {
+            Ok(APIWatchEvent::EnterRange { from, .. }) => assert_eq!(from, getter_id_1),
+            other => panic!("Unexpected event {:?}", other)
+        }
+    }
+
+    assert!(rx_lagged.try_recv().is_err());
+}
+
+#[test]
+fn test_send_with_handle_per_setter_cancellation() {
+    println!("");
+    let manager = AdapterManager::new();
+    let id_slow = Id::<AdapterId>::new("adapter id slow");
+
+    let setter_id_cancelled = Id::<Setter>::new("setter id cancelled");
+    let setter_id_kept = Id::<Setter>::new("setter id kept");
+
+    let service_id_slow = Id::<ServiceId>::new("service id slow");
+
+    let onoff_setter = |id: &Id<Setter>| Channel {
+        id: id.clone(),
+        service: service_id_slow.clone(),
+        adapter: id_slow.clone(),
+        last_seen: None,
+        tags: HashSet::new(),
+        mechanism: Setter {
+            updated: None,
+            kind: ChannelKind::OnOff,
+            push: None,
+        },
+    };
+
+    let service_slow = Service {
+        id: service_id_slow.clone(),
+        adapter: id_slow.clone(),
+        tags: HashSet::new(),
+        getters: HashMap::new(),
+        setters: HashMap::new(),
+    };
+
+    manager.add_adapter(Box::new(SlowAdapter { id: id_slow.clone(), delay: Duration::from_millis(300) })).unwrap();
+    manager.add_service(service_slow).unwrap();
+    manager.add_setter(onoff_setter(&setter_id_cancelled)).unwrap();
+    manager.add_setter(onoff_setter(&setter_id_kept)).unwrap();
+
+    println!("* Two setters routed to the very same adapter get distinct JobTokens: cancelling \
+              one does not cancel the other, even though both are waiting on the same slow \
+              adapter call.");
+    let (handle, future) = manager.send_values_with_handle(vec![
+        (vec![SetterSelector::new().with_id(setter_id_cancelled.clone())], Value::OnOff(OnOff::On)),
+        (vec![SetterSelector::new().with_id(setter_id_kept.clone())], Value::OnOff(OnOff::On)),
+    ]);
+    thread::sleep(Duration::from_millis(20));
+    handle.cancel_setter(&setter_id_cancelled);
+    let data = future.wait();
+    assert_eq!(data.len(), 2);
+    match data.get(&setter_id_cancelled) {
+        Some(&Err(CancellationError::Cancelled)) => {},
+        other => panic!("Unexpected result for the cancelled setter {:?}", other.map(|_| ()))
+    }
+    match data.get(&setter_id_kept) {
+        Some(&Ok(())) => {},
+        other => panic!("Unexpected result for the setter left to resolve normally {:?}", other.map(|_| ()))
+    }
+}
+
+#[test]
+fn test_handles() {
+    println!("");
+    let manager = AdapterManager::new();
+    let id_1 = Id::<AdapterId>::new("adapter id 1");
+
+    let getter_id_1 = Id::<Getter>::new("getter id 1");
+    let setter_id_1 = Id::<Setter>::new("setter id 1");
+    let service_id_1 = Id::<ServiceId>::new("service id 1");
+
+    let service_1 = Service {
+        id: service_id_1.clone(),
+        adapter: id_1.clone(),
+        tags: HashSet::new(),
+        getters: HashMap::new(),
+        setters: HashMap::new(),
+    };
+
+    let adapter_1 = TestAdapter::new(&id_1);
+
+    manager.add_adapter(Box::new(adapter_1)).unwrap();
+
+    println!("* add_service hands back a handle directly, matching a later service_handle lookup.");
+    let service_handle = manager.add_service(service_1).unwrap();
+    assert_eq!(Some(service_handle.clone()), manager.service_handle(&service_id_1));
+
+    println!("* add_getter/add_setter hand back handles the same way.");
+    let getter_handle = manager.add_getter(onoff_getter(&getter_id_1, &service_id_1, &id_1)).unwrap();
+    assert_eq!(Some(getter_handle.clone()), manager.getter_handle(&getter_id_1));
+    let setter_handle = manager.add_setter(onoff_setter(&setter_id_1, &service_id_1, &id_1)).unwrap();
+    assert_eq!(Some(setter_handle.clone()), manager.setter_handle(&setter_id_1));
+
+    println!("* There is no handle for an id that was never registered.");
+    assert_eq!(manager.getter_handle(&Id::<Getter>::new("no such getter")), None);
+
+    println!("* fetch_value_checked/send_value_checked work normally while the handle is still live.");
+    manager.send_value_checked(&setter_handle, Value::OnOff(OnOff::On)).unwrap();
+    assert_eq!(manager.fetch_value_checked(&getter_handle).unwrap(), None);
+
+    println!("* remove_getter_checked/remove_setter_checked succeed while the handle is still live, \
+              and the id can immediately be re-registered afterwards.");
+    manager.remove_getter_checked(&getter_handle).unwrap();
+    manager.remove_setter_checked(&setter_handle).unwrap();
+    let stale_getter_handle = getter_handle;
+    let stale_setter_handle = setter_handle;
+    let getter_handle = manager.add_getter(onoff_getter(&getter_id_1, &service_id_1, &id_1)).unwrap();
+    let setter_handle = manager.add_setter(onoff_setter(&setter_id_1, &service_id_1, &id_1)).unwrap();
+    assert!(stale_getter_handle != getter_handle);
+    assert!(stale_setter_handle != setter_handle);
+
+    println!("* A handle to the old incarnation is now stale, even though the id has been reused.");
+    match manager.remove_getter_checked(&stale_getter_handle) {
+        Err(HandleError::StaleHandle) => {},
+        other => panic!("Unexpected result {:?}", other)
+    }
+    match manager.remove_setter_checked(&stale_setter_handle) {
+        Err(HandleError::StaleHandle) => {},
+        other => panic!("Unexpected result {:?}", other)
+    }
+    match manager.fetch_value_checked(&stale_getter_handle) {
+        Err(HandleError::StaleHandle) => {},
+        other => panic!("Unexpected result {:?}", other)
+    }
+    match manager.send_value_checked(&stale_setter_handle, Value::OnOff(OnOff::Off)) {
+        Err(HandleError::StaleHandle) => {},
+        other => panic!("Unexpected result {:?}", other)
+    }
+
+    println!("* The still-live handles keep working, unaffected by the stale ones above.");
+    manager.remove_getter_checked(&getter_handle).unwrap();
+    manager.remove_setter_checked(&setter_handle).unwrap();
+
+    println!("* remove_service_checked behaves the same way for services.");
+    match manager.remove_service_checked(&service_handle) {
+        Ok(()) => {},
+        other => panic!("Unexpected result {:?}", other)
+    }
+    match manager.remove_service_checked(&service_handle) {
+        Err(HandleError::StaleHandle) => {},
+        other => panic!("Unexpected result {:?}", other)
+    }
+}
+
+#[test]
+fn test_watch_buffer_dropped() {
+    println!("");
+    let manager = AdapterManager::new();
+    let id_1 = Id::<AdapterId>::new("adapter id 1");
+
+    let getter_id_1 = Id::<Getter>::new("getter id 1");
+    let service_id_1 = Id::<ServiceId>::new("service id 1");
+
+    let service_1 = Service {
+        id: service_id_1.clone(),
+        adapter: id_1.clone(),
+        tags: HashSet::new(),
+        getters: HashMap::new(),
+        setters: HashMap::new(),
+    };
+
+    let adapter_1 = TestAdapter::new(&id_1);
+    let tx_adapter_1 = adapter_1.tx.clone();
+
+    manager.add_adapter(Box::new(adapter_1)).unwrap();
+    manager.add_service(service_1).unwrap();
+    manager.add_getter(onoff_getter(&getter_id_1, &service_id_1, &id_1)).unwrap();
+
+    println!("* A consumer too slow to keep up overflows the ring buffer's budget: the oldest \
+              buffered events are dropped to make room for the newest rather than growing \
+              without bound or evicting the whole watch, and on_dropped fires once the buffer \
+              has fully drained, with the number of events lost since the last report.");
+    let (tx_events, rx_events) = std::sync::mpsc::channel();
+    let (tx_dropped, rx_dropped) = std::sync::mpsc::channel();
+    let _guard = manager.register_channel_watch_with_buffer(
+        vec![(vec![GetterSelector::new().with_id(getter_id_1.clone())], Exactly::Always)],
+        Box::new(move |event| {
+            // Slow enough that the injection loop below finishes well before the first event
+            // is even handed off, guaranteeing the buffer actually overflows rather than
+            // draining as fast as it fills.
+            thread::sleep(Duration::from_millis(100));
+            let _ = tx_events.send(event);
+        }),
+        WatchBufferBudget { max_events: Some(2), max_bytes: None },
+        Box::new(move |dropped| { let _ = tx_dropped.send(dropped); }));
+
+    for i in 0..8 {
+        let value = if i % 2 == 0 { OnOff::On } else { OnOff::Off };
+        tx_adapter_1.send(TestOp::InjectGetterValue(getter_id_1.clone(), Ok(Some(Value::OnOff(value))))).unwrap();
+    }
+
+    let mut delivered = 0;
+    while delivered < 3 {
+        match rx_events.recv_timeout(Duration::from_millis(1000)) {
+            Ok(_) => delivered += 1,
+            other => panic!("Unexpected result {:?}", other.map(|_| ()))
+        }
+    }
+
+    match rx_dropped.recv_timeout(Duration::from_millis(1000)) {
+        Ok(dropped) => assert_eq!(dropped.count, 5),
+        other => panic!("Unexpected result {:?}", other.map(|_| ()))
+    }
+}